@@ -1,3 +1,5 @@
 fn main() {
+    // Only the app has a Tauri context to generate
+    #[cfg(feature = "app")]
     tauri_build::build()
 }
@@ -0,0 +1,446 @@
+//! Batch processing: one set of settings applied to many images, each run
+//! through the whole pipeline and exported on its own. Files are processed
+//! in parallel on a pool of a fixed size so a long batch can't take over
+//! every core, and one bad file doesn't stop the rest.
+
+use crate::errors::Error;
+use crate::imaging::export::{export_plates, ExportFormat};
+use crate::imaging::processes::{process_image, ProcessResult};
+use crate::jobs::{CancellationToken, JobContext, JobKind};
+use crate::state::{ExportSettings, ProcessSettings};
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the images the app can open
+pub const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{name}";
+/// Workers used when none are asked for
+pub const DEFAULT_WORKERS: usize = 4;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BatchOptions {
+    /// Base name of each file's exports. `{name}` is the source file name
+    /// without its extension, `{index}` its position in the batch from 1,
+    /// `{preset}` the preset's name and `{date}` today's date.
+    pub filename_template: String,
+    pub format: ExportFormat,
+    /// Files processed at once; capped at the number of cores
+    pub workers: Option<usize>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            format: ExportFormat::default(),
+            workers: None,
+        }
+    }
+}
+
+/// What a batch runs on
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchInput {
+    Files(Vec<String>),
+    /// Every image directly inside the folder
+    Folder(String),
+}
+
+impl BatchInput {
+    pub fn files(self) -> Result<Vec<PathBuf>, Error> {
+        match self {
+            BatchInput::Files(files) => Ok(files.into_iter().map(PathBuf::from).collect()),
+            BatchInput::Folder(folder) => collect_images(Path::new(&folder)),
+        }
+    }
+}
+
+/// Outcome of one file
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BatchItem {
+    pub input: String,
+    /// Base name the exports were written under
+    pub output: String,
+    pub error: Option<String>,
+    /// The batch was cancelled before this file was done; nothing of it is
+    /// kept
+    pub skipped: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BatchReport {
+    pub output_dir: String,
+    pub items: Vec<BatchItem>,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Files left out because the batch was cancelled
+    pub skipped: usize,
+}
+
+pub fn is_supported_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Images directly inside `folder`, sorted by name
+pub fn collect_images(folder: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut images: Vec<PathBuf> = fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && is_supported_image(path))
+        .collect();
+    images.sort();
+    Ok(images)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "image".to_string())
+}
+
+/// Fills in a filename template. Characters that can't appear in a file
+/// name are replaced so a preset name can't point exports elsewhere.
+pub fn render_template(
+    template: &str,
+    source: &Path,
+    index: usize,
+    count: usize,
+    preset: &str,
+) -> Result<String, Error> {
+    let width = count.to_string().len();
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| Error::InvalidBatch(format!("unclosed placeholder in {}", template)))?;
+        match &rest[start + 1..start + end] {
+            "name" => name.push_str(&file_stem(source)),
+            "index" => name.push_str(&format!("{:0width$}", index + 1, width = width)),
+            "preset" => name.push_str(preset),
+            "date" => name.push_str(&chrono::Local::now().format("%Y-%m-%d").to_string()),
+            other => {
+                return Err(Error::InvalidBatch(format!(
+                    "unknown placeholder {{{}}}",
+                    other
+                )))
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_start_matches('.').to_string();
+    if name.is_empty() {
+        return Err(Error::InvalidBatch(
+            "the filename template gives an empty name".to_string(),
+        ));
+    }
+    Ok(name)
+}
+
+/// Base names for every file, numbered where two would clash
+fn output_names(files: &[PathBuf], template: &str, preset: &str) -> Result<Vec<String>, Error> {
+    let mut names: Vec<String> = Vec::with_capacity(files.len());
+    for (i, file) in files.iter().enumerate() {
+        let base = render_template(template, file, i, files.len(), preset)?;
+        let mut name = base.clone();
+        let mut n = 2;
+        while names.iter().any(|other| other.eq_ignore_ascii_case(&name)) {
+            name = format!("{}_{}", base, n);
+            n += 1;
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Runs one file through the pipeline and exports it. The plates only
+/// live in the temp folder until they are exported.
+pub fn process_file(
+    file: &Path,
+    settings: &ProcessSettings,
+    export_settings: &ExportSettings,
+    format: ExportFormat,
+    output_dir: &Path,
+    base_filename: &str,
+    ctx: &JobContext,
+) -> Result<(), Error> {
+    ctx.checkpoint()?;
+    let path = file.to_string_lossy();
+    // The base name keeps plates of files running side by side apart
+    let plates: Vec<ProcessResult> =
+        process_image(&path, Some(settings), Some(base_filename), None, ctx)?;
+    let exported = ctx.checkpoint().and_then(|_| {
+        export_plates(
+            format,
+            &plates,
+            &output_dir.to_string_lossy(),
+            base_filename,
+            settings.colors.as_ref(),
+            export_settings,
+        )
+    });
+    for plate in &plates {
+        let _ = fs::remove_file(&plate.image_path);
+    }
+    exported
+}
+
+/// Processes `files` with `settings`, writing exports into `output_dir`.
+/// `on_item` is called as each file finishes, from whichever worker ran it.
+#[allow(clippy::too_many_arguments)]
+pub fn run_batch(
+    files: &[PathBuf],
+    settings: &ProcessSettings,
+    export_settings: &ExportSettings,
+    options: &BatchOptions,
+    preset: &str,
+    output_dir: &Path,
+    job_id: u64,
+    token: &CancellationToken,
+    on_item: impl Fn(&BatchItem) + Sync,
+) -> Result<BatchReport, Error> {
+    if files.is_empty() {
+        return Err(Error::InvalidBatch(
+            "there are no images to process".to_string(),
+        ));
+    }
+    let names = output_names(files, &options.filename_template, preset)?;
+    fs::create_dir_all(output_dir)?;
+
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let workers = options.workers.unwrap_or(DEFAULT_WORKERS).clamp(1, cores);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .thread_name(|i| format!("r110-batch-{}", i))
+        .build()
+        .map_err(|e| Error::Processing(e.to_string()))?;
+
+    // The pipeline's own parallel steps run on the same pool, so the
+    // worker count bounds the whole batch
+    let items: Vec<BatchItem> = pool.install(|| {
+        files
+            .par_iter()
+            .zip(&names)
+            .map(|(file, name)| {
+                let ctx = JobContext::new(job_id, JobKind::Batch, token.clone(), |_| {});
+                let result = process_file(
+                    file,
+                    settings,
+                    export_settings,
+                    options.format,
+                    output_dir,
+                    name,
+                    &ctx,
+                );
+                // Files cut short by a cancel are skipped, not failed
+                let skipped = matches!(result, Err(Error::Cancelled));
+                let error = match result {
+                    Ok(()) | Err(Error::Cancelled) => None,
+                    Err(e) => {
+                        log::warn!("Batch item {} failed: {}", file.display(), e);
+                        Some(e.to_string())
+                    }
+                };
+                let item = BatchItem {
+                    input: file.to_string_lossy().to_string(),
+                    output: name.clone(),
+                    error,
+                    skipped,
+                };
+                on_item(&item);
+                item
+            })
+            .collect()
+    });
+
+    let failed = items.iter().filter(|item| item.error.is_some()).count();
+    let skipped = items.iter().filter(|item| item.skipped).count();
+    Ok(BatchReport {
+        output_dir: output_dir.to_string_lossy().to_string(),
+        succeeded: items.len() - failed - skipped,
+        failed,
+        skipped,
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn settings() -> ProcessSettings {
+        ProcessSettings {
+            effect: None,
+            filter: None,
+            colors: None,
+            output: None,
+            trapping: None,
+            knockout: None,
+        }
+    }
+
+    fn write_image(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        RgbImage::from_fn(16, 12, |x, y| Rgb([(x * 16) as u8, (y * 20) as u8, 128]))
+            .save(&path)
+            .unwrap();
+        path
+    }
+
+    fn batch(files: &[PathBuf], output: &Path, token: &CancellationToken) -> BatchReport {
+        let options = BatchOptions {
+            format: ExportFormat::Png,
+            workers: Some(2),
+            ..Default::default()
+        };
+        run_batch(
+            files,
+            &settings(),
+            &ExportSettings::default(),
+            &options,
+            "zine",
+            output,
+            1,
+            token,
+            |_| {},
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn templates_fill_placeholders_and_clean_names() {
+        let source = Path::new("/scans/cover.final.png");
+        assert_eq!(
+            render_template("{preset}-{name}-{index}", source, 4, 12, "zine").unwrap(),
+            "zine-cover.final-05"
+        );
+        assert_eq!(render_template("{index}", source, 0, 9, "").unwrap(), "1");
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        assert_eq!(render_template("{date}", source, 0, 1, "").unwrap(), date);
+        // Preset names can't point exports into another folder
+        assert_eq!(
+            render_template("{preset}/{name}", source, 0, 1, "../a:b").unwrap(),
+            "_a_b_cover.final"
+        );
+        assert_eq!(
+            render_template(" ..{name} ", source, 0, 1, "").unwrap(),
+            "cover.final"
+        );
+
+        for bad in ["{name", "{size}", "{preset}", "..."] {
+            assert!(
+                matches!(
+                    render_template(bad, source, 0, 1, ""),
+                    Err(Error::InvalidBatch(_))
+                ),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn clashing_names_are_numbered() {
+        let files: Vec<PathBuf> = ["a/scan.png", "b/scan.jpg", "c/SCAN.png", "d/other.png"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(
+            output_names(&files, "{name}", "").unwrap(),
+            ["scan", "scan_2", "SCAN_3", "other"]
+        );
+        // A numbered name that clashes with a real one moves on
+        let files: Vec<PathBuf> = ["scan.png", "scan_2.png", "x/scan.png"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(
+            output_names(&files, "{name}", "").unwrap(),
+            ["scan", "scan_2", "scan_3"]
+        );
+    }
+
+    #[test]
+    fn bad_files_fail_without_stopping_the_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let mut files = vec![
+            write_image(dir.path(), "batch_ok_first.png"),
+            write_image(dir.path(), "batch_ok_second.png"),
+        ];
+        let broken = dir.path().join("batch_broken.png");
+        fs::write(&broken, b"not a png").unwrap();
+        files.push(broken);
+
+        let report = batch(&files, &output, &CancellationToken::new());
+        assert_eq!((report.succeeded, report.failed, report.skipped), (2, 1, 0));
+        assert!(report.items[2].error.is_some());
+        for item in &report.items[..2] {
+            assert!(item.error.is_none() && !item.skipped);
+            let exported = fs::read_dir(&output)
+                .unwrap()
+                .filter_map(|entry| entry.ok())
+                .any(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    name.starts_with(&item.output) && name.ends_with(".png")
+                });
+            assert!(exported, "{} wasn't exported", item.output);
+        }
+    }
+
+    #[test]
+    fn cancelled_files_are_skipped_not_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let files = vec![
+            write_image(dir.path(), "batch_cancel_first.png"),
+            write_image(dir.path(), "batch_cancel_second.png"),
+        ];
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let report = batch(&files, &output, &token);
+        assert_eq!((report.succeeded, report.failed, report.skipped), (0, 0, 2));
+        assert!(report
+            .items
+            .iter()
+            .all(|item| item.skipped && item.error.is_none()));
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn empty_batches_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = run_batch(
+            &[],
+            &settings(),
+            &ExportSettings::default(),
+            &BatchOptions::default(),
+            "",
+            dir.path(),
+            1,
+            &CancellationToken::new(),
+            |_| {},
+        );
+        assert!(matches!(result, Err(Error::InvalidBatch(_))));
+    }
+}
@@ -0,0 +1,5 @@
+//! Headless command line entry point; see `r110_lib::cli`
+
+fn main() -> std::process::ExitCode {
+    r110_lib::cli::run()
+}
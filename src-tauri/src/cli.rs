@@ -0,0 +1,583 @@
+//! Command line front end for scripting R110 without the app. Runs the same
+//! pipeline and exporters as the app through the batch runner, or the hot
+//! folder watcher with `--watch`; settings come from a preset or project
+//! file and can be overridden with flags.
+
+use crate::batch::{self, BatchOptions, BatchReport};
+use crate::errors::Error;
+use crate::imaging::colormap::ColorMap;
+use crate::imaging::export::ExportFormat;
+use crate::imaging::output::{LengthUnit, OutputSize};
+use crate::jobs::CancellationToken;
+use crate::presets::read_preset;
+use crate::project::open_project;
+use crate::state::{ColorInfo, ExportSettings, ImageEffect, ImageFilter, ProcessSettings};
+use crate::watch::{prepare_folders, watch_folder, WatchSettings, DEFAULT_POLL_SECONDS};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: r110-cli [OPTIONS] <IMAGE>...
+       r110-cli [OPTIONS] --watch <DIR>
+
+Separates images into plates and exports them, or keeps exporting images
+dropped into a folder until stopped.
+
+Options:
+  -o, --output <DIR>       Folder exports are written to [default: .]
+  -f, --format <FORMAT>    pdf, spot-pdf, png, tiff or psd [default: pdf]
+  -n, --name <TEMPLATE>    Base name of each export; {name}, {index},
+                           {preset} and {date} are filled in [default: {name}]
+  -p, --preset <FILE>      Process settings from a .r110preset file
+      --project <FILE>     Settings and export options from a .r110 project;
+                           its source is used when no image is given
+  -e, --effect <EFFECT>    original, dither, halftone or threshold
+      --filter <FILTER>    grayscale, sepia, invert, pixelate, brighten,
+                           darken, contrast, blur or sharpen
+  -i, --ink <HEX[:NAME]>   Ink of the next plate, in plate order; repeat for
+                           each plate
+      --width <LENGTH>     Print width
+      --height <LENGTH>    Print height
+      --unit <UNIT>        mm or in [default: mm]
+      --dpi <DPI>          Output resolution [default: 300 with a size]
+      --job-sheet          Write a job sheet next to the exports
+  -j, --jobs <N>           Images processed at once
+  -w, --watch <DIR>        Process every image dropped into DIR
+      --done <DIR>         Where handled originals go [default: DIR/done]
+      --failed <DIR>       Where failed originals go [default: DIR/failed]
+      --interval <SECS>    How often DIR is checked [default: 2]
+  -q, --quiet              Only print errors
+  -h, --help               Print this help
+  -V, --version            Print the version
+
+Exit status is 0 when every image was exported, 1 when any failed and 2
+when the command line is wrong. A watch runs until interrupted and logs
+each job to r110-watch.log in the output folder.";
+
+const DEFAULT_DPI: f32 = 300.0;
+
+/// Exit status when an image failed or settings couldn't be loaded
+const EXIT_FAILED: u8 = 1;
+/// Exit status for a command line that can't be run
+const EXIT_USAGE: u8 = 2;
+
+#[derive(Debug, Default)]
+struct Args {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    format: Option<ExportFormat>,
+    name: Option<String>,
+    preset: Option<PathBuf>,
+    project: Option<PathBuf>,
+    effect: Option<ImageEffect>,
+    filter: Option<ImageFilter>,
+    inks: Vec<ColorInfo>,
+    width: Option<f32>,
+    height: Option<f32>,
+    unit: Option<LengthUnit>,
+    dpi: Option<f32>,
+    job_sheet: bool,
+    jobs: Option<usize>,
+    watch: Option<PathBuf>,
+    done: Option<PathBuf>,
+    failed: Option<PathBuf>,
+    interval: Option<f32>,
+    quiet: bool,
+}
+
+enum Command {
+    Run(Box<Args>),
+    Help,
+    Version,
+}
+
+/// Matches `value` against `(name, variant)` pairs, ignoring case
+fn choice<T: Clone>(flag: &str, value: &str, choices: &[(&str, T)]) -> Result<T, String> {
+    choices
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(value))
+        .map(|(_, choice)| choice.clone())
+        .ok_or_else(|| {
+            let names: Vec<&str> = choices.iter().map(|(name, _)| *name).collect();
+            format!(
+                "invalid value '{}' for {}; expected one of {}",
+                value,
+                flag,
+                names.join(", ")
+            )
+        })
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number '{}' for {}", value, flag))
+}
+
+fn parse_ink(value: &str) -> Result<ColorInfo, String> {
+    let (hex, name) = value.split_once(':').unwrap_or((value, value));
+    let hex = if hex.starts_with('#') {
+        hex.to_string()
+    } else {
+        format!("#{}", hex)
+    };
+    if ColorMap::hex_to_rgb(&hex).is_err() {
+        return Err(format!(
+            "invalid ink '{}'; expected a hex colour like #FF48B0",
+            value
+        ));
+    }
+    Ok(ColorInfo {
+        hex: hex.to_uppercase(),
+        name: name.to_string(),
+        cost_per_ml: None,
+    })
+}
+
+fn parse_args(raw: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = Args::default();
+    let mut raw = raw.into_iter();
+
+    while let Some(arg) = raw.next() {
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || -> Result<String, String> {
+            match inline {
+                Some(value) => Ok(value.to_string()),
+                None => raw.next().ok_or_else(|| format!("{} needs a value", flag)),
+            }
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-o" | "--output" => args.output = Some(value()?.into()),
+            "-f" | "--format" => {
+                args.format = Some(choice(
+                    &flag,
+                    &value()?,
+                    &[
+                        ("pdf", ExportFormat::Pdf),
+                        ("spot-pdf", ExportFormat::SpotPdf),
+                        ("png", ExportFormat::Png),
+                        ("tiff", ExportFormat::Tiff),
+                        ("psd", ExportFormat::Psd),
+                    ],
+                )?)
+            }
+            "-n" | "--name" => args.name = Some(value()?),
+            "-p" | "--preset" => args.preset = Some(value()?.into()),
+            "--project" => args.project = Some(value()?.into()),
+            "-e" | "--effect" => {
+                args.effect = Some(choice(
+                    &flag,
+                    &value()?,
+                    &[
+                        ("original", ImageEffect::Original),
+                        ("dither", ImageEffect::Dither),
+                        ("halftone", ImageEffect::HalfTone),
+                        ("threshold", ImageEffect::Threshold),
+                    ],
+                )?)
+            }
+            "--filter" => {
+                args.filter = Some(choice(
+                    &flag,
+                    &value()?,
+                    &[
+                        ("grayscale", ImageFilter::Grayscale),
+                        ("sepia", ImageFilter::Sepia),
+                        ("invert", ImageFilter::Invert),
+                        ("pixelate", ImageFilter::Pixelate),
+                        ("brighten", ImageFilter::Brighten),
+                        ("darken", ImageFilter::Darken),
+                        ("contrast", ImageFilter::Contrast),
+                        ("blur", ImageFilter::Blur),
+                        ("sharpen", ImageFilter::Sharpen),
+                    ],
+                )?)
+            }
+            "-i" | "--ink" => args.inks.push(parse_ink(&value()?)?),
+            "--width" => args.width = Some(number(&flag, &value()?)?),
+            "--height" => args.height = Some(number(&flag, &value()?)?),
+            "--unit" => {
+                args.unit = Some(choice(
+                    &flag,
+                    &value()?,
+                    &[("mm", LengthUnit::Millimetres), ("in", LengthUnit::Inches)],
+                )?)
+            }
+            "--dpi" => args.dpi = Some(number(&flag, &value()?)?),
+            "--job-sheet" => args.job_sheet = true,
+            "-j" | "--jobs" => args.jobs = Some(number(&flag, &value()?)?),
+            "-w" | "--watch" => args.watch = Some(value()?.into()),
+            "--done" => args.done = Some(value()?.into()),
+            "--failed" => args.failed = Some(value()?.into()),
+            "--interval" => args.interval = Some(number(&flag, &value()?)?),
+            "-q" | "--quiet" => args.quiet = true,
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("unknown option {}", flag))
+            }
+            _ => args.inputs.push(arg.into()),
+        }
+    }
+
+    if args.watch.is_some() {
+        if !args.inputs.is_empty() {
+            return Err("images can't be given with --watch".to_string());
+        }
+    } else if args.inputs.is_empty() && args.project.is_none() {
+        return Err("no image given".to_string());
+    }
+    Ok(Command::Run(Box::new(args)))
+}
+
+/// Settings for the run: the project's, then the preset's, then the flags
+fn resolve(args: &mut Args) -> Result<(ProcessSettings, ExportSettings, String), Error> {
+    let mut settings = ProcessSettings {
+        effect: None,
+        filter: None,
+        colors: None,
+        output: None,
+        trapping: None,
+        knockout: None,
+    };
+    let mut export_settings = ExportSettings::default();
+    let mut preset_name = String::new();
+
+    if let Some(path) = &args.project {
+        let opened = open_project(path)?;
+        if args.inputs.is_empty() && args.watch.is_none() {
+            args.inputs.push(opened.source_path.into());
+        }
+        if let Some(project_settings) = opened.project.process_settings {
+            settings = project_settings;
+        }
+        export_settings = opened.project.export_settings;
+        preset_name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+    }
+    if let Some(path) = &args.preset {
+        let preset = read_preset(path)?;
+        settings = preset.settings;
+        preset_name = preset.name;
+    }
+
+    if args.effect.is_some() {
+        settings.effect = args.effect.clone();
+    }
+    if args.filter.is_some() {
+        settings.filter = args.filter.clone();
+    }
+    if !args.inks.is_empty() {
+        settings.colors = Some(args.inks.clone());
+    }
+    if args.width.is_some() || args.height.is_some() || args.dpi.is_some() {
+        let current = settings.output.take();
+        let output = OutputSize {
+            width: args.width.or(current.as_ref().and_then(|o| o.width)),
+            height: args.height.or(current.as_ref().and_then(|o| o.height)),
+            unit: args
+                .unit
+                .or(current.as_ref().map(|o| o.unit))
+                .unwrap_or_default(),
+            dpi: args
+                .dpi
+                .or(current.as_ref().map(|o| o.dpi))
+                .unwrap_or(DEFAULT_DPI),
+        };
+        output.validate()?;
+        settings.output = Some(output);
+    }
+    if args.job_sheet {
+        export_settings.job_sheet.enabled = true;
+    }
+    Ok((settings, export_settings, preset_name))
+}
+
+fn print_report(report: &BatchReport, quiet: bool) {
+    if !quiet {
+        println!(
+            "{} exported, {} failed, {} skipped, written to {}",
+            report.succeeded, report.failed, report.skipped, report.output_dir
+        );
+    }
+}
+
+fn run_args(mut args: Args) -> Result<BatchReport, Error> {
+    let (settings, export_settings, preset_name) = resolve(&mut args)?;
+    let options = BatchOptions {
+        filename_template: args
+            .name
+            .clone()
+            .unwrap_or_else(|| batch::DEFAULT_FILENAME_TEMPLATE.to_string()),
+        format: args.format.unwrap_or_default(),
+        workers: args.jobs,
+    };
+    let output_dir = args.output.clone().unwrap_or_else(|| PathBuf::from("."));
+    let quiet = args.quiet;
+
+    batch::run_batch(
+        &args.inputs,
+        &settings,
+        &export_settings,
+        &options,
+        &preset_name,
+        Path::new(&output_dir),
+        0,
+        &CancellationToken::new(),
+        |item| match &item.error {
+            Some(error) => eprintln!("error: {}: {}", item.input, error),
+            None if !quiet => println!("{} -> {}", item.input, item.output),
+            None => {}
+        },
+    )
+}
+
+/// Watches a folder until the process is interrupted. Only returns when
+/// the watch can't go on.
+fn run_watch(mut args: Args) -> Result<(), Error> {
+    let (settings, export_settings, preset_name) = resolve(&mut args)?;
+    let folder = args.watch.clone().unwrap_or_default();
+    let path_string =
+        |path: &Option<PathBuf>| path.as_ref().map(|path| path.to_string_lossy().to_string());
+    let watch = WatchSettings {
+        folder: folder.to_string_lossy().to_string(),
+        output_dir: path_string(&args.output).unwrap_or_else(|| ".".to_string()),
+        done_dir: path_string(&args.done),
+        failed_dir: path_string(&args.failed),
+        format: args.format.unwrap_or_default(),
+        filename_template: args
+            .name
+            .clone()
+            .unwrap_or_else(|| batch::DEFAULT_FILENAME_TEMPLATE.to_string()),
+        poll_seconds: args.interval.unwrap_or(DEFAULT_POLL_SECONDS),
+    };
+    prepare_folders(&watch)?;
+    if !args.quiet {
+        println!(
+            "Watching {}, exporting to {} (Ctrl+C to stop)",
+            watch.folder, watch.output_dir
+        );
+    }
+
+    let quiet = args.quiet;
+    watch_folder(
+        &watch,
+        &settings,
+        &export_settings,
+        &preset_name,
+        0,
+        &CancellationToken::new(),
+        |job| match &job.error {
+            Some(error) => eprintln!("error: {}: {}", job.source, error),
+            None if !quiet => println!("{} -> {}", job.source, job.output),
+            None => {}
+        },
+    )
+}
+
+/// Runs the command line in `std::env::args` and returns the exit status
+pub fn run() -> ExitCode {
+    run_with(std::env::args().skip(1))
+}
+
+fn run_with(raw: impl IntoIterator<Item = String>) -> ExitCode {
+    let args = match parse_args(raw) {
+        Ok(Command::Run(args)) => *args,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("r110-cli {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("error: {}\nRun r110-cli --help for usage.", message);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    if args.watch.is_some() {
+        // The watch only comes back when it has to give up
+        let Err(e) = run_watch(args) else {
+            return ExitCode::SUCCESS;
+        };
+        eprintln!("error: {}", e);
+        return ExitCode::from(EXIT_FAILED);
+    }
+
+    let quiet = args.quiet;
+    match run_args(args) {
+        Ok(report) => {
+            print_report(&report, quiet);
+            if report.failed > 0 {
+                ExitCode::from(EXIT_FAILED)
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_FAILED)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::{Preset, PRESET_VERSION};
+    use crate::project::{save_project, SaveOptions};
+    use crate::state::AppStateInner;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        match parse_args(args.iter().map(|arg| arg.to_string()))? {
+            Command::Run(args) => Ok(*args),
+            _ => Err("not a run".to_string()),
+        }
+    }
+
+    fn settings(effect: ImageEffect) -> ProcessSettings {
+        ProcessSettings {
+            effect: Some(effect),
+            filter: None,
+            colors: None,
+            output: None,
+            trapping: None,
+            knockout: None,
+        }
+    }
+
+    #[test]
+    fn flags_take_inline_or_separate_values() {
+        let args = parse(&[
+            "--format=tiff",
+            "-o",
+            "plates",
+            "--ink=0078bf:Blue",
+            "-i",
+            "#FF48B0",
+            "--dpi=600",
+            "scan.png",
+        ])
+        .unwrap();
+        assert!(matches!(args.format, Some(ExportFormat::Tiff)));
+        assert_eq!(args.output, Some(PathBuf::from("plates")));
+        assert_eq!(args.dpi, Some(600.0));
+        assert_eq!(args.inputs, vec![PathBuf::from("scan.png")]);
+        assert_eq!(args.inks[0].hex, "#0078BF");
+        assert_eq!(args.inks[0].name, "Blue");
+        assert_eq!(args.inks[1].name, "#FF48B0");
+    }
+
+    #[test]
+    fn choices_ignore_case() {
+        let args = parse(&["--effect", "HalfTone", "--filter=Sepia", "a.png"]).unwrap();
+        assert!(matches!(args.effect, Some(ImageEffect::HalfTone)));
+        assert!(matches!(args.filter, Some(ImageFilter::Sepia)));
+    }
+
+    #[test]
+    fn bad_command_lines_are_rejected() {
+        for args in [
+            &["--format", "gif", "a.png"][..],
+            &["--dpi=many", "a.png"],
+            &["--ink", "#12345", "a.png"],
+            &["--bogus", "a.png"],
+            &["a.png", "--output"],
+            &[],
+            &["--watch", "in", "a.png"],
+        ] {
+            assert!(parse(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn usage_errors_exit_with_2() {
+        let run = |args: &[&str]| run_with(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(run(&["--format=gif", "a.png"]), ExitCode::from(EXIT_USAGE));
+        assert_eq!(run(&["--interval", "soon"]), ExitCode::from(EXIT_USAGE));
+        assert_eq!(run(&["--help"]), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn missing_settings_files_exit_with_1() {
+        let dir = tempfile::tempdir().unwrap();
+        let preset = dir.path().join("missing.r110preset");
+        let code = run_with([
+            "--preset".to_string(),
+            preset.to_string_lossy().to_string(),
+            "a.png".to_string(),
+        ]);
+        assert_eq!(code, ExitCode::from(EXIT_FAILED));
+    }
+
+    #[test]
+    fn preset_overrides_project_and_flags_override_both() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("page.png");
+        image::RgbImage::new(4, 4).save(&source).unwrap();
+
+        let mut state = AppStateInner {
+            image_path: Some(source.to_string_lossy().to_string()),
+            image_name: Some("page.png".to_string()),
+            process_settings: Some(settings(ImageEffect::Dither)),
+            ..Default::default()
+        };
+        state.export_settings.marks.crop_marks = true;
+        let project = dir.path().join("job.r110");
+        save_project(&state, &project, &SaveOptions::default()).unwrap();
+
+        let preset = dir.path().join("zine.r110preset");
+        let data = serde_json::to_vec(&Preset {
+            version: PRESET_VERSION,
+            name: "Zine".to_string(),
+            settings: settings(ImageEffect::Threshold),
+        })
+        .unwrap();
+        std::fs::write(&preset, data).unwrap();
+
+        let project = project.to_string_lossy().to_string();
+        let preset = preset.to_string_lossy().to_string();
+
+        // The project alone supplies its settings and its source
+        let mut args = parse(&["--project", &project]).unwrap();
+        let (settings, export_settings, name) = resolve(&mut args).unwrap();
+        assert!(matches!(settings.effect, Some(ImageEffect::Dither)));
+        assert!(export_settings.marks.crop_marks);
+        assert_eq!(name, "job");
+        assert_eq!(args.inputs, vec![source.clone()]);
+
+        // A preset replaces the project's process settings but keeps its
+        // export settings
+        let mut args = parse(&["--project", &project, "--preset", &preset]).unwrap();
+        let (settings, export_settings, name) = resolve(&mut args).unwrap();
+        assert!(matches!(settings.effect, Some(ImageEffect::Threshold)));
+        assert!(export_settings.marks.crop_marks);
+        assert_eq!(name, "Zine");
+
+        let mut args = parse(&[
+            "--project",
+            &project,
+            "--preset",
+            &preset,
+            "--effect=halftone",
+            "--width=100",
+            "other.png",
+        ])
+        .unwrap();
+        let (settings, _, _) = resolve(&mut args).unwrap();
+        assert!(matches!(settings.effect, Some(ImageEffect::HalfTone)));
+        let output = settings.output.unwrap();
+        assert_eq!(output.width, Some(100.0));
+        assert_eq!(output.dpi, DEFAULT_DPI);
+        assert_eq!(args.inputs, vec![PathBuf::from("other.png")]);
+    }
+}
@@ -1,11 +1,28 @@
+use crate::batch::{self, BatchInput, BatchItem, BatchOptions, BatchReport, IMAGE_EXTENSIONS};
 use crate::errors::Error;
-use crate::imaging::export::{save_channels_to_disk, save_channels_to_pdf};
-use crate::imaging::processes::{apply_colormap, process_image, process_image_background};
-use crate::state::{AppState, ProcessSettings, ProcessingStatus};
+use crate::history::HistoryListing;
+use crate::imaging::composite::{
+    composite_channels, composite_preview, save_composite, CompositeFormat,
+};
+use crate::imaging::cost::{estimate_cost, CostEstimate, CostSettings};
+use crate::imaging::coverage::{coverage_report, CoverageReport};
+use crate::imaging::export::{export_plates, ExportFormat};
+use crate::imaging::processes::{
+    apply_colormap, process_image, process_image_background, ProcessResult,
+};
+use crate::imaging::proof::{render_proof_for_channels, ProofSettings};
+use crate::imaging::registration::{registration_report, PlateOffset, RegistrationReport};
+use crate::imaging::variants::{
+    fit_cell, validate_specs, variant_grid, GridCell, Variant, VariantSpec, DEFAULT_CELL_SIZE,
+};
+use crate::jobs::{JobContext, JobKind, JobManager, JobProgress};
+use crate::presets::{Preset, PresetStore, PRESET_EXTENSION};
+use crate::project::{self, SaveOptions, PROJECT_EXTENSION};
+use crate::state::{AppState, AppStateInner, ExportSettings, ProcessSettings, ProcessingStatus};
+use crate::watch::{prepare_folders, watch_folder, WatchSettings, WatchStatus};
 use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
-use log;
 use std::fs;
-use tauri::{AppHandle, Manager, State};
+use tauri::{AppHandle, Emitter, Manager, State};
 use tauri_plugin_dialog::DialogExt;
 
 #[derive(Debug, serde::Serialize)]
@@ -17,15 +34,103 @@ pub struct AppResponse {
     processing_status: ProcessingStatus,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct ProjectResponse {
+    processed_images: Option<Vec<crate::imaging::processes::ProcessResult>>,
+    image_path: String,
+    image_type: String,
+    image_name: String,
+    process_settings: Option<ProcessSettings>,
+    export_settings: ExportSettings,
+    /// The source image changed on disk since the project was saved
+    source_modified: bool,
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct ProcessingCompletePayload {
     processed_images: Option<Vec<crate::imaging::processes::ProcessResult>>,
     status: ProcessingStatus,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProcessingStatusPayload {
+    status: ProcessingStatus,
+    error: Option<String>,
+}
+
+/// Applies a status transition and tells the frontend about it. Every change
+/// emits `processing-status`; finished states also emit `processing-complete`.
+fn set_processing_status(
+    app: &AppHandle,
+    state: &mut AppStateInner,
+    status: ProcessingStatus,
+    error: Option<String>,
+) {
+    if let Err(e) = state.transition(status.clone(), error.clone()) {
+        log::warn!("{}", e);
+        return;
+    }
+
+    let payload = ProcessingStatusPayload {
+        status: status.clone(),
+        error: error.clone(),
+    };
+    if let Err(e) = app.emit("processing-status", payload) {
+        log::warn!("Failed to emit status event: {}", e);
+    }
+
+    if status.is_finished() {
+        let payload = ProcessingCompletePayload {
+            processed_images: state.processed_images.clone(),
+            status,
+            error,
+        };
+        if let Err(e) = app.emit("processing-complete", payload) {
+            log::warn!("Failed to emit completion event: {}", e);
+        }
+    }
+}
+
+/// Runs heavy work on the blocking pool so the async runtime stays free
+async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| Error::Processing(e.to_string()))?
+}
+
+/// Deletes the plates history let go of that nothing uses any more, off the
+/// lock and the async runtime
+fn remove_unused_plates(state: &mut AppStateInner) {
+    let unused = state.unused_plates();
+    if unused.is_empty() {
+        return;
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        for path in unused {
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::warn!("Failed to remove plate {}: {}", path, e);
+            }
+        }
+    });
+}
+
+/// Forwards job progress to the frontend as `processing-progress` events
+fn progress_reporter(app: &AppHandle) -> impl Fn(JobProgress) + Send + Sync + 'static {
+    let app = app.clone();
+    move |progress| {
+        if let Err(e) = app.emit("processing-progress", progress) {
+            log::warn!("Failed to emit progress event: {}", e);
+        }
+    }
 }
 
 #[tauri::command]
 pub async fn select_image(
     state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
     app: AppHandle,
 ) -> Result<AppResponse, Error> {
     if let Some(selected_path) = app
@@ -35,8 +140,6 @@ pub async fn select_image(
         .blocking_pick_file()
         .map(|p| p.to_string())
     {
-        let mut state_lock = state.lock().unwrap();
-
         let image_type = selected_path
             .rsplit_once('.')
             .map(|(_, ext)| ext.to_string())
@@ -48,31 +151,76 @@ pub async fn select_image(
             .unwrap_or_default();
 
         // Store in state
+        let mut state_lock = state.write();
         state_lock.image_path = Some(selected_path.clone());
         state_lock.current_image = Some(selected_path.clone());
         state_lock.image_type = Some(image_type.clone());
         state_lock.image_name = Some(image_name.clone());
-        state_lock.processing_status = ProcessingStatus::Processing;
+        // Channels cached for the previous image must not leak into this one
+        state_lock.preprocessed_channels = None;
+        state_lock.processed_images = None;
+        state_lock.history.clear();
+        state_lock.variants.clear();
+        remove_unused_plates(&mut state_lock);
+        set_processing_status(&app, &mut state_lock, ProcessingStatus::Processing, None);
+
+        // A new image supersedes any separation or processing still running
+        jobs.cancel(JobKind::Processing);
+        jobs.cancel(JobKind::Variants);
+        let (job_id, token) = jobs.start(JobKind::Separation);
+        let ctx = JobContext::new(job_id, JobKind::Separation, token, progress_reporter(&app));
 
         // Clone what we need for the background task
         let image_path = selected_path.clone();
         let app_handle = app.clone();
 
-        // Create a background task to process the image
-        tauri::async_runtime::spawn(async move {
-            // Process the image in the background
-            match process_image_background(&image_path) {
+        // Separate the channels off the command thread
+        tauri::async_runtime::spawn_blocking(move || {
+            let result = process_image_background(&image_path, &ctx);
+
+            let app_state = app_handle.state::<AppState>();
+            let jobs = app_handle.state::<JobManager>();
+            let mut state = app_state.write();
+            if !jobs.is_current(JobKind::Separation, job_id) {
+                log::info!("Discarding stale separation job {}", job_id);
+                return;
+            }
+            jobs.finish(JobKind::Separation, job_id);
+
+            // The status is only this separation's to change while it is still
+            // the Processing it set: a processing run that started, finished or
+            // failed in the meantime owns it now. Checked under the write lock
+            // so a run can't slip in between.
+            let owns_status = state.processing_status == ProcessingStatus::Processing
+                && state.processed_images.is_none()
+                && !jobs.is_running(JobKind::Processing);
+
+            match result {
                 Ok(result) => {
-                    let app_state = app_handle.state::<AppState>();
-                    let mut state = app_state.lock().unwrap();
                     // Store in a separate field, NOT processed_images
                     state.preprocessed_channels = Some(result);
-                    // We don't update processing_status to Completed yet,
-                    // nor do we emit the event that updates the UI.
+                    // The separation is only a cache, so the image goes back to
+                    // idle rather than completed
+                    if owns_status {
+                        set_processing_status(
+                            &app_handle,
+                            &mut state,
+                            ProcessingStatus::Idle,
+                            None,
+                        );
+                    }
                     log::info!("Background channel separation completed and cached");
                 }
                 Err(e) => {
                     log::error!("Background processing failed: {}", e);
+                    if owns_status {
+                        set_processing_status(
+                            &app_handle,
+                            &mut state,
+                            ProcessingStatus::Failed,
+                            Some(e.to_string()),
+                        );
+                    }
                 }
             };
         });
@@ -83,7 +231,7 @@ pub async fn select_image(
             image_path: selected_path,
             image_type,
             image_name,
-            processing_status: ProcessingStatus::Processing,
+            processing_status: state_lock.processing_status.clone(),
         })
     } else {
         Err(Error::NoImageSelected)
@@ -91,14 +239,18 @@ pub async fn select_image(
 }
 
 #[tauri::command]
-pub fn get_processing_status(state: State<'_, AppState>) -> ProcessingStatus {
-    state.lock().unwrap().processing_status.clone()
+pub fn get_processing_status(state: State<'_, AppState>) -> ProcessingStatusPayload {
+    let state = state.read();
+    ProcessingStatusPayload {
+        status: state.processing_status.clone(),
+        error: state.processing_error.clone(),
+    }
 }
 
 #[tauri::command]
 pub fn read_image(state: State<'_, AppState>) -> Result<String, Error> {
-    let state = state.lock().unwrap();
-    if let Some(ref path) = state.current_image {
+    let current_image = state.read().current_image.clone();
+    if let Some(ref path) = current_image {
         let image_bytes = fs::read(path)?;
         let base64_string = base64_engine.encode(&image_bytes);
         Ok(base64_string)
@@ -109,8 +261,8 @@ pub fn read_image(state: State<'_, AppState>) -> Result<String, Error> {
 
 #[tauri::command]
 pub async fn read_processed_images(state: State<'_, AppState>) -> Result<Vec<[String; 2]>, Error> {
-    let state = state.lock().unwrap();
-    if let Some(ref processed_images) = state.processed_images {
+    let processed_images = state.read().processed_images.clone();
+    if let Some(ref processed_images) = processed_images {
         let mut images = vec![];
         for img in processed_images {
             let image_bytes = fs::read(&img.image_path)?;
@@ -133,33 +285,402 @@ pub async fn process_colormap(
     Ok(colormap)
 }
 
+/// Runs the pipeline with `settings` as the current processing job, taking
+/// the status through Processing to its outcome. `on_complete` runs under
+/// the same lock that stores the plates, so nothing sees one without the
+/// other.
+async fn run_processing(
+    state: &AppState,
+    jobs: &JobManager,
+    app: &AppHandle,
+    settings: ProcessSettings,
+    on_complete: impl FnOnce(&mut AppStateInner, &[ProcessResult]),
+) -> Result<Vec<ProcessResult>, Error> {
+    let (job_id, token, snapshot) = {
+        let mut state = state.write();
+        let path = state.image_path.clone().ok_or(Error::NoImageSelected)?;
+        // Update state with new process settings
+        state.process_settings = Some(settings.clone());
+        log::info!("Processing image: {:?}", state.process_settings);
+        // Register the job before the status changes so a separation finishing
+        // in between can see that processing has taken over
+        let (job_id, token) = jobs.start(JobKind::Processing);
+        set_processing_status(app, &mut state, ProcessingStatus::Processing, None);
+        log::debug!("Processing job {} started for {}", job_id, path);
+        (job_id, token, state.clone())
+    };
+
+    // The pipeline works on a snapshot, so the state stays unlocked meanwhile
+    let ctx = JobContext::new(job_id, JobKind::Processing, token, progress_reporter(app));
+    let path = snapshot.image_path.clone().unwrap_or_default();
+    let result = run_blocking(move || {
+        process_image(
+            &path,
+            Some(&settings),
+            snapshot.image_name.as_deref(),
+            snapshot.preprocessed_channels.as_ref(),
+            &ctx,
+        )
+    })
+    .await;
+
+    let mut state = state.write();
+    // A newer request has replaced this one; its result wins
+    if !jobs.is_current(JobKind::Processing, job_id) {
+        return Err(Error::Cancelled);
+    }
+    jobs.finish(JobKind::Processing, job_id);
+
+    let processed_result = match result {
+        Ok(processed_result) => processed_result,
+        Err(Error::Cancelled) => {
+            set_processing_status(app, &mut state, ProcessingStatus::Cancelled, None);
+            return Err(Error::Cancelled);
+        }
+        Err(e) => {
+            set_processing_status(
+                app,
+                &mut state,
+                ProcessingStatus::Failed,
+                Some(e.to_string()),
+            );
+            return Err(e);
+        }
+    };
+    state.processed_images = Some(processed_result.clone());
+    on_complete(&mut state, &processed_result);
+    set_processing_status(app, &mut state, ProcessingStatus::Completed, None);
+    Ok(processed_result)
+}
+
 #[tauri::command(rename_all = "snake_case")]
-pub fn process_selected_image(
+pub async fn process_selected_image(
     state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
     process_data: ProcessSettings,
 ) -> Result<AppResponse, Error> {
-    let mut state = state.lock().unwrap();
-    if let Some(path) = state.image_path.clone() {
-        // Update state with new process settings
-        state.process_settings = Some(process_data);
-        log::info!("Processing image: {:?}", state.process_settings);
+    let settings = process_data.clone();
+    let processed_result = run_processing(&state, &jobs, &app, process_data, |state, results| {
+        state.history.push(settings, results.to_vec());
+        remove_unused_plates(state);
+    })
+    .await?;
 
-        match process_image(&path, &state, state.preprocessed_channels.as_ref()) {
-            Ok(processed_result) => {
-                state.processed_images = Some(processed_result.clone());
-                Ok(AppResponse {
-                    processed_images: Some(processed_result),
-                    image_path: path,
-                    image_type: state.image_type.clone().unwrap_or_default(),
-                    image_name: state.image_name.clone().unwrap_or_default(),
-                    processing_status: state.processing_status.clone(),
-                })
+    let state = state.read();
+    Ok(AppResponse {
+        processed_images: Some(processed_result),
+        image_path: state.image_path.clone().unwrap_or_default(),
+        image_type: state.image_type.clone().unwrap_or_default(),
+        image_name: state.image_name.clone().unwrap_or_default(),
+        processing_status: state.processing_status.clone(),
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct HistoryResponse {
+    processed_images: Vec<ProcessResult>,
+    process_settings: ProcessSettings,
+    history: HistoryListing,
+}
+
+/// Makes a history entry current, reprocessing it when its plates are no
+/// longer cached
+async fn show_history_entry(
+    state: &AppState,
+    jobs: &JobManager,
+    app: &AppHandle,
+    id: u64,
+) -> Result<HistoryResponse, Error> {
+    let (settings, cached) = {
+        let mut state = state.write();
+        let (settings, cached) = state.history.select(id)?;
+        // Temp files can be cleaned up under us
+        let cached = cached.filter(|plates| {
+            plates
+                .iter()
+                .all(|plate| std::path::Path::new(&plate.image_path).exists())
+        });
+        if let Some(plates) = &cached {
+            if jobs.cancel(JobKind::Processing) {
+                set_processing_status(app, &mut state, ProcessingStatus::Cancelled, None);
             }
-            Err(e) => Err(e),
+            state.process_settings = Some(settings.clone());
+            state.processed_images = Some(plates.clone());
+            // The plates shown before may have been waiting on this
+            remove_unused_plates(&mut state);
         }
-    } else {
-        Err(Error::NoImageSelected)
+        (settings, cached)
+    };
+
+    let processed_images = match cached {
+        Some(plates) => plates,
+        None => {
+            run_processing(state, jobs, app, settings.clone(), |state, results| {
+                state.history.cache(id, results.to_vec());
+                remove_unused_plates(state);
+            })
+            .await?
+        }
+    };
+
+    Ok(HistoryResponse {
+        processed_images,
+        process_settings: settings,
+        history: state.read().history.listing(),
+    })
+}
+
+/// Goes back to the settings of the previous run
+#[tauri::command]
+pub async fn undo_processing(
+    state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
+) -> Result<HistoryResponse, Error> {
+    let id = state.read().history.previous()?;
+    show_history_entry(&state, &jobs, &app, id).await
+}
+
+/// Goes forward again after an undo
+#[tauri::command]
+pub async fn redo_processing(
+    state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
+) -> Result<HistoryResponse, Error> {
+    let id = state.read().history.next()?;
+    show_history_entry(&state, &jobs, &app, id).await
+}
+
+/// Jumps to any entry in the history listing
+#[tauri::command]
+pub async fn go_to_history(
+    state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
+    id: u64,
+) -> Result<HistoryResponse, Error> {
+    show_history_entry(&state, &jobs, &app, id).await
+}
+
+#[tauri::command]
+pub fn get_history(state: State<'_, AppState>) -> HistoryListing {
+    state.read().history.listing()
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VariantPreview {
+    #[serde(flatten)]
+    variant: Variant,
+    /// PNG data URL of the variant's composite
+    composite: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VariantsResponse {
+    /// PNG data URL of every composite laid out side by side
+    grid: String,
+    cells: Vec<GridCell>,
+    variants: Vec<VariantPreview>,
+}
+
+/// Runs the pipeline once per variant and keeps each result under its name,
+/// replacing earlier variants with the same name. The current plates are
+/// left alone until a variant is promoted.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn generate_variants(
+    state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
+    variants: Vec<VariantSpec>,
+    cell_size: Option<u32>,
+) -> Result<VariantsResponse, Error> {
+    let specs = validate_specs(variants)?;
+    let snapshot = state.snapshot();
+    let path = snapshot.image_path.clone().ok_or(Error::NoImageSelected)?;
+    let (job_id, token) = jobs.start(JobKind::Variants);
+    let report = std::sync::Arc::new(progress_reporter(&app));
+    let cell_size = cell_size.unwrap_or(DEFAULT_CELL_SIZE);
+
+    let result = run_blocking(move || {
+        let count = specs.len();
+        let image_name = snapshot.image_name.clone().unwrap_or_default();
+        let mut generated = Vec::with_capacity(count);
+        let mut composites = Vec::with_capacity(count);
+
+        for (i, spec) in specs.into_iter().enumerate() {
+            // Each run reports its own progress; spread them over the job
+            let report = report.clone();
+            let ctx = JobContext::new(job_id, JobKind::Variants, token.clone(), move |progress| {
+                report(JobProgress {
+                    progress: (i as f32 + progress.progress) / count as f32,
+                    ..progress
+                })
+            });
+            // Runs can finish within the same millisecond, so the index
+            // keeps their plate files apart
+            let plates = process_image(
+                &path,
+                Some(&spec.settings),
+                Some(&format!("variant{}_{}", i, image_name)),
+                snapshot.preprocessed_channels.as_ref(),
+                &ctx,
+            )?;
+            // Only the cell sized composite is kept, not one per variant at
+            // full resolution
+            let composite = composite_channels(&plates, spec.settings.colors.as_ref())?;
+            composites.push((spec.name.clone(), fit_cell(&composite, cell_size)));
+            generated.push(Variant {
+                name: spec.name,
+                settings: spec.settings,
+                processed_images: plates,
+            });
+        }
+
+        let (grid, cells) = variant_grid(&composites, cell_size)?;
+        let previews = generated
+            .into_iter()
+            .zip(&composites)
+            .map(|(variant, (_, composite))| {
+                Ok(VariantPreview {
+                    variant,
+                    composite: composite_preview(composite, Some(cell_size))?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(VariantsResponse {
+            grid: composite_preview(&grid, None)?,
+            cells,
+            variants: previews,
+        })
+    })
+    .await;
+
+    let mut state = state.write();
+    // A newer request or another image has replaced this one
+    if !jobs.is_current(JobKind::Variants, job_id) {
+        return Err(Error::Cancelled);
+    }
+    jobs.finish(JobKind::Variants, job_id);
+
+    let response = result?;
+    for preview in &response.variants {
+        let variant = preview.variant.clone();
+        match state
+            .variants
+            .iter_mut()
+            .find(|v| v.name.eq_ignore_ascii_case(&variant.name))
+        {
+            Some(existing) => *existing = variant,
+            None => state.variants.push(variant),
+        }
+    }
+    Ok(response)
+}
+
+#[tauri::command]
+pub fn list_variants(state: State<'_, AppState>) -> Vec<Variant> {
+    state.read().variants.clone()
+}
+
+fn find_variant(state: &AppStateInner, name: &str) -> Result<Variant, Error> {
+    state
+        .variants
+        .iter()
+        .find(|v| v.name.eq_ignore_ascii_case(name.trim()))
+        .cloned()
+        .ok_or_else(|| Error::VariantNotFound(name.to_string()))
+}
+
+/// Plates of a variant as base64 PNGs with their channel names, like
+/// `read_processed_images`
+#[tauri::command]
+pub async fn read_variant_plates(
+    state: State<'_, AppState>,
+    name: String,
+) -> Result<Vec<[String; 2]>, Error> {
+    let variant = find_variant(&state.read(), &name)?;
+    run_blocking(move || {
+        variant
+            .processed_images
+            .iter()
+            .map(|plate| {
+                let image_bytes = fs::read(&plate.image_path)?;
+                Ok([base64_engine.encode(&image_bytes), plate.channel.clone()])
+            })
+            .collect()
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn delete_variant(state: State<'_, AppState>, name: String) -> Result<(), Error> {
+    let mut state = state.write();
+    let before = state.variants.len();
+    state
+        .variants
+        .retain(|v| !v.name.eq_ignore_ascii_case(name.trim()));
+    if state.variants.len() == before {
+        return Err(Error::VariantNotFound(name));
+    }
+    Ok(())
+}
+
+/// Makes a variant the current job, so exports use its plates. The switch
+/// is recorded in the history and can be undone.
+#[tauri::command]
+pub fn promote_variant(
+    state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
+    name: String,
+) -> Result<HistoryResponse, Error> {
+    let mut state = state.write();
+    let variant = find_variant(&state, &name)?;
+    // A run still going would overwrite the promoted plates when it finishes
+    if jobs.cancel(JobKind::Processing) {
+        set_processing_status(&app, &mut state, ProcessingStatus::Cancelled, None);
     }
+
+    state.process_settings = Some(variant.settings.clone());
+    state.processed_images = Some(variant.processed_images.clone());
+    state
+        .history
+        .push(variant.settings.clone(), variant.processed_images.clone());
+    Ok(HistoryResponse {
+        processed_images: variant.processed_images,
+        process_settings: variant.settings,
+        history: state.history.listing(),
+    })
+}
+
+#[tauri::command]
+pub fn cancel_processing(state: State<'_, AppState>, jobs: State<'_, JobManager>, app: AppHandle) {
+    // The cancelled job sees itself as stale and leaves the status alone,
+    // so the transition is made here
+    let mut state = state.write();
+    if jobs.cancel(JobKind::Processing) {
+        set_processing_status(&app, &mut state, ProcessingStatus::Cancelled, None);
+    }
+}
+
+#[tauri::command]
+pub fn get_export_settings(state: State<'_, AppState>) -> ExportSettings {
+    state.read().export_settings.clone()
+}
+
+#[tauri::command]
+pub fn set_export_settings(
+    state: State<'_, AppState>,
+    settings: ExportSettings,
+) -> Result<(), Error> {
+    settings.page.validate()?;
+    if let Some(cost) = &settings.job_sheet.cost {
+        cost.validate()?;
+    }
+    state.write().export_settings = settings;
+    Ok(())
 }
 
 #[tauri::command(rename_all = "snake_case")]
@@ -168,65 +689,250 @@ pub async fn export_channels(
     app: AppHandle,
     export_type: String,
 ) -> Result<(), Error> {
-    let state = state.lock().unwrap();
-    if let Some(processed_images) = &state.processed_images {
-        // Get the base filename without extension
-        let base_name = state
-            .image_name
+    // Work from a copy so the dialog and the export don't block other commands
+    let snapshot = state.snapshot();
+    let processed_images = snapshot
+        .processed_images
+        .clone()
+        .ok_or_else(|| Error::Processing("No processed images to export".to_string()))?;
+
+    // Get the base filename without extension
+    let base_name = snapshot.base_name().unwrap_or_else(|| "export".to_string());
+
+    // Set up dialog with appropriate filter based on export type
+    let dialog = app
+        .dialog()
+        .file()
+        .set_directory(app.path().download_dir().unwrap());
+
+    let format = ExportFormat::from_export_type(&export_type)?;
+    let dialog = match format {
+        ExportFormat::Pdf | ExportFormat::SpotPdf => dialog
+            .add_filter("PDF Document", &["pdf"])
+            .set_file_name(format!("{}.pdf", base_name)),
+        ExportFormat::Png => dialog
+            .add_filter("PNG Images", &["png"])
+            .set_file_name(format!("{}_channels", base_name)),
+        ExportFormat::Tiff => dialog
+            .add_filter("TIFF Images", &["tif", "tiff"])
+            .set_file_name(format!("{}_channels", base_name)),
+        ExportFormat::Psd => dialog
+            .add_filter("Photoshop Document", &["psd"])
+            .set_file_name(format!("{}.psd", base_name)),
+    };
+
+    let Some(export_path) = dialog.blocking_save_file().map(|p| p.to_string()) else {
+        // User cancelled
+        return Ok(());
+    };
+
+    run_blocking(move || {
+        // The dialog returns a full path including filename.
+        // We want to use the directory for PNGs or the full path for PDF.
+        // But our export functions expect a directory and a base filename.
+        let path_obj = std::path::Path::new(&export_path);
+        let parent_dir = path_obj.parent().unwrap().to_str().unwrap();
+        let file_stem = path_obj.file_stem().unwrap().to_str().unwrap();
+
+        // Get colors from process settings
+        let colors = snapshot
+            .process_settings
             .as_ref()
-            .map(|n| {
-                n.rsplit_once('.')
-                    .map(|(name, _)| name.to_string())
-                    .unwrap_or_else(|| n.clone())
-            })
-            .unwrap_or_else(|| "export".to_string());
-
-        // Set up dialog with appropriate filter based on export type
-        let dialog = app
-            .dialog()
-            .file()
-            .set_directory(app.path().download_dir().unwrap());
-
-        let dialog = match export_type.as_str() {
-            "0" => dialog
-                .add_filter("PDF Document", &["pdf"])
-                .set_file_name(&format!("{}.pdf", base_name)),
-            "1" => dialog
-                .add_filter("PNG Images", &["png"])
-                .set_file_name(&format!("{}_channels", base_name)),
-            _ => return Err(Error::Processing("Invalid export type".to_string())),
-        };
+            .and_then(|s| s.colors.as_ref());
 
-        if let Some(export_path) = dialog.blocking_save_file().map(|p| p.to_string()) {
-            // The dialog returns a full path including filename.
-            // We want to use the directory for PNGs or the full path for PDF.
-            // But our export functions expect a directory and a base filename.
-
-            let path_obj = std::path::Path::new(&export_path);
-            let parent_dir = path_obj.parent().unwrap().to_str().unwrap();
-            let file_stem = path_obj.file_stem().unwrap().to_str().unwrap();
-
-            // Get colors from process settings
-            let colors = state
-                .process_settings
-                .as_ref()
-                .and_then(|s| s.colors.as_ref());
-
-            match export_type.as_str() {
-                "0" => save_channels_to_pdf(processed_images, parent_dir, file_stem, colors)?, // PDF
-                "1" => save_channels_to_disk(processed_images, parent_dir, file_stem, colors)?, // PNG
-                _ => return Err(Error::Processing("Invalid export type".to_string())),
-            }
-            Ok(())
-        } else {
-            // User cancelled
-            Ok(())
-        }
-    } else {
-        Err(Error::Processing(
-            "No processed images to export".to_string(),
-        ))
-    }
+        export_plates(
+            format,
+            &processed_images,
+            parent_dir,
+            file_stem,
+            colors,
+            &snapshot.export_settings,
+        )
+    })
+    .await
+}
+
+/// Composite of the processed plates in their inks as a PNG data URL, scaled
+/// to fit `max_size` pixels when given
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_composite_preview(
+    state: State<'_, AppState>,
+    max_size: Option<u32>,
+) -> Result<String, Error> {
+    let snapshot = state.snapshot();
+    let processed_images = snapshot
+        .processed_images
+        .ok_or_else(|| Error::Processing("No processed images to composite".to_string()))?;
+    let colors = snapshot.process_settings.and_then(|s| s.colors);
+
+    run_blocking(move || {
+        let image = composite_channels(&processed_images, colors.as_ref())?;
+        composite_preview(&image, max_size)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_composite(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    format: CompositeFormat,
+) -> Result<(), Error> {
+    let snapshot = state.snapshot();
+    let processed_images = snapshot
+        .processed_images
+        .clone()
+        .ok_or_else(|| Error::Processing("No processed images to export".to_string()))?;
+    let colors = snapshot
+        .process_settings
+        .as_ref()
+        .and_then(|s| s.colors.clone());
+
+    let default_name = snapshot
+        .base_name()
+        .map(|name| format!("{}_composite", name))
+        .unwrap_or_else(|| "composite".to_string());
+    let extension = format.extension();
+
+    let Some(save_path) = app
+        .dialog()
+        .file()
+        .add_filter("Composite Image", &[extension])
+        .set_directory(app.path().download_dir().unwrap())
+        .set_file_name(format!("{}.{}", default_name, extension))
+        .blocking_save_file()
+        .map(|p| p.to_string())
+    else {
+        // User cancelled
+        return Ok(());
+    };
+
+    run_blocking(move || {
+        let image = composite_channels(&processed_images, colors.as_ref())?;
+        let dpi = processed_images.first().and_then(|c| c.dpi);
+        save_composite(&image, std::path::Path::new(&save_path), format, dpi)
+    })
+    .await
+}
+
+/// Simulated RISO print of the processed plates as a PNG data URL, scaled to
+/// fit `max_size` pixels when given
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_proof_preview(
+    state: State<'_, AppState>,
+    settings: ProofSettings,
+    max_size: Option<u32>,
+) -> Result<String, Error> {
+    let snapshot = state.snapshot();
+    let processed_images = snapshot
+        .processed_images
+        .ok_or_else(|| Error::Processing("No processed images to proof".to_string()))?;
+    let colors = snapshot.process_settings.and_then(|s| s.colors);
+
+    run_blocking(move || {
+        let image = render_proof_for_channels(&processed_images, colors.as_ref(), &settings)?;
+        composite_preview(&image, max_size)
+    })
+    .await
+}
+
+/// Ink coverage of each processed plate and a heatmap of where the total
+/// area coverage goes over `tac_limit` percent
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_coverage_report(
+    state: State<'_, AppState>,
+    tac_limit: Option<f32>,
+    max_size: Option<u32>,
+) -> Result<CoverageReport, Error> {
+    let snapshot = state.snapshot();
+    let processed_images = snapshot
+        .processed_images
+        .ok_or_else(|| Error::Processing("No processed images to measure".to_string()))?;
+    let colors = snapshot.process_settings.and_then(|s| s.colors);
+
+    run_blocking(move || coverage_report(&processed_images, colors.as_ref(), tac_limit, max_size))
+        .await
+}
+
+/// Masters, ink and cost of printing the processed plates, placed on the
+/// current page setup
+#[tauri::command]
+pub async fn estimate_job_cost(
+    state: State<'_, AppState>,
+    settings: CostSettings,
+) -> Result<CostEstimate, Error> {
+    let snapshot = state.snapshot();
+    let processed_images = snapshot
+        .processed_images
+        .ok_or_else(|| Error::Processing("No processed images to estimate".to_string()))?;
+    let colors = snapshot.process_settings.and_then(|s| s.colors);
+    let page = snapshot.export_settings.page;
+
+    run_blocking(move || estimate_cost(&processed_images, colors.as_ref(), &page, &settings)).await
+}
+
+/// Renders the print with each plate offset by the given misregistration and
+/// reports where white gaps or colour fringes would show
+#[tauri::command(rename_all = "snake_case")]
+pub async fn check_misregistration(
+    state: State<'_, AppState>,
+    offsets: Vec<PlateOffset>,
+    max_size: Option<u32>,
+) -> Result<RegistrationReport, Error> {
+    let snapshot = state.snapshot();
+    let processed_images = snapshot
+        .processed_images
+        .ok_or_else(|| Error::Processing("No processed images to check".to_string()))?;
+    let colors = snapshot.process_settings.and_then(|s| s.colors);
+
+    run_blocking(move || {
+        registration_report(&processed_images, colors.as_ref(), &offsets, max_size)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_proof(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    settings: ProofSettings,
+    format: CompositeFormat,
+) -> Result<(), Error> {
+    let snapshot = state.snapshot();
+    let processed_images = snapshot
+        .processed_images
+        .clone()
+        .ok_or_else(|| Error::Processing("No processed images to export".to_string()))?;
+    let colors = snapshot
+        .process_settings
+        .as_ref()
+        .and_then(|s| s.colors.clone());
+
+    let default_name = snapshot
+        .base_name()
+        .map(|name| format!("{}_proof", name))
+        .unwrap_or_else(|| "proof".to_string());
+    let extension = format.extension();
+
+    let Some(save_path) = app
+        .dialog()
+        .file()
+        .add_filter("Proof Image", &[extension])
+        .set_directory(app.path().download_dir().unwrap())
+        .set_file_name(format!("{}.{}", default_name, extension))
+        .blocking_save_file()
+        .map(|p| p.to_string())
+    else {
+        // User cancelled
+        return Ok(());
+    };
+
+    run_blocking(move || {
+        let image = render_proof_for_channels(&processed_images, colors.as_ref(), &settings)?;
+        let dpi = processed_images.first().and_then(|c| c.dpi);
+        save_composite(&image, std::path::Path::new(&save_path), format, dpi)
+    })
+    .await
 }
 
 #[tauri::command(rename_all = "snake_case")]
@@ -235,16 +941,11 @@ pub async fn save_composed_image(
     app: AppHandle,
     image_data: String,
 ) -> Result<(), Error> {
-    let state = state.lock().unwrap();
+    // Remove extension and add _composed suffix
     let default_name = state
-        .image_name
-        .as_ref()
-        .map(|n| {
-            // Remove extension and add _composed suffix
-            n.rsplit_once('.')
-                .map(|(name, _)| format!("{}_composed", name))
-                .unwrap_or_else(|| format!("{}_composed", n))
-        })
+        .read()
+        .base_name()
+        .map(|name| format!("{}_composed", name))
         .unwrap_or_else(|| "composed_image".to_string());
 
     if let Some(save_path) = app
@@ -256,30 +957,400 @@ pub async fn save_composed_image(
         .blocking_save_file()
         .map(|p| p.to_string())
     {
-        // The image_data is a base64 string (with or without data URL prefix)
-        let base64_data = if image_data.contains(",") {
-            image_data.split(',').nth(1).unwrap_or(&image_data)
-        } else {
-            &image_data
-        };
-
-        let image_bytes = base64_engine
-            .decode(base64_data)
-            .map_err(|e| Error::Processing(format!("Failed to decode base64: {}", e)))?;
+        run_blocking(move || {
+            // The image_data is a base64 string (with or without data URL prefix)
+            let base64_data = if image_data.contains(",") {
+                image_data.split(',').nth(1).unwrap_or(&image_data)
+            } else {
+                &image_data
+            };
 
-        // Ensure the path has .png extension
-        let final_path = if save_path.ends_with(".png") {
-            save_path
-        } else {
-            format!("{}.png", save_path)
-        };
+            let image_bytes = base64_engine
+                .decode(base64_data)
+                .map_err(|e| Error::Processing(format!("Failed to decode base64: {}", e)))?;
 
-        fs::write(&final_path, image_bytes)
-            .map_err(|e| Error::Processing(format!("Failed to save image: {}", e)))?;
+            // Ensure the path has .png extension
+            let final_path = if save_path.ends_with(".png") {
+                save_path
+            } else {
+                format!("{}.png", save_path)
+            };
 
-        Ok(())
+            fs::write(&final_path, image_bytes)
+                .map_err(|e| Error::Processing(format!("Failed to save image: {}", e)))
+        })
+        .await
     } else {
         // User cancelled
         Ok(())
     }
 }
+
+/// Saves the current job as a `.r110` project, optionally embedding the
+/// source image and the processed plates
+#[tauri::command]
+pub async fn save_project(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    options: SaveOptions,
+) -> Result<(), Error> {
+    let snapshot = state.snapshot();
+    let default_name = snapshot.base_name().ok_or(Error::NoImageSelected)?;
+
+    let Some(save_path) = app
+        .dialog()
+        .file()
+        .add_filter("R110 Project", &[PROJECT_EXTENSION])
+        .set_directory(app.path().document_dir().unwrap_or_default())
+        .set_file_name(format!("{}.{}", default_name, PROJECT_EXTENSION))
+        .blocking_save_file()
+        .map(|p| p.to_string())
+    else {
+        // User cancelled
+        return Ok(());
+    };
+
+    run_blocking(move || {
+        let path = std::path::Path::new(&save_path).with_extension(PROJECT_EXTENSION);
+        project::save_project(&snapshot, &path, &options)
+    })
+    .await
+}
+
+/// Opens a `.r110` project, replacing the current job with it
+#[tauri::command]
+pub async fn open_project(
+    state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
+) -> Result<ProjectResponse, Error> {
+    let Some(path) = app
+        .dialog()
+        .file()
+        .add_filter("R110 Project", &[PROJECT_EXTENSION])
+        .blocking_pick_file()
+        .map(|p| p.to_string())
+    else {
+        return Err(Error::NoImageSelected);
+    };
+
+    let opened = run_blocking(move || project::open_project(std::path::Path::new(&path))).await?;
+    let source = &opened.project.source;
+
+    // Whatever was running belongs to the job being replaced
+    jobs.cancel(JobKind::Separation);
+    jobs.cancel(JobKind::Processing);
+    jobs.cancel(JobKind::Variants);
+
+    let mut state = state.write();
+    state.image_path = Some(opened.source_path.clone());
+    state.current_image = Some(opened.source_path.clone());
+    state.image_type = Some(source.image_type.clone());
+    state.image_name = Some(source.name.clone());
+    state.process_settings = opened.project.process_settings.clone();
+    state.export_settings = opened.project.export_settings.clone();
+    state.processed_images = opened.processed_images.clone();
+    state.preprocessed_channels = None;
+    state.history.clear();
+    state.variants.clear();
+    if let (Some(settings), Some(plates)) = (&state.process_settings, &state.processed_images) {
+        let (settings, plates) = (settings.clone(), plates.clone());
+        state.history.push(settings, plates);
+    }
+    remove_unused_plates(&mut state);
+    set_processing_status(&app, &mut state, ProcessingStatus::Idle, None);
+
+    Ok(ProjectResponse {
+        processed_images: opened.processed_images,
+        image_path: opened.source_path,
+        image_type: source.image_type.clone(),
+        image_name: source.name.clone(),
+        process_settings: opened.project.process_settings,
+        export_settings: opened.project.export_settings,
+        source_modified: opened.source_modified,
+    })
+}
+
+/// Presets live in `presets` under the app config dir
+fn preset_store(app: &AppHandle) -> Result<PresetStore, Error> {
+    let dir = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| Error::Processing(format!("No config directory: {}", e)))?;
+    Ok(PresetStore::new(dir.join("presets")))
+}
+
+#[tauri::command]
+pub async fn list_presets(app: AppHandle) -> Result<Vec<Preset>, Error> {
+    let store = preset_store(&app)?;
+    run_blocking(move || store.list()).await
+}
+
+/// Saves `settings` as a preset, or the current settings when none are given
+#[tauri::command]
+pub async fn save_preset(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    name: String,
+    settings: Option<ProcessSettings>,
+) -> Result<Preset, Error> {
+    let settings = settings
+        .or_else(|| state.read().process_settings.clone())
+        .ok_or_else(|| Error::InvalidPreset("there are no settings to save".to_string()))?;
+    let store = preset_store(&app)?;
+    run_blocking(move || store.save(&name, settings)).await
+}
+
+/// Makes a preset's settings the current ones and returns them so the
+/// frontend can update its controls before processing
+#[tauri::command]
+pub async fn apply_preset(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    name: String,
+) -> Result<ProcessSettings, Error> {
+    let store = preset_store(&app)?;
+    let preset = run_blocking(move || store.get(&name)).await?;
+    state.write().process_settings = Some(preset.settings.clone());
+    Ok(preset.settings)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn rename_preset(
+    app: AppHandle,
+    name: String,
+    new_name: String,
+) -> Result<Preset, Error> {
+    let store = preset_store(&app)?;
+    run_blocking(move || store.rename(&name, &new_name)).await
+}
+
+#[tauri::command]
+pub async fn delete_preset(app: AppHandle, name: String) -> Result<(), Error> {
+    let store = preset_store(&app)?;
+    run_blocking(move || store.delete(&name)).await
+}
+
+/// Imports a preset file picked by the user; `None` when cancelled
+#[tauri::command]
+pub async fn import_preset(app: AppHandle) -> Result<Option<Preset>, Error> {
+    let Some(path) = app
+        .dialog()
+        .file()
+        .add_filter("R110 Preset", &[PRESET_EXTENSION])
+        .blocking_pick_file()
+        .map(|p| p.to_string())
+    else {
+        return Ok(None);
+    };
+
+    let store = preset_store(&app)?;
+    run_blocking(move || store.import(std::path::Path::new(&path)).map(Some)).await
+}
+
+#[tauri::command]
+pub async fn export_preset(app: AppHandle, name: String) -> Result<(), Error> {
+    let Some(save_path) = app
+        .dialog()
+        .file()
+        .add_filter("R110 Preset", &[PRESET_EXTENSION])
+        .set_directory(app.path().document_dir().unwrap_or_default())
+        .set_file_name(format!("{}.{}", name.trim(), PRESET_EXTENSION))
+        .blocking_save_file()
+        .map(|p| p.to_string())
+    else {
+        // User cancelled
+        return Ok(());
+    };
+
+    let store = preset_store(&app)?;
+    run_blocking(move || {
+        let path = std::path::Path::new(&save_path).with_extension(PRESET_EXTENSION);
+        store.export(&name, &path)
+    })
+    .await
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BatchProgress {
+    job_id: u64,
+    done: usize,
+    total: usize,
+    item: BatchItem,
+}
+
+/// Processes many images with a preset and exports each of them. Asks for
+/// files when no input is given and for the output folder when none is
+/// given; `None` when a dialog is cancelled. Each finished file is sent as a
+/// `batch-progress` event.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn run_batch(
+    state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
+    preset: String,
+    input: Option<BatchInput>,
+    output_dir: Option<String>,
+    options: Option<BatchOptions>,
+) -> Result<Option<BatchReport>, Error> {
+    let input = match input {
+        Some(input) => input,
+        None => {
+            let Some(picked) = app
+                .dialog()
+                .file()
+                .add_filter("Images", &IMAGE_EXTENSIONS)
+                .blocking_pick_files()
+            else {
+                return Ok(None);
+            };
+            BatchInput::Files(picked.into_iter().map(|p| p.to_string()).collect())
+        }
+    };
+    let output_dir = match output_dir {
+        Some(dir) => dir,
+        None => {
+            let Some(dir) = app
+                .dialog()
+                .file()
+                .set_directory(app.path().download_dir().unwrap_or_default())
+                .blocking_pick_folder()
+            else {
+                return Ok(None);
+            };
+            dir.to_string()
+        }
+    };
+
+    let preset = {
+        let store = preset_store(&app)?;
+        run_blocking(move || store.get(&preset)).await?
+    };
+    let files = run_blocking(move || input.files()).await?;
+    let export_settings = state.read().export_settings.clone();
+    let options = options.unwrap_or_default();
+    let (job_id, token) = jobs.start(JobKind::Batch);
+    let total = files.len();
+    let done = std::sync::atomic::AtomicUsize::new(0);
+    let events = app.clone();
+
+    let report = run_blocking(move || {
+        batch::run_batch(
+            &files,
+            &preset.settings,
+            &export_settings,
+            &options,
+            &preset.name,
+            std::path::Path::new(&output_dir),
+            job_id,
+            &token,
+            |item| {
+                let done = done.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                let progress = BatchProgress {
+                    job_id,
+                    done,
+                    total,
+                    item: item.clone(),
+                };
+                if let Err(e) = events.emit("batch-progress", progress) {
+                    log::warn!("Failed to emit batch progress: {}", e);
+                }
+            },
+        )
+    })
+    .await;
+    jobs.finish(JobKind::Batch, job_id);
+    report.map(Some)
+}
+
+/// Stops a running batch; files already exported are kept
+#[tauri::command]
+pub fn cancel_batch(jobs: State<'_, JobManager>) -> bool {
+    jobs.cancel(JobKind::Batch)
+}
+
+/// Starts watching a folder with a preset, replacing a watch already
+/// running. Each handled file is sent as a `watch-job` event, and a
+/// `watch-stopped` event carries the error if the watch gives up.
+#[tauri::command]
+pub async fn start_watch(
+    state: State<'_, AppState>,
+    jobs: State<'_, JobManager>,
+    app: AppHandle,
+    preset: String,
+    settings: WatchSettings,
+) -> Result<WatchStatus, Error> {
+    let preset = {
+        let store = preset_store(&app)?;
+        run_blocking(move || store.get(&preset)).await?
+    };
+    let checked = settings.clone();
+    run_blocking(move || prepare_folders(&checked)).await?;
+
+    let (job_id, token) = jobs.start(JobKind::Watch);
+    let export_settings = {
+        let mut state = state.write();
+        state.watch = WatchStatus {
+            running: true,
+            settings: Some(settings.clone()),
+            preset: Some(preset.name.clone()),
+            ..Default::default()
+        };
+        state.export_settings.clone()
+    };
+
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let jobs = app_handle.state::<JobManager>();
+        let app_state = app_handle.state::<AppState>();
+        let result = watch_folder(
+            &settings,
+            &preset.settings,
+            &export_settings,
+            &preset.name,
+            job_id,
+            &token,
+            |job| {
+                // A watch that has been replaced doesn't report into the new one
+                if jobs.is_current(JobKind::Watch, job_id) {
+                    app_state.write().watch.record(job.clone());
+                }
+                if let Err(e) = app_handle.emit("watch-job", job) {
+                    log::warn!("Failed to emit watch job: {}", e);
+                }
+            },
+        );
+
+        // Stopped or replaced; whoever did that has updated the status
+        let mut state = app_state.write();
+        if !jobs.is_current(JobKind::Watch, job_id) {
+            return;
+        }
+        jobs.finish(JobKind::Watch, job_id);
+        state.watch.running = false;
+        if let Err(e) = result {
+            log::error!("Watch stopped: {}", e);
+            state.watch.error = Some(e.to_string());
+            if let Err(e) = app_handle.emit("watch-stopped", e.to_string()) {
+                log::warn!("Failed to emit watch stop: {}", e);
+            }
+        }
+    });
+
+    Ok(state.read().watch.clone())
+}
+
+/// Stops the hot folder; a file being processed is left for the next watch
+#[tauri::command]
+pub fn stop_watch(state: State<'_, AppState>, jobs: State<'_, JobManager>) -> WatchStatus {
+    let mut state = state.write();
+    if jobs.cancel(JobKind::Watch) {
+        state.watch.running = false;
+    }
+    state.watch.clone()
+}
+
+#[tauri::command]
+pub fn get_watch_status(state: State<'_, AppState>) -> WatchStatus {
+    state.read().watch.clone()
+}
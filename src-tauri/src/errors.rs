@@ -1,3 +1,4 @@
+use crate::state::ProcessingStatus;
 use image::ImageError;
 
 #[derive(Debug, thiserror::Error)]
@@ -13,6 +14,57 @@ pub enum Error {
 
     #[error("Image processing error: {0}")]
     Processing(String),
+
+    #[error("Invalid page setup: {0}")]
+    InvalidPageSetup(String),
+
+    #[error("Invalid output size: {0}")]
+    InvalidOutputSize(String),
+
+    #[error("Invalid colour: {0}")]
+    InvalidColor(String),
+
+    #[error("Invalid trapping: {0}")]
+    InvalidTrapping(String),
+
+    #[error("Invalid project file: {0}")]
+    InvalidProject(String),
+
+    #[error("Invalid preset: {0}")]
+    InvalidPreset(String),
+
+    #[error("No preset named {0}")]
+    PresetNotFound(String),
+
+    #[error("Invalid variants: {0}")]
+    InvalidVariant(String),
+
+    #[error("No variant named {0}")]
+    VariantNotFound(String),
+
+    #[error("Invalid batch: {0}")]
+    InvalidBatch(String),
+
+    #[error("Invalid watch folder: {0}")]
+    InvalidWatch(String),
+
+    #[error("Nothing to undo")]
+    NothingToUndo,
+
+    #[error("Nothing to redo")]
+    NothingToRedo,
+
+    #[error("No history entry {0}")]
+    HistoryEntryNotFound(u64),
+
+    #[error("Processing was cancelled")]
+    Cancelled,
+
+    #[error("Cannot change processing status from {from:?} to {to:?}")]
+    InvalidStatusTransition {
+        from: ProcessingStatus,
+        to: ProcessingStatus,
+    },
 }
 
 // we must manually implement serde::Serialize
@@ -0,0 +1,311 @@
+//! Undo/redo of processing runs. Every successful run pushes a snapshot of
+//! its settings; the plates of the most recently used entries are kept so
+//! stepping to them is instant, while older entries are reprocessed.
+
+use crate::errors::Error;
+use crate::imaging::processes::ProcessResult;
+use crate::state::ProcessSettings;
+
+/// Entries kept before the oldest are dropped
+pub const MAX_HISTORY: usize = 50;
+/// Entries whose plates are kept
+pub const CACHED_RESULTS: usize = 5;
+
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    id: u64,
+    settings: ProcessSettings,
+    created_at: String,
+    results: Option<Vec<ProcessResult>>,
+    /// Tick of the last time the entry was shown, for evicting results
+    last_used: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HistoryItem {
+    pub id: u64,
+    pub settings: ProcessSettings,
+    pub created_at: String,
+    /// The plates are at hand, so showing the entry needs no processing
+    pub cached: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HistoryListing {
+    pub entries: Vec<HistoryItem>,
+    pub current: Option<u64>,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    current: Option<usize>,
+    next_id: u64,
+    tick: u64,
+    /// Plates of entries dropped or evicted, not yet handed out by
+    /// `take_released`
+    released: Vec<String>,
+}
+
+impl History {
+    pub fn clear(&mut self) {
+        let entries = std::mem::take(&mut self.entries);
+        let released = std::mem::take(&mut self.released);
+        *self = Self {
+            released,
+            ..Self::default()
+        };
+        self.release(entries.into_iter().filter_map(|entry| entry.results));
+    }
+
+    fn release(&mut self, results: impl IntoIterator<Item = Vec<ProcessResult>>) {
+        self.released
+            .extend(results.into_iter().flatten().map(|plate| plate.image_path));
+    }
+
+    /// Plate files the history no longer points at. Other state may still
+    /// show them, so the caller checks before deleting any.
+    pub fn take_released(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.released)
+    }
+
+    /// Hands plates back that couldn't be deleted yet, to be offered again
+    /// by the next `take_released`
+    pub fn keep_released(&mut self, paths: Vec<String>) {
+        self.released.extend(paths);
+    }
+
+    /// Plate files of the entries that still cache theirs
+    pub fn cached_plates(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.results.as_ref())
+            .flatten()
+            .map(|plate| plate.image_path.as_str())
+    }
+
+    fn touch(&mut self, index: usize) {
+        self.tick += 1;
+        self.entries[index].last_used = self.tick;
+    }
+
+    /// Drops the plates of all but the `CACHED_RESULTS` most recently used
+    /// entries, releasing their files
+    fn evict(&mut self) {
+        let mut cached: Vec<(u64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.results.is_some())
+            .map(|(i, entry)| (entry.last_used, i))
+            .collect();
+        if cached.len() <= CACHED_RESULTS {
+            return;
+        }
+        cached.sort_unstable_by(|a, b| b.cmp(a));
+        let evicted: Vec<Vec<ProcessResult>> = cached[CACHED_RESULTS..]
+            .iter()
+            .filter_map(|&(_, i)| self.entries[i].results.take())
+            .collect();
+        self.release(evicted);
+    }
+
+    /// Records a finished run. Entries after the current one (the redo side)
+    /// are discarded, as in any editor.
+    pub fn push(&mut self, settings: ProcessSettings, results: Vec<ProcessResult>) -> u64 {
+        let mut dropped = Vec::new();
+        if let Some(current) = self.current {
+            dropped.extend(self.entries.drain(current + 1..));
+        }
+        if self.entries.len() >= MAX_HISTORY {
+            dropped.push(self.entries.remove(0));
+        }
+        self.release(dropped.into_iter().filter_map(|entry| entry.results));
+
+        self.next_id += 1;
+        self.entries.push(HistoryEntry {
+            id: self.next_id,
+            settings,
+            created_at: chrono::Local::now().to_rfc3339(),
+            results: Some(results),
+            last_used: 0,
+        });
+        let index = self.entries.len() - 1;
+        self.current = Some(index);
+        self.touch(index);
+        self.evict();
+        self.next_id
+    }
+
+    fn index_of(&self, id: u64) -> Result<usize, Error> {
+        self.entries
+            .iter()
+            .position(|entry| entry.id == id)
+            .ok_or(Error::HistoryEntryNotFound(id))
+    }
+
+    /// Entry before the current one
+    pub fn previous(&self) -> Result<u64, Error> {
+        match self.current {
+            Some(current) if current > 0 => Ok(self.entries[current - 1].id),
+            _ => Err(Error::NothingToUndo),
+        }
+    }
+
+    /// Entry after the current one
+    pub fn next(&self) -> Result<u64, Error> {
+        match self.current {
+            Some(current) if current + 1 < self.entries.len() => Ok(self.entries[current + 1].id),
+            _ => Err(Error::NothingToRedo),
+        }
+    }
+
+    /// Makes `id` the current entry and returns its settings, with its plates
+    /// when they are still cached
+    pub fn select(
+        &mut self,
+        id: u64,
+    ) -> Result<(ProcessSettings, Option<Vec<ProcessResult>>), Error> {
+        let index = self.index_of(id)?;
+        self.current = Some(index);
+        self.touch(index);
+        let entry = &self.entries[index];
+        Ok((entry.settings.clone(), entry.results.clone()))
+    }
+
+    /// Keeps the plates of an entry that had to be reprocessed
+    pub fn cache(&mut self, id: u64, results: Vec<ProcessResult>) {
+        if let Ok(index) = self.index_of(id) {
+            let replaced = self.entries[index].results.replace(results);
+            self.release(replaced);
+            self.touch(index);
+            self.evict();
+        }
+    }
+
+    pub fn listing(&self) -> HistoryListing {
+        HistoryListing {
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| HistoryItem {
+                    id: entry.id,
+                    settings: entry.settings.clone(),
+                    created_at: entry.created_at.clone(),
+                    cached: entry.results.is_some(),
+                })
+                .collect(),
+            current: self.current.map(|i| self.entries[i].id),
+            can_undo: self.previous().is_ok(),
+            can_redo: self.next().is_ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::plate::PlateDepth;
+
+    fn settings() -> ProcessSettings {
+        ProcessSettings {
+            effect: None,
+            filter: None,
+            colors: None,
+            output: None,
+            trapping: None,
+            knockout: None,
+        }
+    }
+
+    fn plates(run: u64) -> Vec<ProcessResult> {
+        ["cyan", "magenta"]
+            .iter()
+            .map(|channel| ProcessResult {
+                channel: channel.to_string(),
+                image_path: format!("run{}_{}.png", run, channel),
+                depth: PlateDepth::Gray8,
+                dpi: None,
+            })
+            .collect()
+    }
+
+    fn paths(results: &[ProcessResult]) -> Vec<String> {
+        results.iter().map(|p| p.image_path.clone()).collect()
+    }
+
+    #[test]
+    fn pushing_after_an_undo_drops_the_redo_side() {
+        let mut history = History::default();
+        let ids: Vec<u64> = (1..=3)
+            .map(|run| history.push(settings(), plates(run)))
+            .collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert!(matches!(history.next(), Err(Error::NothingToRedo)));
+
+        let (_, cached) = history.select(history.previous().unwrap()).unwrap();
+        assert_eq!(paths(&cached.unwrap()), paths(&plates(2)));
+        assert_eq!(history.next().unwrap(), 3);
+        assert!(history.take_released().is_empty());
+
+        let id = history.push(settings(), plates(4));
+        let listing = history.listing();
+        let listed: Vec<u64> = listing.entries.iter().map(|e| e.id).collect();
+        assert_eq!(listed, [1, 2, id]);
+        assert_eq!(listing.current, Some(id));
+        assert!(listing.can_undo && !listing.can_redo);
+        assert_eq!(history.take_released(), paths(&plates(3)));
+        assert!(matches!(
+            history.select(3),
+            Err(Error::HistoryEntryNotFound(3))
+        ));
+    }
+
+    #[test]
+    fn only_recently_used_plates_are_kept() {
+        let mut history = History::default();
+        for run in 1..=CACHED_RESULTS as u64 {
+            history.push(settings(), plates(run));
+        }
+        // Showing the first entry again keeps it ahead of the second; going
+        // back to the last keeps the entries in between
+        history.select(1).unwrap();
+        history.select(CACHED_RESULTS as u64).unwrap();
+        history.push(settings(), plates(6));
+
+        assert_eq!(history.take_released(), paths(&plates(2)));
+        let (_, cached) = history.select(2).unwrap();
+        assert!(cached.is_none());
+        assert!(history.select(1).unwrap().1.is_some());
+
+        // Reprocessed plates are cached again, pushing out the oldest used
+        history.cache(2, plates(7));
+        assert_eq!(history.take_released(), paths(&plates(3)));
+        assert_eq!(history.cached_plates().count(), CACHED_RESULTS * 2);
+    }
+
+    #[test]
+    fn dropped_and_cleared_entries_release_their_plates() {
+        let mut history = History::default();
+        for run in 1..=MAX_HISTORY as u64 + 1 {
+            history.push(settings(), plates(run));
+        }
+        assert_eq!(history.listing().entries.len(), MAX_HISTORY);
+        assert_eq!(history.listing().entries[0].id, 2);
+        let released = history.take_released();
+        assert_eq!(released.len(), (MAX_HISTORY + 1 - CACHED_RESULTS) * 2);
+
+        history.clear();
+        let mut released = history.take_released();
+        released.sort();
+        let mut expected: Vec<String> = (MAX_HISTORY as u64 + 2 - CACHED_RESULTS as u64
+            ..=MAX_HISTORY as u64 + 1)
+            .flat_map(|run| paths(&plates(run)))
+            .collect();
+        expected.sort();
+        assert_eq!(released, expected);
+        assert!(history.listing().entries.is_empty());
+    }
+}
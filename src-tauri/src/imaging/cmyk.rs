@@ -1,11 +1,12 @@
-use image::{DynamicImage, RgbImage};
+use super::filters::ImageFilter;
+use super::tiles::{tile_rows, Tile};
+use image::{DynamicImage, GenericImageView, GrayImage};
 use rayon::prelude::*;
 
 /// Represents a set of CMYK channels using bitflags
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct CmykChannels(u8);
 
-#[allow(dead_code)]
 impl CmykChannels {
     pub const CYAN: CmykChannels = CmykChannels(0b0001);
     pub const MAGENTA: CmykChannels = CmykChannels(0b0010);
@@ -56,80 +57,100 @@ pub fn rgb_to_cmyk(r: u8, g: u8, b: u8) -> (u8, u8, u8, u8) {
     (c, m, y, k)
 }
 
-fn split_rgb_to_cmyk_channels(img: &DynamicImage) -> Option<Vec<RgbImage>> {
-    let rgb_img = img.to_rgb8();
-    let (width, height) = rgb_img.dimensions();
+/// Separates the image into C, M, Y and K plates tile by tile. Each tile is
+/// cropped from the source with the filter's halo of extra rows and filtered
+/// before separation, so neighbourhood filters never need a full-size
+/// intermediate copy of the image.
+fn split_rgb_to_cmyk_channels(
+    img: &DynamicImage,
+    filter: Option<&dyn ImageFilter>,
+) -> Option<Vec<GrayImage>> {
+    let (width, height) = img.dimensions();
+    let halo = filter.map_or(0, |f| f.halo());
+    let rows = tile_rows(filter.map_or(1, |f| f.alignment()));
+    let tile_len = (width * rows) as usize;
     let num_pixels = (width * height) as usize;
+    if num_pixels == 0 {
+        return None;
+    }
 
-    // Pre-allocate pixel data for each channel
-    let mut c_data = vec![0u8; num_pixels * 3];
-    let mut m_data = vec![0u8; num_pixels * 3];
-    let mut y_data = vec![0u8; num_pixels * 3];
-    let mut k_data = vec![0u8; num_pixels * 3];
+    // One byte per pixel per plate: plates are single channel
+    let mut c_data = vec![0u8; num_pixels];
+    let mut m_data = vec![0u8; num_pixels];
+    let mut y_data = vec![0u8; num_pixels];
+    let mut k_data = vec![0u8; num_pixels];
 
-    // Process pixels in parallel
+    // Process tiles in parallel
     c_data
-        .par_chunks_mut(3)
-        .zip(m_data.par_chunks_mut(3))
-        .zip(y_data.par_chunks_mut(3))
-        .zip(k_data.par_chunks_mut(3))
+        .par_chunks_mut(tile_len)
+        .zip(m_data.par_chunks_mut(tile_len))
+        .zip(y_data.par_chunks_mut(tile_len))
+        .zip(k_data.par_chunks_mut(tile_len))
         .enumerate()
         .for_each(|(i, (((c, m), y), k))| {
-            let x = (i as u32) % width;
-            let y_pos = (i as u32) / width;
-            let pixel = rgb_img.get_pixel(x, y_pos);
-            let (c_val, m_val, y_val, k_val) = rgb_to_cmyk(pixel[0], pixel[1], pixel[2]);
-
-            // Use grayscale (black and white) for all channels
-            // Inverting values since in CMYK, 0 means no ink (white) and 255 means full ink (black)
-            let c_gray = 255 - c_val;
-            let m_gray = 255 - m_val;
-            let y_gray = 255 - y_val;
-            let k_gray = 255 - k_val;
-            // K channel is already correct (255 = black, 0 = white)
-
-            // Set all RGB channels to the same value to create grayscale
-            c.copy_from_slice(&[c_gray, c_gray, c_gray]);
-            m.copy_from_slice(&[m_gray, m_gray, m_gray]);
-            y.copy_from_slice(&[y_gray, y_gray, y_gray]);
-            k.copy_from_slice(&[k_gray, k_gray, k_gray]);
+            let tile = Tile {
+                y: i as u32 * rows,
+                height: (c.len() / width as usize) as u32,
+            };
+            let source = tile.with_halo(halo, height);
+            let region = img.crop_imm(0, source.y, width, source.height);
+            let region = match filter {
+                Some(filter) => filter.apply(&region),
+                None => region,
+            }
+            .to_rgb8();
+            let offset = tile.y - source.y;
+
+            for (j, (((c, m), y), k)) in c
+                .iter_mut()
+                .zip(m.iter_mut())
+                .zip(y.iter_mut())
+                .zip(k.iter_mut())
+                .enumerate()
+            {
+                let x = j as u32 % width;
+                let y_pos = j as u32 / width + offset;
+                let pixel = region.get_pixel(x, y_pos);
+                let (c_val, m_val, y_val, k_val) = rgb_to_cmyk(pixel[0], pixel[1], pixel[2]);
+
+                // Inverting values since in CMYK, 0 means no ink (white) and 255 means full ink (black)
+                *c = 255 - c_val;
+                *m = 255 - m_val;
+                *y = 255 - y_val;
+                *k = 255 - k_val;
+            }
         });
 
     // Construct images from raw pixel data
-    let c_img = RgbImage::from_raw(width, height, c_data)?;
-    let m_img = RgbImage::from_raw(width, height, m_data)?;
-    let y_img = RgbImage::from_raw(width, height, y_data)?;
-    let k_img = RgbImage::from_raw(width, height, k_data)?;
+    let c_img = GrayImage::from_raw(width, height, c_data)?;
+    let m_img = GrayImage::from_raw(width, height, m_data)?;
+    let y_img = GrayImage::from_raw(width, height, y_data)?;
+    let k_img = GrayImage::from_raw(width, height, k_data)?;
 
     Some(vec![c_img, m_img, y_img, k_img])
 }
 
-pub fn split_channels(image: &DynamicImage, channels: CmykChannels) -> Option<Vec<RgbImage>> {
-    let channel_images = split_rgb_to_cmyk_channels(image)?;
+pub fn split_channels(
+    image: &DynamicImage,
+    channels: CmykChannels,
+    filter: Option<&dyn ImageFilter>,
+) -> Option<Vec<GrayImage>> {
+    let channel_images = split_rgb_to_cmyk_channels(image, filter)?;
 
     let channel_flags = [
-        (CmykChannels::CYAN, 0),
-        (CmykChannels::MAGENTA, 1),
-        (CmykChannels::YELLOW, 2),
-        (CmykChannels::BLACK, 3),
+        CmykChannels::CYAN,
+        CmykChannels::MAGENTA,
+        CmykChannels::YELLOW,
+        CmykChannels::BLACK,
     ];
 
-    // Filter to get only the requested channels
-    let active_channels: Vec<_> = channel_flags
-        .iter()
+    // Keep only the requested channels, moving rather than cloning the plates
+    let images = channel_flags
+        .into_iter()
+        .zip(channel_images)
         .filter(|(flag, _)| channels.contains(*flag))
+        .map(|(_, image)| image)
         .collect();
 
-    // Early return if no channels requested
-    if active_channels.is_empty() {
-        return Some(Vec::new());
-    }
-
-    let images: Vec<_> = active_channels
-        .iter()
-        .map(|(_, idx)| channel_images[*idx].clone())
-        .collect();
-
-    // print length of images
     Some(images)
 }
@@ -0,0 +1,177 @@
+//! Renders processed plates back into a picture of the print. Each plate is
+//! tinted with its ink and the tints are multiplied together, the same model
+//! the canvas preview uses, but at full resolution and without the webview's
+//! canvas size limits.
+
+use crate::errors::Error;
+use crate::imaging::colormap::ColorMap;
+use crate::imaging::plate::{load_plate, png_pixel_dims};
+use crate::imaging::processes::ProcessResult;
+use crate::imaging::tiff::write_rgb_tiff;
+use crate::state::ColorInfo;
+use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
+use image::codecs::jpeg::{JpegEncoder, PixelDensity};
+use image::imageops::FilterType;
+use image::{GrayImage, ImageFormat, RgbImage};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::Path;
+
+const JPEG_QUALITY: u8 = 92;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum CompositeFormat {
+    Png,
+    Jpeg,
+    Tiff,
+}
+
+impl CompositeFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompositeFormat::Png => "png",
+            CompositeFormat::Jpeg => "jpg",
+            CompositeFormat::Tiff => "tif",
+        }
+    }
+}
+
+/// Fraction of light each plate value lets through, per RGB channel, for an
+/// ink. 255 (paper) passes everything, 0 (full ink) passes the ink colour.
+fn transmission(hex: &str) -> Result<[[f32; 3]; 256], Error> {
+    let (r, g, b) = ColorMap::hex_to_rgb(hex)?;
+    let ink = [r, g, b].map(|c| c as f32 / 255.0);
+    let mut table = [[1.0; 3]; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        let coverage = 1.0 - value as f32 / 255.0;
+        for (channel, ink) in entry.iter_mut().zip(ink) {
+            *channel = 1.0 - coverage * (1.0 - ink);
+        }
+    }
+    Ok(table)
+}
+
+/// Multiplies the tinted plates together on white paper
+pub fn composite(plates: &[GrayImage], inks: &[&str]) -> Result<RgbImage, Error> {
+    let Some(first) = plates.first() else {
+        return Err(Error::Processing("No plates to composite".to_string()));
+    };
+    let (width, height) = first.dimensions();
+    if plates.iter().any(|p| p.dimensions() != (width, height)) {
+        return Err(Error::Processing(
+            "All plates must be the same size".to_string(),
+        ));
+    }
+
+    let tables = inks
+        .iter()
+        .map(|hex| transmission(hex))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut image = RgbImage::new(width, height);
+    image.par_chunks_mut(3).enumerate().for_each(|(i, pixel)| {
+        let mut light = [1.0f32; 3];
+        for (plate, table) in plates.iter().zip(&tables) {
+            let entry = &table[plate.as_raw()[i] as usize];
+            for (channel, pass) in light.iter_mut().zip(entry) {
+                *channel *= pass;
+            }
+        }
+        for (out, channel) in pixel.iter_mut().zip(light) {
+            *out = (channel * 255.0).round() as u8;
+        }
+    });
+
+    Ok(image)
+}
+
+/// Loads the plates of a job with the hex colour of the ink assigned to
+/// each. Plates without an ink print in black.
+pub fn plates_with_inks<'a>(
+    channels: &[ProcessResult],
+    colors: Option<&'a Vec<ColorInfo>>,
+) -> Result<(Vec<GrayImage>, Vec<&'a str>), Error> {
+    let plates = channels
+        .iter()
+        .map(|channel| load_plate(&channel.image_path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let inks = (0..channels.len())
+        .map(|i| {
+            colors
+                .and_then(|c| c.get(i))
+                .map_or("#000000", |c| c.hex.as_str())
+        })
+        .collect();
+    Ok((plates, inks))
+}
+
+/// Loads the plates of a job and composites them with their assigned inks
+pub fn composite_channels(
+    channels: &[ProcessResult],
+    colors: Option<&Vec<ColorInfo>>,
+) -> Result<RgbImage, Error> {
+    let (plates, inks) = plates_with_inks(channels, colors)?;
+    composite(&plates, &inks)
+}
+
+/// PNG data URL of the composite, scaled down to fit `max_size` pixels on
+/// its longest side when given
+pub fn composite_preview(image: &RgbImage, max_size: Option<u32>) -> Result<String, Error> {
+    let longest = image.width().max(image.height());
+    let scaled;
+    let image = match max_size {
+        Some(max_size) if max_size > 0 && longest > max_size => {
+            let scale = max_size as f32 / longest as f32;
+            let width = ((image.width() as f32 * scale).round() as u32).max(1);
+            let height = ((image.height() as f32 * scale).round() as u32).max(1);
+            scaled = image::imageops::resize(image, width, height, FilterType::Triangle);
+            &scaled
+        }
+        _ => image,
+    };
+
+    let mut buffer = Cursor::new(Vec::new());
+    image
+        .write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| Error::Processing(format!("Failed to encode image: {}", e)))?;
+
+    Ok(format!(
+        "data:image/png;base64,{}",
+        base64_engine.encode(buffer.into_inner())
+    ))
+}
+
+/// Writes the composite, tagging it with the job's resolution when known
+pub fn save_composite(
+    image: &RgbImage,
+    path: &Path,
+    format: CompositeFormat,
+    dpi: Option<f32>,
+) -> Result<(), Error> {
+    let encode_error =
+        |e: &dyn std::fmt::Display| Error::Processing(format!("Failed to write composite: {}", e));
+
+    match format {
+        CompositeFormat::Png => {
+            let writer = BufWriter::new(File::create(path)?);
+            let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_pixel_dims(dpi.map(png_pixel_dims));
+            encoder
+                .write_header()
+                .and_then(|mut writer| writer.write_image_data(image.as_raw()))
+                .map_err(|e| encode_error(&e))
+        }
+        CompositeFormat::Jpeg => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            let mut encoder = JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY);
+            if let Some(dpi) = dpi {
+                let dpi = dpi.round() as u16;
+                encoder.set_pixel_density(PixelDensity::dpi(dpi));
+            }
+            encoder.encode_image(image).map_err(|e| encode_error(&e))
+        }
+        CompositeFormat::Tiff => write_rgb_tiff(path, image, dpi.unwrap_or(72.0)),
+    }
+}
@@ -0,0 +1,164 @@
+//! Cost of printing a job: one master per plate (more on long runs) plus the
+//! ink each drum lays down, worked out from the plates' coverage and the
+//! size they print at.
+
+use crate::errors::Error;
+use crate::imaging::coverage::{coverage_stats, PlateCoverage};
+use crate::imaging::page::PageSetup;
+use crate::imaging::processes::ProcessResult;
+use crate::state::ColorInfo;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CostSettings {
+    pub copies: u32,
+    pub master_cost: f32,
+    /// Copies a master lasts before it has to be remade
+    pub copies_per_master: u32,
+    /// Ink used for a square metre at full coverage
+    pub ink_ml_per_m2: f32,
+    /// Price per millilitre for inks without one in the library
+    pub default_ink_cost_per_ml: f32,
+}
+
+impl Default for CostSettings {
+    fn default() -> Self {
+        Self {
+            copies: 100,
+            master_cost: 0.5,
+            copies_per_master: 4000,
+            ink_ml_per_m2: 18.0,
+            default_ink_cost_per_ml: 0.05,
+        }
+    }
+}
+
+impl CostSettings {
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: &str| {
+            Err(Error::Processing(format!(
+                "Invalid cost settings: {}",
+                message
+            )))
+        };
+        let non_negative = |v: f32| v.is_finite() && v >= 0.0;
+
+        if self.copies == 0 {
+            return invalid("at least one copy is needed");
+        }
+        if self.copies_per_master == 0 {
+            return invalid("a master must last at least one copy");
+        }
+        if !(self.ink_ml_per_m2.is_finite() && self.ink_ml_per_m2 > 0.0) {
+            return invalid("ink use must be positive");
+        }
+        if !non_negative(self.master_cost) || !non_negative(self.default_ink_cost_per_ml) {
+            return invalid("costs can't be negative");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InkEstimate {
+    pub channel: String,
+    pub ink: Option<String>,
+    /// Share of the print area covered, from 0.0 to 1.0
+    pub coverage: f32,
+    pub masters: u32,
+    pub ink_ml: f32,
+    pub ink_cost: f32,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CostEstimate {
+    pub copies: u32,
+    /// Printed area of one plate in square millimetres
+    pub area_mm2: f32,
+    pub inks: Vec<InkEstimate>,
+    pub masters: u32,
+    pub ink_ml: f32,
+    pub master_cost: f32,
+    pub ink_cost: f32,
+    pub total_cost: f32,
+    pub cost_per_copy: f32,
+}
+
+/// Estimates a job from its processed plates. The plates are measured at
+/// the size they would be placed on `page`.
+pub fn estimate_cost(
+    channels: &[ProcessResult],
+    colors: Option<&Vec<ColorInfo>>,
+    page: &PageSetup,
+    settings: &CostSettings,
+) -> Result<CostEstimate, Error> {
+    settings.validate()?;
+    let coverage = coverage_stats(channels, colors)?;
+    estimate_from_coverage(channels, colors, &coverage, page, settings)
+}
+
+/// Same as `estimate_cost`, for callers that have already measured the
+/// plates
+pub fn estimate_from_coverage(
+    channels: &[ProcessResult],
+    colors: Option<&Vec<ColorInfo>>,
+    coverage: &[PlateCoverage],
+    page: &PageSetup,
+    settings: &CostSettings,
+) -> Result<CostEstimate, Error> {
+    settings.validate()?;
+    let Some(first) = channels.first() else {
+        return Err(Error::Processing("No plates to estimate".to_string()));
+    };
+
+    let (width, height) = image::image_dimensions(&first.image_path)?;
+    let placement = page.place(width as usize, height as usize, first.dpi);
+    let area_mm2 = placement.width * placement.height;
+    let masters_per_plate = settings.copies.div_ceil(settings.copies_per_master);
+
+    let inks: Vec<InkEstimate> = coverage
+        .iter()
+        .enumerate()
+        .map(|(i, plate)| {
+            // Blank plates are left off the machine
+            let masters = if plate.mean > 0.0 {
+                masters_per_plate
+            } else {
+                0
+            };
+            let ink_ml =
+                plate.mean * area_mm2 / 1e6 * settings.ink_ml_per_m2 * settings.copies as f32;
+            let cost_per_ml = colors
+                .and_then(|c| c.get(i))
+                .and_then(|c| c.cost_per_ml)
+                .unwrap_or(settings.default_ink_cost_per_ml);
+
+            InkEstimate {
+                channel: plate.channel.clone(),
+                ink: plate.ink.clone(),
+                coverage: plate.mean,
+                masters,
+                ink_ml,
+                ink_cost: ink_ml * cost_per_ml,
+            }
+        })
+        .collect();
+
+    let masters: u32 = inks.iter().map(|ink| ink.masters).sum();
+    let ink_ml = inks.iter().map(|ink| ink.ink_ml).sum();
+    let ink_cost: f32 = inks.iter().map(|ink| ink.ink_cost).sum();
+    let master_cost = masters as f32 * settings.master_cost;
+    let total_cost = master_cost + ink_cost;
+
+    Ok(CostEstimate {
+        copies: settings.copies,
+        area_mm2,
+        inks,
+        masters,
+        ink_ml,
+        master_cost,
+        ink_cost,
+        total_cost,
+        cost_per_copy: total_cost / settings.copies as f32,
+    })
+}
@@ -0,0 +1,230 @@
+//! Ink coverage of the processed plates: how much each drum lays down, and
+//! where the inks pile up. Heavy total area coverage (TAC) on a RISO smears,
+//! sets off onto the next sheet and takes long to dry.
+
+use crate::errors::Error;
+use crate::imaging::composite::composite_preview;
+use crate::imaging::plate::{fit_plate, load_plate, plate_tone, tone_sigma, PlateDepth};
+use crate::imaging::processes::ProcessResult;
+use crate::state::ColorInfo;
+use image::{GrayImage, RgbImage};
+use rayon::prelude::*;
+
+/// TAC, in percent, above which most uncoated stock struggles
+pub const DEFAULT_TAC_LIMIT: f32 = 240.0;
+
+const OVER_LIMIT_COLOR: [u8; 3] = [230, 0, 0];
+// Grey of a pixel right at the limit; paper stays white
+const AT_LIMIT_GREY: f32 = 64.0;
+/// Longest side, in pixels, the heatmap is built at. Summing every plate's
+/// tone at full resolution takes gigabytes for a large job.
+const MAX_HEATMAP_SIZE: u32 = 2048;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PlateCoverage {
+    pub channel: String,
+    /// Name of the ink assigned to the plate, if any
+    pub ink: Option<String>,
+    /// Share of the plate covered in ink, from 0.0 to 1.0
+    pub mean: f32,
+    /// Number of pixels at each coverage from 0% to 100%
+    pub histogram: Vec<u64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CoverageReport {
+    pub plates: Vec<PlateCoverage>,
+    /// Highest total area coverage on the sheet, in percent
+    pub max_tac: f32,
+    pub tac_limit: f32,
+    /// Share of the sheet above the limit
+    pub over_limit_ratio: f32,
+    /// TAC as a PNG data URL, from white (no ink) to dark grey (at the
+    /// limit), with pixels above the limit in red
+    pub heatmap: String,
+}
+
+fn plate_coverage(
+    channel: &ProcessResult,
+    color: Option<&ColorInfo>,
+    plate: &GrayImage,
+) -> PlateCoverage {
+    let mut histogram = vec![0u64; 101];
+    let mut total = 0u64;
+    for &value in plate.as_raw() {
+        let ink = 255 - value as u64;
+        total += ink;
+        histogram[((ink * 100 + 127) / 255) as usize] += 1;
+    }
+    let pixels = plate.as_raw().len().max(1) as f32;
+
+    PlateCoverage {
+        channel: channel.channel.clone(),
+        ink: color.map(|c| c.name.clone()),
+        mean: total as f32 / 255.0 / pixels,
+        histogram,
+    }
+}
+
+/// Per plate coverage of a job's processed plates. Plates are loaded one at
+/// a time, so only one is in memory at once.
+pub fn coverage_stats(
+    channels: &[ProcessResult],
+    colors: Option<&Vec<ColorInfo>>,
+) -> Result<Vec<PlateCoverage>, Error> {
+    channels
+        .iter()
+        .enumerate()
+        .map(|(i, channel)| {
+            let plate = load_plate(&channel.image_path)?;
+            Ok(plate_coverage(
+                channel,
+                colors.and_then(|c| c.get(i)),
+                &plate,
+            ))
+        })
+        .collect()
+}
+
+/// Sums the plates' coverage into a TAC heatmap. Screened plates are read as
+/// the tone they print. Returns the heatmap, the highest TAC in percent and
+/// the number of pixels above `limit`.
+pub fn tac_heatmap(
+    plates: &[GrayImage],
+    depths: &[PlateDepth],
+    dpi: f32,
+    limit: f32,
+) -> Result<(RgbImage, f32, usize), Error> {
+    let Some(first) = plates.first() else {
+        return Err(Error::Processing("No plates to measure".to_string()));
+    };
+    let (width, height) = first.dimensions();
+    if plates.iter().any(|p| p.dimensions() != (width, height)) {
+        return Err(Error::Processing(
+            "All plates must be the same size".to_string(),
+        ));
+    }
+
+    // Summed plate by plate, so only one plate's tone is held at a time
+    let mut total = vec![0.0f32; width as usize * height as usize];
+    for (i, plate) in plates.iter().enumerate() {
+        let screened = depths.get(i) == Some(&PlateDepth::Bilevel);
+        let tone = plate_tone(plate, if screened { tone_sigma(dpi) } else { 0.0 });
+        total
+            .par_iter_mut()
+            .zip(tone)
+            .for_each(|(sum, tone)| *sum += tone);
+    }
+
+    let mut heatmap = RgbImage::new(width, height);
+    let (max_tac, over) = heatmap
+        .par_chunks_mut(3)
+        .zip(total)
+        .map(|(pixel, total)| {
+            let tac = total * 100.0;
+            let color = if tac > limit {
+                OVER_LIMIT_COLOR
+            } else {
+                let grey = (255.0 - (255.0 - AT_LIMIT_GREY) * tac / limit).round() as u8;
+                [grey; 3]
+            };
+            pixel.copy_from_slice(&color);
+            (tac, (tac > limit) as usize)
+        })
+        .reduce(|| (0.0, 0), |a, b| (a.0.max(b.0), a.1 + b.1));
+
+    Ok((heatmap, max_tac, over))
+}
+
+/// Coverage of every plate plus the TAC heatmap, scaled to fit `max_size`
+/// pixels when given. The TAC figures come from the heatmap, so on plates
+/// larger than `MAX_HEATMAP_SIZE` they are averaged over a few pixels.
+pub fn coverage_report(
+    channels: &[ProcessResult],
+    colors: Option<&Vec<ColorInfo>>,
+    tac_limit: Option<f32>,
+    max_size: Option<u32>,
+) -> Result<CoverageReport, Error> {
+    let tac_limit = tac_limit.unwrap_or(DEFAULT_TAC_LIMIT);
+    if !(tac_limit.is_finite() && tac_limit > 0.0) {
+        return Err(Error::Processing(
+            "TAC limit must be a positive percentage".to_string(),
+        ));
+    }
+
+    // Coverage is measured on each plate at full size, then the plate is
+    // scaled down for the heatmap before the next one is loaded
+    let mut stats = Vec::with_capacity(channels.len());
+    let mut plates = Vec::with_capacity(channels.len());
+    let mut scale = 1.0;
+    for (i, channel) in channels.iter().enumerate() {
+        let plate = load_plate(&channel.image_path)?;
+        stats.push(plate_coverage(
+            channel,
+            colors.and_then(|c| c.get(i)),
+            &plate,
+        ));
+        let (plate, plate_scale) = fit_plate(plate, MAX_HEATMAP_SIZE);
+        plates.push(plate);
+        scale = plate_scale;
+    }
+
+    let depths: Vec<PlateDepth> = channels.iter().map(|c| c.depth).collect();
+    let dpi = channels.first().and_then(|c| c.dpi).unwrap_or(72.0) * scale;
+    let (heatmap, max_tac, over) = tac_heatmap(&plates, &depths, dpi, tac_limit)?;
+    let pixels = (heatmap.width() as usize * heatmap.height() as usize).max(1) as f32;
+
+    Ok(CoverageReport {
+        plates: stats,
+        max_tac,
+        tac_limit,
+        over_limit_ratio: over as f32 / pixels,
+        heatmap: composite_preview(&heatmap, max_size)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::plate::save_plate_png;
+    use image::Luma;
+
+    #[test]
+    fn large_plates_are_measured_in_full_and_mapped_scaled() {
+        let dir = tempfile::tempdir().unwrap();
+        // Half of each plate is solid, the rest paper, on the same side
+        let (width, height) = (3000, 40);
+        let plate =
+            GrayImage::from_fn(width, height, |x, _| Luma([if x < 1500 { 0 } else { 255 }]));
+        let channels: Vec<ProcessResult> = (0..3)
+            .map(|i| {
+                let path = dir.path().join(format!("{}.png", i));
+                save_plate_png(&plate, PlateDepth::Gray8, Some(300.0), &path).unwrap();
+                ProcessResult {
+                    channel: format!("plate{}", i),
+                    image_path: path.to_string_lossy().to_string(),
+                    depth: PlateDepth::Gray8,
+                    dpi: Some(300.0),
+                }
+            })
+            .collect();
+
+        let report = coverage_report(&channels, None, None, Some(500)).unwrap();
+        for stats in &report.plates {
+            assert!((stats.mean - 0.5).abs() < 1e-6);
+            assert_eq!(stats.histogram[100], 1500 * 40);
+            assert_eq!(stats.histogram[0], 1500 * 40);
+        }
+        assert!((report.max_tac - 300.0).abs() < 0.5, "{}", report.max_tac);
+        // Only the column the scaled edge falls on is averaged
+        assert!(
+            (report.over_limit_ratio - 0.5).abs() < 0.002,
+            "{}",
+            report.over_limit_ratio
+        );
+        assert_eq!(
+            report.plates.len(),
+            coverage_stats(&channels, None).unwrap().len()
+        );
+    }
+}
@@ -1,7 +1,17 @@
-use image::{DynamicImage, Rgb, RgbImage};
-
-pub trait ImageEffect {
-    fn apply(&self, image: &DynamicImage) -> DynamicImage;
+use super::plate::PlateDepth;
+use super::tiles::tile_rows;
+use image::GrayImage;
+use rayon::prelude::*;
+
+/// Effects work in place on a single-channel plate, where 0 is full ink and
+/// 255 is paper. Point and cell based effects run tile by tile.
+pub trait ImageEffect: Send + Sync {
+    fn apply(&self, plate: &mut GrayImage);
+
+    /// How the plates are stored. Effects that keep tones need eight bits
+    fn depth(&self) -> PlateDepth {
+        PlateDepth::Gray8
+    }
 }
 
 pub struct Dither;
@@ -11,137 +21,142 @@ pub struct Threshold;
 pub struct Original;
 
 impl ImageEffect for Original {
-    fn apply(&self, image: &DynamicImage) -> DynamicImage {
-        image.clone()
-    }
+    fn apply(&self, _plate: &mut GrayImage) {}
 }
 
 impl ImageEffect for Dither {
-    fn apply(&self, image: &DynamicImage) -> DynamicImage {
-        let gray = image.to_luma8();
-        let width = gray.width();
-        let height = gray.height();
+    // Floyd-Steinberg only ever pushes error one row down, so the plate is
+    // streamed row by row with two rows of error instead of a full f32 copy
+    fn apply(&self, plate: &mut GrayImage) {
+        let width = plate.width() as usize;
+        if width == 0 {
+            return;
+        }
 
-        // Buffer for error diffusion (using f32 to handle error distribution)
-        let mut buffer: Vec<f32> = gray.pixels().map(|p| p[0] as f32).collect();
+        let mut current = vec![0f32; width];
+        let mut next = vec![0f32; width];
 
-        for y in 0..height {
+        for row in plate.chunks_exact_mut(width) {
             for x in 0..width {
-                let idx = (y * width + x) as usize;
-                let old_pixel = buffer[idx];
+                let old_pixel = row[x] as f32 + current[x];
                 let new_pixel = if old_pixel > 128.0 { 255.0 } else { 0.0 };
-                buffer[idx] = new_pixel;
+                row[x] = new_pixel as u8;
 
                 let quant_error = old_pixel - new_pixel;
 
                 // Floyd-Steinberg distribution
                 // x+1
                 if x + 1 < width {
-                    buffer[idx + 1] += quant_error * 7.0 / 16.0;
+                    current[x + 1] += quant_error * 7.0 / 16.0;
                 }
                 // x-1, y+1
-                if x > 0 && y + 1 < height {
-                    buffer[idx + width as usize - 1] += quant_error * 3.0 / 16.0;
+                if x > 0 {
+                    next[x - 1] += quant_error * 3.0 / 16.0;
                 }
                 // y+1
-                if y + 1 < height {
-                    buffer[idx + width as usize] += quant_error * 5.0 / 16.0;
-                }
+                next[x] += quant_error * 5.0 / 16.0;
                 // x+1, y+1
-                if x + 1 < width && y + 1 < height {
-                    buffer[idx + width as usize + 1] += quant_error * 1.0 / 16.0;
+                if x + 1 < width {
+                    next[x + 1] += quant_error * 1.0 / 16.0;
                 }
             }
-        }
 
-        let mut output = RgbImage::new(width, height);
-        for y in 0..height {
-            for x in 0..width {
-                let val = buffer[(y * width + x) as usize].clamp(0.0, 255.0) as u8;
-                output.put_pixel(x, y, Rgb([val, val, val]));
-            }
+            std::mem::swap(&mut current, &mut next);
+            next.fill(0.0);
         }
-        DynamicImage::ImageRgb8(output)
+    }
+
+    // Leaves only full ink or paper, so the plates are stored at one bit
+    // per pixel
+    fn depth(&self) -> PlateDepth {
+        PlateDepth::Bilevel
     }
 }
 
+const HALFTONE_CELL_SIZE: u32 = 6;
+
 impl ImageEffect for HalfTone {
-    fn apply(&self, image: &DynamicImage) -> DynamicImage {
-        let gray = image.to_luma8();
-        let width = gray.width();
-        let height = gray.height();
-        let mut output = RgbImage::new(width, height);
-
-        // Initialize with white
-        for p in output.pixels_mut() {
-            *p = Rgb([255, 255, 255]);
+    fn apply(&self, plate: &mut GrayImage) {
+        let width = plate.width();
+        let cell_size = HALFTONE_CELL_SIZE;
+        if width == 0 {
+            return;
         }
 
-        let cell_size = 6;
-
-        for y_cell in 0..(height / cell_size) {
-            for x_cell in 0..(width / cell_size) {
-                let base_x = x_cell * cell_size;
-                let base_y = y_cell * cell_size;
-
-                let mut sum = 0u32;
-                let mut count = 0u32;
-
-                // Calculate average brightness
-                for y in 0..cell_size {
-                    for x in 0..cell_size {
-                        if base_x + x < width && base_y + y < height {
-                            sum += gray.get_pixel(base_x + x, base_y + y)[0] as u32;
-                            count += 1;
+        // Tiles hold whole rows of cells so each one is independent
+        let rows = tile_rows(cell_size);
+        plate
+            .par_chunks_mut((width * rows) as usize)
+            .for_each(|tile| {
+                let height = tile.len() as u32 / width;
+                let source = tile.to_vec();
+                let gray = |x: u32, y: u32| source[(y * width + x) as usize];
+
+                // Initialize with white; partial cells at the edges stay blank
+                tile.fill(255);
+
+                for y_cell in 0..(height / cell_size) {
+                    for x_cell in 0..(width / cell_size) {
+                        let base_x = x_cell * cell_size;
+                        let base_y = y_cell * cell_size;
+
+                        let mut sum = 0u32;
+                        let mut count = 0u32;
+
+                        // Calculate average brightness
+                        for y in 0..cell_size {
+                            for x in 0..cell_size {
+                                sum += gray(base_x + x, base_y + y) as u32;
+                                count += 1;
+                            }
                         }
-                    }
-                }
 
-                if count == 0 {
-                    continue;
-                }
-                let avg = sum / count;
-
-                // Calculate radius based on darkness (inverted brightness)
-                // 0 (black) -> max radius, 255 (white) -> 0 radius
-                let max_radius = (cell_size as f32) / 1.3;
-                let radius = max_radius * (1.0 - (avg as f32 / 255.0));
-                let radius_sq = radius * radius;
-
-                let center_x = base_x as f32 + (cell_size as f32 / 2.0);
-                let center_y = base_y as f32 + (cell_size as f32 / 2.0);
-
-                // Draw dot
-                for y in 0..cell_size {
-                    for x in 0..cell_size {
-                        let px = base_x + x;
-                        let py = base_y + y;
-                        if px < width && py < height {
-                            let dx = px as f32 + 0.5 - center_x;
-                            let dy = py as f32 + 0.5 - center_y;
-                            if dx * dx + dy * dy <= radius_sq {
-                                output.put_pixel(px, py, Rgb([0, 0, 0]));
+                        let avg = sum / count;
+
+                        // Calculate radius based on darkness (inverted brightness)
+                        // 0 (black) -> max radius, 255 (white) -> 0 radius
+                        let max_radius = (cell_size as f32) / 1.3;
+                        let radius = max_radius * (1.0 - (avg as f32 / 255.0));
+                        let radius_sq = radius * radius;
+
+                        let center_x = base_x as f32 + (cell_size as f32 / 2.0);
+                        let center_y = base_y as f32 + (cell_size as f32 / 2.0);
+
+                        // Draw dot
+                        for y in 0..cell_size {
+                            for x in 0..cell_size {
+                                let px = base_x + x;
+                                let py = base_y + y;
+                                let dx = px as f32 + 0.5 - center_x;
+                                let dy = py as f32 + 0.5 - center_y;
+                                if dx * dx + dy * dy <= radius_sq {
+                                    tile[(py * width + px) as usize] = 0;
+                                }
                             }
                         }
                     }
                 }
-            }
-        }
-        DynamicImage::ImageRgb8(output)
+            });
+    }
+
+    fn depth(&self) -> PlateDepth {
+        PlateDepth::Bilevel
     }
 }
 
 impl ImageEffect for Threshold {
-    fn apply(&self, image: &DynamicImage) -> DynamicImage {
-        let gray = image.to_luma8();
-        let mut output = RgbImage::new(gray.width(), gray.height());
+    fn apply(&self, plate: &mut GrayImage) {
         let threshold = 128;
 
-        for (x, y, pixel) in gray.enumerate_pixels() {
-            let val = if pixel[0] > threshold { 255 } else { 0 };
-            output.put_pixel(x, y, Rgb([val, val, val]));
-        }
-        DynamicImage::ImageRgb8(output)
+        plate.par_chunks_mut(4096).for_each(|tile| {
+            for pixel in tile {
+                *pixel = if *pixel > threshold { 255 } else { 0 };
+            }
+        });
+    }
+
+    fn depth(&self) -> PlateDepth {
+        PlateDepth::Bilevel
     }
 }
 
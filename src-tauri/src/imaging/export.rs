@@ -1,45 +1,291 @@
 use crate::errors::Error;
+use crate::imaging::job_sheet::write_job_sheet;
+use crate::imaging::marks::{add_marks_to_plate, color_bar, layout_marks, Mark, SlugInfo};
+use crate::imaging::page::Placement;
+use crate::imaging::plate::{load_plate, save_plate_png, PlateDepth};
 use crate::imaging::processes::ProcessResult;
+use crate::imaging::psd::save_channels_to_psd;
+use crate::imaging::spot_pdf::save_channels_to_spot_pdf;
+use crate::imaging::tiff::{write_tiff, TiffPage};
 use crate::state::ColorInfo;
-use ::image::open;
+use crate::state::ExportSettings;
+use ::image::GrayImage;
 use printpdf::*;
 use std::fs;
 use std::path::Path;
 
+/// Formats plates can be exported in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ExportFormat {
+    #[default]
+    Pdf,
+    Png,
+    SpotPdf,
+    Tiff,
+    Psd,
+}
+
+impl ExportFormat {
+    /// Parses the index the frontend's export menu sends
+    pub fn from_export_type(export_type: &str) -> Result<Self, Error> {
+        match export_type {
+            "0" => Ok(Self::Pdf),
+            "1" => Ok(Self::Png),
+            "2" => Ok(Self::SpotPdf),
+            "3" => Ok(Self::Tiff),
+            "4" => Ok(Self::Psd),
+            _ => Err(Error::Processing("Invalid export type".to_string())),
+        }
+    }
+}
+
+/// Exports plates into `export_path` as `format`, followed by the job sheet
+/// when it is enabled
+pub fn export_plates(
+    format: ExportFormat,
+    channels: &[ProcessResult],
+    export_path: &str,
+    base_filename: &str,
+    colors: Option<&Vec<ColorInfo>>,
+    settings: &ExportSettings,
+) -> Result<(), Error> {
+    let (dir, base) = (export_path, base_filename);
+    match format {
+        ExportFormat::Pdf => save_channels_to_pdf(channels, dir, base, colors, settings),
+        ExportFormat::Png => save_channels_to_disk(channels, dir, base, colors, settings),
+        ExportFormat::SpotPdf => save_channels_to_spot_pdf(channels, dir, base, colors, settings),
+        ExportFormat::Tiff => save_channels_to_tiff(channels, dir, base, colors, settings),
+        ExportFormat::Psd => save_channels_to_psd(channels, dir, base, colors),
+    }?;
+    if settings.job_sheet.enabled {
+        write_job_sheet(channels, dir, base, colors, settings)?;
+    }
+    Ok(())
+}
+
+/// Loads a plate for a raster export, adding printer's marks around it when
+/// any are enabled
+fn load_plate_with_marks(
+    channels: &[ProcessResult],
+    index: usize,
+    ink_name: &str,
+    base_filename: &str,
+    settings: &ExportSettings,
+) -> Result<GrayImage, Error> {
+    let channel = &channels[index];
+    let plate = load_plate(&channel.image_path)?;
+    let marks = &settings.marks;
+    if !marks.any() {
+        return Ok(plate);
+    }
+
+    // Marks are sized for the plate as it prints on the PDF page
+    let (width, height) = plate.dimensions();
+    let dpi = settings
+        .page
+        .place(width as usize, height as usize, channel.dpi)
+        .dpi;
+    let width_mm = width as f32 / dpi * 25.4;
+    let height_mm = height as f32 / dpi * 25.4;
+
+    let slug = SlugInfo::new(base_filename, ink_name, Some(index), channels.len());
+    let mut layout = layout_marks(marks, width_mm, height_mm, &slug);
+    layout.extend(color_bar(marks, height_mm, index));
+
+    let screened = channel.depth == PlateDepth::Bilevel;
+    Ok(add_marks_to_plate(&plate, &layout, marks, dpi, screened))
+}
+
+/// File name for one exported plate: `{base}_{channel}_{Ink_Name}.{extension}`
+fn plate_filename(
+    base_filename: &str,
+    channel: &ProcessResult,
+    color: Option<&ColorInfo>,
+    extension: &str,
+) -> String {
+    // Get the color name for this channel if available
+    let color_suffix = color
+        .map(|color_info| format!("_{}", color_info.name.replace(" ", "_")))
+        .unwrap_or_default();
+
+    format!(
+        "{}_{}{}.{}",
+        base_filename, channel.channel, color_suffix, extension
+    )
+}
+
 pub fn save_channels_to_disk(
     channels: &[ProcessResult],
     export_path: &str,
     base_filename: &str,
     colors: Option<&Vec<ColorInfo>>,
+    settings: &ExportSettings,
+) -> Result<(), Error> {
+    let export_dir = Path::new(export_path);
+    if !export_dir.exists() {
+        fs::create_dir_all(export_dir)?;
+    }
+
+    for (i, channel) in channels.iter().enumerate() {
+        let color = colors.and_then(|c| c.get(i));
+        let ink_name = color.map_or(channel.channel.as_str(), |c| c.name.as_str());
+        let plate = load_plate_with_marks(channels, i, ink_name, base_filename, settings)?;
+
+        let save_path = export_dir.join(plate_filename(base_filename, channel, color, "png"));
+        save_plate_png(&plate, channel.depth, channel.dpi, &save_path)?;
+    }
+
+    Ok(())
+}
+
+/// Writes each plate as a grayscale TIFF, or all of them as pages of one
+/// `{base}.tif` when `settings.tiff.multi_page` is set. Plates without a job
+/// DPI are tagged at 72 DPI.
+pub fn save_channels_to_tiff(
+    channels: &[ProcessResult],
+    export_path: &str,
+    base_filename: &str,
+    colors: Option<&Vec<ColorInfo>>,
+    settings: &ExportSettings,
 ) -> Result<(), Error> {
     let export_dir = Path::new(export_path);
     if !export_dir.exists() {
         fs::create_dir_all(export_dir)?;
     }
 
+    let mut plates = vec![];
     for (i, channel) in channels.iter().enumerate() {
-        let img = open(&channel.image_path).map_err(|e| Error::Processing(e.to_string()))?;
-
-        // Get the color name for this channel if available
-        let color_suffix = colors
-            .and_then(|c| c.get(i))
-            .map(|color_info| format!("_{}", color_info.name.replace(" ", "_")))
-            .unwrap_or_default();
-
-        let filename = format!("{}_{}{}.png", base_filename, channel.channel, color_suffix);
-        let save_path = export_dir.join(filename);
-        img.save(&save_path)
-            .map_err(|e| Error::Processing(e.to_string()))?;
+        let color = colors.and_then(|c| c.get(i));
+        let ink_name = color.map_or(channel.channel.as_str(), |c| c.name.as_str());
+        let plate = load_plate_with_marks(channels, i, ink_name, base_filename, settings)?;
+        plates.push((plate, ink_name));
+    }
+
+    let pages: Vec<TiffPage> = plates
+        .iter()
+        .zip(channels)
+        .map(|((plate, ink_name), channel)| TiffPage {
+            plate,
+            depth: channel.depth,
+            dpi: channel.dpi.unwrap_or(72.0),
+            name: ink_name,
+        })
+        .collect();
+    let compression = settings.tiff.compression;
+
+    if settings.tiff.multi_page {
+        let save_path = export_dir.join(format!("{}.tif", base_filename));
+        return write_tiff(&save_path, &pages, compression);
+    }
+
+    for (i, page) in pages.iter().enumerate() {
+        let color = colors.and_then(|c| c.get(i));
+        let filename = plate_filename(base_filename, &channels[i], color, "tif");
+        write_tiff(
+            &export_dir.join(filename),
+            std::slice::from_ref(page),
+            compression,
+        )?;
     }
 
     Ok(())
 }
 
+// Distance of the channel label's baseline above the top margin line, and
+// the smallest top margin that leaves room for it
+const LABEL_OFFSET_MM: f32 = 5.0;
+const LABEL_MIN_MARGIN_MM: f32 = 10.0;
+
+fn grey(tint: f32) -> Color {
+    Color::Greyscale(Greyscale::new(1.0 - tint, None))
+}
+
+/// Drawing operations for printer's marks around an image placed on the page
+fn mark_ops(marks: &[Mark], placement: &Placement, line_weight_pt: f32) -> Vec<Op> {
+    if marks.is_empty() {
+        return vec![];
+    }
+
+    let point = |(x, y): (f32, f32)| LinePoint {
+        p: Point {
+            x: Mm(placement.x + x).into_pt(),
+            y: Mm(placement.y + y).into_pt(),
+        },
+        bezier: false,
+    };
+
+    let mut ops = vec![
+        Op::SaveGraphicsState,
+        Op::SetOutlineThickness {
+            pt: Pt(line_weight_pt),
+        },
+        Op::SetOutlineColor { col: grey(1.0) },
+    ];
+
+    for mark in marks {
+        match mark {
+            Mark::Path { points, closed } => ops.push(Op::DrawLine {
+                line: Line {
+                    points: points.iter().copied().map(point).collect(),
+                    is_closed: *closed,
+                },
+            }),
+            Mark::Patch {
+                x,
+                y,
+                width,
+                height,
+                tint,
+            } => ops.extend([
+                Op::SetFillColor { col: grey(*tint) },
+                Op::DrawPolygon {
+                    polygon: Polygon {
+                        rings: vec![PolygonRing {
+                            points: [
+                                (*x, *y),
+                                (x + width, *y),
+                                (x + width, y + height),
+                                (*x, y + height),
+                            ]
+                            .into_iter()
+                            .map(point)
+                            .collect(),
+                        }],
+                        mode: PaintMode::Fill,
+                        winding_order: WindingOrder::NonZero,
+                    },
+                },
+            ]),
+            Mark::Text { x, y, size, text } => ops.extend([
+                Op::SetFillColor { col: grey(1.0) },
+                Op::StartTextSection,
+                Op::SetTextCursor {
+                    pos: point((*x, *y)).p,
+                },
+                Op::SetFontSizeBuiltinFont {
+                    // Helvetica's cap height is about 0.72 of its size
+                    size: Mm(size / 0.72).into_pt(),
+                    font: BuiltinFont::Helvetica,
+                },
+                Op::WriteTextBuiltinFont {
+                    items: vec![TextItem::Text(text.clone())],
+                    font: BuiltinFont::Helvetica,
+                },
+                Op::EndTextSection,
+            ]),
+        }
+    }
+
+    ops.push(Op::RestoreGraphicsState);
+    ops
+}
+
 pub fn save_channels_to_pdf(
     channels: &[ProcessResult],
     export_path: &str,
     base_filename: &str,
     colors: Option<&Vec<ColorInfo>>,
+    settings: &ExportSettings,
 ) -> Result<(), Error> {
     let export_dir = Path::new(export_path);
     if !export_dir.exists() {
@@ -49,10 +295,18 @@ pub fn save_channels_to_pdf(
     let pdf_filename = format!("{}.pdf", base_filename);
     let pdf_path = export_dir.join(pdf_filename);
 
-    // Page dimensions (A4)
-    let page_width = Mm(210.0);
-    let page_height = Mm(297.0);
-    let margin = Mm(20.0);
+    let page = &settings.page;
+    page.validate()?;
+    let (media_width, media_height) = page.media_size();
+    let (trim_width, trim_height) = page.trim_size();
+    // printpdf writes a Rect's width and height as the upper right corner,
+    // so they hold the far corner of the trim here
+    let trim_box = Rect {
+        x: Mm(page.bleed_mm).into_pt(),
+        y: Mm(page.bleed_mm).into_pt(),
+        width: Mm(page.bleed_mm + trim_width).into_pt(),
+        height: Mm(page.bleed_mm + trim_height).into_pt(),
+    };
 
     // Create a new PDF document
     let mut doc = PdfDocument::new(base_filename);
@@ -62,73 +316,77 @@ pub fn save_channels_to_pdf(
     // Helper function to add an image page
     let add_image_page = |doc: &mut PdfDocument,
                           raw_image: &RawImage,
+                          placement: Placement,
                           label_text: String,
+                          marks: &[Mark],
                           pages: &mut Vec<PdfPage>| {
         let image_id = doc.add_image(raw_image);
 
-        let available_width = page_width.0 - 2.0 * margin.0;
-        let available_height = page_height.0 - 2.0 * margin.0 - 15.0;
-
-        let img_width_px = raw_image.width as f32;
-        let img_height_px = raw_image.height as f32;
-
-        let dpi_for_width = img_width_px / available_width * 25.4;
-        let dpi_for_height = img_height_px / available_height * 25.4;
-        let target_dpi = dpi_for_width.max(dpi_for_height).max(72.0);
-
-        let final_width_mm = img_width_px / target_dpi * 25.4;
-        let final_height_mm = img_height_px / target_dpi * 25.4;
-
-        let x_offset = margin.0 + (available_width - final_width_mm) / 2.0;
-        let y_offset = margin.0 + (available_height - final_height_mm) / 2.0;
+        let mut ops = vec![Op::UseXobject {
+            id: image_id,
+            transform: XObjectTransform {
+                translate_x: Some(Mm(placement.x).into_pt()),
+                translate_y: Some(Mm(placement.y).into_pt()),
+                dpi: Some(placement.dpi),
+                ..Default::default()
+            },
+        }];
 
-        let ops = vec![
-            Op::UseXobject {
-                id: image_id,
-                transform: XObjectTransform {
-                    translate_x: Some(Pt(x_offset * 2.834645669)),
-                    translate_y: Some(Pt(y_offset * 2.834645669)),
-                    dpi: Some(target_dpi),
-                    ..Default::default()
+        // The label sits in the top margin when there is room for it
+        if page.margins.top >= LABEL_MIN_MARGIN_MM {
+            let top = page.bleed_mm + trim_height - page.margins.top;
+            ops.extend([
+                Op::StartTextSection,
+                Op::SetTextCursor {
+                    pos: Point {
+                        x: Mm(page.bleed_mm + page.margins.left).into_pt(),
+                        y: Mm(top + LABEL_OFFSET_MM).into_pt(),
+                    },
                 },
-            },
-            Op::StartTextSection,
-            Op::SetTextCursor {
-                pos: Point {
-                    x: Pt(margin.0 * 2.834645669),
-                    y: Pt((page_height.0 - margin.0 + 5.0) * 2.834645669),
+                Op::SetFontSizeBuiltinFont {
+                    size: Pt(14.0),
+                    font: BuiltinFont::Helvetica,
                 },
-            },
-            Op::SetFontSizeBuiltinFont {
-                size: Pt(14.0),
-                font: BuiltinFont::Helvetica,
-            },
-            Op::WriteTextBuiltinFont {
-                items: vec![TextItem::Text(label_text)],
-                font: BuiltinFont::Helvetica,
-            },
-            Op::EndTextSection,
-        ];
+                Op::WriteTextBuiltinFont {
+                    items: vec![TextItem::Text(label_text)],
+                    font: BuiltinFont::Helvetica,
+                },
+                Op::EndTextSection,
+            ]);
+        }
+        ops.extend(mark_ops(marks, &placement, settings.marks.line_weight_pt));
 
-        pages.push(PdfPage::new(page_width, page_height, ops));
+        let mut pdf_page = PdfPage::new(Mm(media_width), Mm(media_height), ops);
+        pdf_page.trim_box = trim_box.clone();
+        pages.push(pdf_page);
     };
 
     // Add pages for each channel
     for (i, channel) in channels.iter().enumerate() {
-        let img_bytes =
-            fs::read(&channel.image_path).map_err(|e| Error::Processing(e.to_string()))?;
-
-        let raw_image = RawImage::decode_from_bytes(&img_bytes, &mut warnings)
-            .map_err(|e| Error::Processing(e))?;
+        // Plates go in as DeviceGray images, one byte per pixel
+        let plate = load_plate(&channel.image_path)?;
+        let raw_image = RawImage {
+            width: plate.width() as usize,
+            height: plate.height() as usize,
+            pixels: RawImageData::U8(plate.into_raw()),
+            data_format: RawImageFormat::R8,
+            tag: Vec::new(),
+        };
 
         // Get color name for this channel (use the RISO color name from UI)
-        let color_info = colors
-            .and_then(|c| c.get(i))
-            .map(|c| format!(" - {}", c.name))
-            .unwrap_or_default();
+        let color = colors.and_then(|c| c.get(i));
+        let color_info = color.map(|c| format!(" - {}", c.name)).unwrap_or_default();
         let label_text = format!("Channel: {}{}", channel.channel, color_info);
 
-        add_image_page(&mut doc, &raw_image, label_text, &mut pages);
+        let placement = page.place(raw_image.width, raw_image.height, channel.dpi);
+        let ink_name = color.map_or(channel.channel.as_str(), |c| c.name.as_str());
+        let slug = SlugInfo::new(base_filename, ink_name, Some(i), channels.len());
+        let mut marks = layout_marks(&settings.marks, placement.width, placement.height, &slug);
+        marks.extend(color_bar(&settings.marks, placement.height, i));
+
+        add_image_page(
+            &mut doc, &raw_image, placement, label_text, &marks, &mut pages,
+        );
     }
 
     // Add all pages to the document
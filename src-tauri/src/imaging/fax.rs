@@ -0,0 +1,387 @@
+//! CCITT Group 4 (T.6) encoder for screened plates. Each row is coded
+//! against the row above it, which shrinks 1-bit halftones and dithers far
+//! more than general purpose compression. Pixels below 128 are black (ink).
+
+use image::GrayImage;
+
+/// Code bits and their length, most significant bit first
+type Code = (u16, u8);
+
+const PASS: Code = (0b0001, 4);
+const HORIZONTAL: Code = (0b001, 3);
+// Vertical mode codes for a1 - b1 from -3 to 3
+const VERTICAL: [Code; 7] = [
+    (0b0000010, 7),
+    (0b000010, 6),
+    (0b010, 3),
+    (0b1, 1),
+    (0b011, 3),
+    (0b000011, 6),
+    (0b0000011, 7),
+];
+const EOL: Code = (0b000000000001, 12);
+
+// Run lengths 0 to 63
+const WHITE_TERMINATING: [Code; 64] = [
+    (0b00110101, 8),
+    (0b000111, 6),
+    (0b0111, 4),
+    (0b1000, 4),
+    (0b1011, 4),
+    (0b1100, 4),
+    (0b1110, 4),
+    (0b1111, 4),
+    (0b10011, 5),
+    (0b10100, 5),
+    (0b00111, 5),
+    (0b01000, 5),
+    (0b001000, 6),
+    (0b000011, 6),
+    (0b110100, 6),
+    (0b110101, 6),
+    (0b101010, 6),
+    (0b101011, 6),
+    (0b0100111, 7),
+    (0b0001100, 7),
+    (0b0001000, 7),
+    (0b0010111, 7),
+    (0b0000011, 7),
+    (0b0000100, 7),
+    (0b0101000, 7),
+    (0b0101011, 7),
+    (0b0010011, 7),
+    (0b0100100, 7),
+    (0b0011000, 7),
+    (0b00000010, 8),
+    (0b00000011, 8),
+    (0b00011010, 8),
+    (0b00011011, 8),
+    (0b00010010, 8),
+    (0b00010011, 8),
+    (0b00010100, 8),
+    (0b00010101, 8),
+    (0b00010110, 8),
+    (0b00010111, 8),
+    (0b00101000, 8),
+    (0b00101001, 8),
+    (0b00101010, 8),
+    (0b00101011, 8),
+    (0b00101100, 8),
+    (0b00101101, 8),
+    (0b00000100, 8),
+    (0b00000101, 8),
+    (0b00001010, 8),
+    (0b00001011, 8),
+    (0b01010010, 8),
+    (0b01010011, 8),
+    (0b01010100, 8),
+    (0b01010101, 8),
+    (0b00100100, 8),
+    (0b00100101, 8),
+    (0b01011000, 8),
+    (0b01011001, 8),
+    (0b01011010, 8),
+    (0b01011011, 8),
+    (0b01001010, 8),
+    (0b01001011, 8),
+    (0b00110010, 8),
+    (0b00110011, 8),
+    (0b00110100, 8),
+];
+
+// Run lengths 64 to 1728 in steps of 64
+const WHITE_MAKEUP: [Code; 27] = [
+    (0b11011, 5),
+    (0b10010, 5),
+    (0b010111, 6),
+    (0b0110111, 7),
+    (0b00110110, 8),
+    (0b00110111, 8),
+    (0b01100100, 8),
+    (0b01100101, 8),
+    (0b01101000, 8),
+    (0b01100111, 8),
+    (0b011001100, 9),
+    (0b011001101, 9),
+    (0b011010010, 9),
+    (0b011010011, 9),
+    (0b011010100, 9),
+    (0b011010101, 9),
+    (0b011010110, 9),
+    (0b011010111, 9),
+    (0b011011000, 9),
+    (0b011011001, 9),
+    (0b011011010, 9),
+    (0b011011011, 9),
+    (0b010011000, 9),
+    (0b010011001, 9),
+    (0b010011010, 9),
+    (0b011000, 6),
+    (0b010011011, 9),
+];
+
+// Run lengths 0 to 63
+const BLACK_TERMINATING: [Code; 64] = [
+    (0b0000110111, 10),
+    (0b010, 3),
+    (0b11, 2),
+    (0b10, 2),
+    (0b011, 3),
+    (0b0011, 4),
+    (0b0010, 4),
+    (0b00011, 5),
+    (0b000101, 6),
+    (0b000100, 6),
+    (0b0000100, 7),
+    (0b0000101, 7),
+    (0b0000111, 7),
+    (0b00000100, 8),
+    (0b00000111, 8),
+    (0b000011000, 9),
+    (0b0000010111, 10),
+    (0b0000011000, 10),
+    (0b0000001000, 10),
+    (0b00001100111, 11),
+    (0b00001101000, 11),
+    (0b00001101100, 11),
+    (0b00000110111, 11),
+    (0b00000101000, 11),
+    (0b00000010111, 11),
+    (0b00000011000, 11),
+    (0b000011001010, 12),
+    (0b000011001011, 12),
+    (0b000011001100, 12),
+    (0b000011001101, 12),
+    (0b000001101000, 12),
+    (0b000001101001, 12),
+    (0b000001101010, 12),
+    (0b000001101011, 12),
+    (0b000011010010, 12),
+    (0b000011010011, 12),
+    (0b000011010100, 12),
+    (0b000011010101, 12),
+    (0b000011010110, 12),
+    (0b000011010111, 12),
+    (0b000001101100, 12),
+    (0b000001101101, 12),
+    (0b000011011010, 12),
+    (0b000011011011, 12),
+    (0b000001010100, 12),
+    (0b000001010101, 12),
+    (0b000001010110, 12),
+    (0b000001010111, 12),
+    (0b000001100100, 12),
+    (0b000001100101, 12),
+    (0b000001010010, 12),
+    (0b000001010011, 12),
+    (0b000000100100, 12),
+    (0b000000110111, 12),
+    (0b000000111000, 12),
+    (0b000000100111, 12),
+    (0b000000101000, 12),
+    (0b000001011000, 12),
+    (0b000001011001, 12),
+    (0b000000101011, 12),
+    (0b000000101100, 12),
+    (0b000001011010, 12),
+    (0b000001100110, 12),
+    (0b000001100111, 12),
+];
+
+// Run lengths 64 to 1728 in steps of 64
+const BLACK_MAKEUP: [Code; 27] = [
+    (0b0000001111, 10),
+    (0b000011001000, 12),
+    (0b000011001001, 12),
+    (0b000001011011, 12),
+    (0b000000110011, 12),
+    (0b000000110100, 12),
+    (0b000000110101, 12),
+    (0b0000001101100, 13),
+    (0b0000001101101, 13),
+    (0b0000001001010, 13),
+    (0b0000001001011, 13),
+    (0b0000001001100, 13),
+    (0b0000001001101, 13),
+    (0b0000001110010, 13),
+    (0b0000001110011, 13),
+    (0b0000001110100, 13),
+    (0b0000001110101, 13),
+    (0b0000001110110, 13),
+    (0b0000001110111, 13),
+    (0b0000001010010, 13),
+    (0b0000001010011, 13),
+    (0b0000001010100, 13),
+    (0b0000001010101, 13),
+    (0b0000001011010, 13),
+    (0b0000001011011, 13),
+    (0b0000001100100, 13),
+    (0b0000001100101, 13),
+];
+
+// Run lengths 1792 to 2560 in steps of 64, shared by both colours
+const EXTENDED_MAKEUP: [Code; 13] = [
+    (0b00000001000, 11),
+    (0b00000001100, 11),
+    (0b00000001101, 11),
+    (0b000000010010, 12),
+    (0b000000010011, 12),
+    (0b000000010100, 12),
+    (0b000000010101, 12),
+    (0b000000010110, 12),
+    (0b000000010111, 12),
+    (0b000000011100, 12),
+    (0b000000011101, 12),
+    (0b000000011110, 12),
+    (0b000000011111, 12),
+];
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    filled: u8,
+}
+
+impl BitWriter {
+    fn put(&mut self, (bits, length): Code) {
+        for i in (0..length).rev() {
+            self.current = (self.current << 1) | ((bits >> i) & 1) as u8;
+            self.filled += 1;
+            if self.filled == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn put_run(&mut self, mut run: usize, black: bool) {
+        let (terminating, makeup) = if black {
+            (&BLACK_TERMINATING, &BLACK_MAKEUP)
+        } else {
+            (&WHITE_TERMINATING, &WHITE_MAKEUP)
+        };
+
+        while run >= 2560 {
+            self.put(EXTENDED_MAKEUP[12]);
+            run -= 2560;
+        }
+        if run >= 1792 {
+            let index = (run - 1792) / 64;
+            self.put(EXTENDED_MAKEUP[index]);
+            run -= 1792 + index * 64;
+        } else if run >= 64 {
+            let index = run / 64 - 1;
+            self.put(makeup[index]);
+            run -= (index + 1) * 64;
+        }
+        self.put(terminating[run]);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.filled > 0 {
+            self.bytes.push(self.current << (8 - self.filled));
+        }
+        self.bytes
+    }
+}
+
+/// Position of the first pixel at or after `from` whose colour differs from
+/// the pixel before it, or the line width if there is none. The pixel
+/// before the start of a line is white.
+fn next_change(line: &[bool], from: usize) -> usize {
+    (from..line.len())
+        .find(|&i| line[i] != (i > 0 && line[i - 1]))
+        .unwrap_or(line.len())
+}
+
+fn encode_line(writer: &mut BitWriter, reference: &[bool], line: &[bool]) {
+    let width = line.len();
+    // a0 starts on an imaginary white pixel just before the line
+    let mut a0: Option<usize> = None;
+    let mut black = false;
+
+    loop {
+        let start = a0.map_or(0, |a0| a0 + 1);
+        let a1 = next_change(line, start);
+        let mut b1 = next_change(reference, start);
+        if b1 < width && reference[b1] == black {
+            b1 = next_change(reference, b1 + 1);
+        }
+        let b2 = next_change(reference, b1 + 1);
+
+        let next = if b2 < a1 {
+            writer.put(PASS);
+            b2
+        } else if a1.abs_diff(b1) <= 3 {
+            writer.put(VERTICAL[(a1 as isize - b1 as isize + 3) as usize]);
+            black = !black;
+            a1
+        } else {
+            let a2 = next_change(line, a1 + 1);
+            writer.put(HORIZONTAL);
+            writer.put_run(a1 - a0.unwrap_or(0), black);
+            writer.put_run(a2 - a1, !black);
+            a2
+        };
+
+        if next >= width {
+            break;
+        }
+        a0 = Some(next);
+    }
+}
+
+/// Encodes a plate as a single Group 4 strip, ending with the end of
+/// facsimile block
+pub fn encode_group4(plate: &GrayImage) -> Vec<u8> {
+    let width = plate.width() as usize;
+    let mut writer = BitWriter::default();
+
+    if width > 0 {
+        let mut reference = vec![false; width];
+        let mut line = vec![false; width];
+        for row in plate.as_raw().chunks_exact(width) {
+            for (pixel, value) in line.iter_mut().zip(row) {
+                *pixel = *value < 128;
+            }
+            encode_line(&mut writer, &reference, &line);
+            std::mem::swap(&mut reference, &mut line);
+        }
+    }
+
+    writer.put(EOL);
+    writer.put(EOL);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_page_is_vertical_codes_and_end_of_block() {
+        // One V0 per row, then two EOLs: 1 000000000001 000000000001
+        let plate = GrayImage::from_pixel(8, 1, image::Luma([255]));
+        assert_eq!(encode_group4(&plate), vec![0x80, 0x08, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn runs_use_makeup_codes() {
+        let mut writer = BitWriter::default();
+        writer.put_run(64, false);
+        // Makeup 64 (11011) then terminating 0 (00110101)
+        assert_eq!(writer.finish(), vec![0b1101_1001, 0b1010_1000]);
+
+        let mut writer = BitWriter::default();
+        writer.put_run(2560 + 1792, true);
+        // Extended 2560 (000000011111), extended 1792 (00000001000) then
+        // black terminating 0 (0000110111)
+        let mut expected = BitWriter::default();
+        expected.put((0b000000011111, 12));
+        expected.put((0b00000001000, 11));
+        expected.put((0b0000110111, 10));
+        assert_eq!(writer.finish(), expected.finish());
+    }
+}
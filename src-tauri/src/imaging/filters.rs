@@ -1,7 +1,20 @@
 use image::{imageops, DynamicImage, GenericImageView};
 
-pub trait ImageFilter {
+/// Filters run on tiles of the source image (see `tiles`), so each one states
+/// how much surrounding context it needs to give the same result as a pass
+/// over the whole image.
+pub trait ImageFilter: Send + Sync {
     fn apply(&self, image: &DynamicImage) -> DynamicImage;
+
+    /// Rows of context needed above and below a tile
+    fn halo(&self) -> u32 {
+        0
+    }
+
+    /// Tiles must start on a multiple of this many rows
+    fn alignment(&self) -> u32 {
+        1
+    }
 }
 
 pub struct Grayscale;
@@ -56,10 +69,13 @@ impl ImageFilter for Invert {
     }
 }
 
+const PIXELATE_BLOCK_SIZE: u32 = 10; // Adjustable pixelation factor
+const BLUR_SIGMA: f32 = 3.0;
+
 impl ImageFilter for Pixelate {
     fn apply(&self, image: &DynamicImage) -> DynamicImage {
         let (width, height) = image.dimensions();
-        let block_size = 10; // Adjustable pixelation factor
+        let block_size = PIXELATE_BLOCK_SIZE;
 
         let scaled_down = image.resize(
             width / block_size,
@@ -69,6 +85,11 @@ impl ImageFilter for Pixelate {
 
         scaled_down.resize(width, height, imageops::FilterType::Nearest)
     }
+
+    // Blocks must not straddle tile boundaries
+    fn alignment(&self) -> u32 {
+        PIXELATE_BLOCK_SIZE
+    }
 }
 
 impl ImageFilter for Brighten {
@@ -91,7 +112,12 @@ impl ImageFilter for Contrast {
 
 impl ImageFilter for Blur {
     fn apply(&self, image: &DynamicImage) -> DynamicImage {
-        image.blur(3.0) // Gaussian blur with sigma = 3.0
+        image.blur(BLUR_SIGMA) // Gaussian blur with sigma = 3.0
+    }
+
+    // The gaussian kernel reaches 2 sigma either side of each pixel
+    fn halo(&self) -> u32 {
+        (2.0 * BLUR_SIGMA).ceil() as u32 + 1
     }
 }
 
@@ -104,6 +130,10 @@ impl ImageFilter for Sharpen {
         );
         img
     }
+
+    fn halo(&self) -> u32 {
+        1
+    }
 }
 
 pub fn get_filter(filter_type: &crate::state::ImageFilter) -> Box<dyn ImageFilter> {
@@ -0,0 +1,99 @@
+//! Job sheet: a plain text summary written next to exported plates, so
+//! whoever runs the machine knows which ink goes on which drum, how much of
+//! it each plate needs and, when asked, what the run will cost.
+
+use crate::errors::Error;
+use crate::imaging::cost::{estimate_from_coverage, CostSettings};
+use crate::imaging::coverage::{coverage_stats, PlateCoverage};
+use crate::imaging::processes::ProcessResult;
+use crate::state::{ColorInfo, ExportSettings};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct JobSheetSettings {
+    pub enabled: bool,
+    /// Adds a cost estimate for a run of this size when set
+    pub cost: Option<CostSettings>,
+}
+
+fn sheet_text(
+    channels: &[ProcessResult],
+    base_filename: &str,
+    colors: Option<&Vec<ColorInfo>>,
+    coverage: &[PlateCoverage],
+    settings: &ExportSettings,
+) -> Result<String, Error> {
+    let page = &settings.page;
+    let (width, height) = page.trim_size();
+
+    let mut lines = vec![
+        "R110 job sheet".to_string(),
+        format!("Job: {}", base_filename),
+        format!("Date: {}", chrono::Local::now().format("%Y-%m-%d %H:%M")),
+        format!(
+            "Page: {} x {} mm ({:?}), bleed {} mm",
+            width, height, page.orientation, page.bleed_mm
+        ),
+    ];
+    if let Some(dpi) = channels.first().and_then(|c| c.dpi) {
+        lines.push(format!("Resolution: {} dpi", dpi));
+    }
+
+    lines.push(String::new());
+    lines.push("Drum  Ink                   Colour    Coverage".to_string());
+    for (i, plate) in coverage.iter().enumerate() {
+        let hex = colors.and_then(|c| c.get(i)).map_or("", |c| c.hex.as_str());
+        lines.push(format!(
+            "{:<5} {:<21} {:<9} {:>7.1}%",
+            i + 1,
+            plate.ink.as_deref().unwrap_or(&plate.channel),
+            hex,
+            plate.mean * 100.0
+        ));
+    }
+
+    if let Some(cost) = &settings.job_sheet.cost {
+        let estimate = estimate_from_coverage(channels, colors, coverage, page, cost)?;
+        lines.push(String::new());
+        lines.push(format!("Estimate for {} copies", estimate.copies));
+        lines.push(format!(
+            "Masters: {} ({:.2})",
+            estimate.masters, estimate.master_cost
+        ));
+        for ink in &estimate.inks {
+            lines.push(format!(
+                "Ink {}: {:.1} ml ({:.2})",
+                ink.ink.as_deref().unwrap_or(&ink.channel),
+                ink.ink_ml,
+                ink.ink_cost
+            ));
+        }
+        lines.push(format!("Total: {:.2}", estimate.total_cost));
+        lines.push(format!("Per copy: {:.4}", estimate.cost_per_copy));
+    }
+
+    lines.push(String::new());
+    Ok(lines.join("\n"))
+}
+
+/// Writes `{base_filename}_job.txt` into `export_path`
+pub fn write_job_sheet(
+    channels: &[ProcessResult],
+    export_path: &str,
+    base_filename: &str,
+    colors: Option<&Vec<ColorInfo>>,
+    settings: &ExportSettings,
+) -> Result<(), Error> {
+    let export_dir = Path::new(export_path);
+    if !export_dir.exists() {
+        fs::create_dir_all(export_dir)?;
+    }
+
+    // Measured once for both the coverage table and the estimate
+    let coverage = coverage_stats(channels, colors)?;
+    let text = sheet_text(channels, base_filename, colors, &coverage, settings)?;
+    fs::write(export_dir.join(format!("{}_job.txt", base_filename)), text)?;
+    Ok(())
+}
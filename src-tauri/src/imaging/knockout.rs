@@ -0,0 +1,99 @@
+//! Knockout and overprint. Separated plates all overprint each other, which
+//! suits photos but muddies flat artwork where one ink should sit cleanly on
+//! paper. A knockout ink clears its area from every plate below it in the
+//! stacking order, leaving the other inks to overprint as before.
+
+use crate::errors::Error;
+use image::GrayImage;
+use rayon::prelude::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum InkMode {
+    #[default]
+    Overprint,
+    Knockout,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct KnockoutSettings {
+    /// Plate indices from the bottom of the stack to the top. Plates left
+    /// out sit below the listed ones; empty means plate order.
+    pub stacking_order: Vec<usize>,
+    /// Mode of each plate, by plate index. Plates without one overprint.
+    pub modes: Vec<InkMode>,
+}
+
+impl KnockoutSettings {
+    /// Plate indices from bottom to top, checked against the number of plates
+    fn stack(&self, plate_count: usize) -> Result<Vec<usize>, Error> {
+        let mut listed = vec![false; plate_count];
+        for &index in &self.stacking_order {
+            match listed.get_mut(index) {
+                Some(listed) if !*listed => *listed = true,
+                _ => {
+                    return Err(Error::Processing(format!(
+                        "Invalid stacking order: plate {} doesn't exist or is listed twice",
+                        index
+                    )))
+                }
+            }
+        }
+
+        let mut stack: Vec<usize> = (0..plate_count).filter(|&i| !listed[i]).collect();
+        stack.extend(&self.stacking_order);
+        Ok(stack)
+    }
+
+    fn mode(&self, index: usize) -> InkMode {
+        self.modes.get(index).copied().unwrap_or_default()
+    }
+}
+
+/// Removes each knockout plate's ink from the plates below it. Partial tones
+/// knock out partially, so antialiased edges stay smooth.
+pub fn knock_out(plates: &mut [GrayImage], settings: &KnockoutSettings) -> Result<(), Error> {
+    let stack = settings.stack(plates.len())?;
+    let Some(first) = plates.first() else {
+        return Ok(());
+    };
+    let dimensions = first.dimensions();
+    if plates.iter().any(|p| p.dimensions() != dimensions) {
+        return Err(Error::Processing(
+            "All plates must be the same size".to_string(),
+        ));
+    }
+
+    // Share of each pixel the plates above leave open, from the top down
+    let mut open: Option<Vec<f32>> = None;
+    for &index in stack.iter().rev() {
+        let plate = &mut plates[index];
+        let knocks_out = settings.mode(index) == InkMode::Knockout;
+
+        match open.as_mut() {
+            Some(open) => {
+                plate
+                    .par_iter_mut()
+                    .zip(open.par_iter_mut())
+                    .for_each(|(value, open)| {
+                        let coverage = 1.0 - *value as f32 / 255.0;
+                        *value = (255.0 - coverage * *open * 255.0).round() as u8;
+                        if knocks_out {
+                            *open *= 1.0 - coverage;
+                        }
+                    })
+            }
+            None if knocks_out => {
+                open = Some(
+                    plate
+                        .as_raw()
+                        .par_iter()
+                        .map(|&value| value as f32 / 255.0)
+                        .collect(),
+                );
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
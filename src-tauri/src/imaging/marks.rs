@@ -0,0 +1,406 @@
+//! Printer's marks drawn outside the trim area of exported plates:
+//! registration targets for lining up drums, crop marks, a tint bar per
+//! ink and a slug line identifying the plate.
+//!
+//! Marks are laid out once as geometry in millimetres, with the origin at
+//! the bottom left corner of the trim box and y pointing up. The PNG and PDF
+//! exports each render that geometry in their own way.
+
+use image::GrayImage;
+
+/// Length of crop marks and size of registration targets
+const MARK_LENGTH_MM: f32 = 5.0;
+const BAR_PATCH_MM: f32 = 3.0;
+const BAR_GAP_MM: f32 = 1.0;
+const BAR_TINTS: [f32; 5] = [1.0, 0.75, 0.5, 0.25, 0.1];
+const SLUG_TEXT_MM: f32 = 2.5;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct MarkSettings {
+    pub registration: bool,
+    pub crop_marks: bool,
+    pub color_bars: bool,
+    pub slug: bool,
+    /// Gap between the trim edge and the marks, in millimetres
+    pub offset_mm: f32,
+    /// Stroke width of the marks, in points
+    pub line_weight_pt: f32,
+}
+
+impl Default for MarkSettings {
+    fn default() -> Self {
+        Self {
+            registration: false,
+            crop_marks: false,
+            color_bars: false,
+            slug: false,
+            offset_mm: 3.0,
+            line_weight_pt: 0.25,
+        }
+    }
+}
+
+impl MarkSettings {
+    pub fn any(&self) -> bool {
+        self.registration || self.crop_marks || self.color_bars || self.slug
+    }
+
+    /// Space needed on each side of the trim to fit the enabled marks
+    pub fn margin_mm(&self) -> f32 {
+        if !self.any() {
+            return 0.0;
+        }
+        let slug = if self.slug { SLUG_TEXT_MM + 2.0 } else { 0.0 };
+        self.offset_mm + MARK_LENGTH_MM + slug + 1.0
+    }
+}
+
+/// What the slug line says about a plate
+#[derive(Debug, Clone)]
+pub struct SlugInfo {
+    pub file_name: String,
+    pub ink_name: String,
+    /// `None` when one sheet carries every plate
+    pub plate_index: Option<usize>,
+    pub plate_count: usize,
+    pub date: String,
+}
+
+impl SlugInfo {
+    pub fn new(
+        file_name: &str,
+        ink_name: &str,
+        plate_index: Option<usize>,
+        plate_count: usize,
+    ) -> Self {
+        Self {
+            file_name: file_name.to_string(),
+            ink_name: ink_name.to_string(),
+            plate_index,
+            plate_count,
+            date: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let plate = match self.plate_index {
+            Some(index) => format!("Plate {}/{}", index + 1, self.plate_count),
+            None => format!("{} plates", self.plate_count),
+        };
+        format!(
+            "{} | {} | {} | {}",
+            self.file_name, self.ink_name, plate, self.date
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mark {
+    /// Stroked path at the configured line weight
+    Path {
+        points: Vec<(f32, f32)>,
+        closed: bool,
+    },
+    /// Filled rectangle, `tint` is ink coverage from 0.0 to 1.0
+    Patch {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        tint: f32,
+    },
+    /// Text with its baseline starting at `x`, `y`; `size` is the cap height
+    Text {
+        x: f32,
+        y: f32,
+        size: f32,
+        text: String,
+    },
+}
+
+fn line(from: (f32, f32), to: (f32, f32)) -> Mark {
+    Mark::Path {
+        points: vec![from, to],
+        closed: false,
+    }
+}
+
+fn registration_target(center: (f32, f32)) -> Vec<Mark> {
+    let (cx, cy) = center;
+    let half = MARK_LENGTH_MM / 2.0;
+    let radius = MARK_LENGTH_MM * 0.3;
+    let circle = (0..48)
+        .map(|i| {
+            let angle = i as f32 / 48.0 * std::f32::consts::TAU;
+            (cx + radius * angle.cos(), cy + radius * angle.sin())
+        })
+        .collect();
+
+    vec![
+        line((cx - half, cy), (cx + half, cy)),
+        line((cx, cy - half), (cx, cy + half)),
+        Mark::Path {
+            points: circle,
+            closed: true,
+        },
+    ]
+}
+
+/// Crop marks, registration targets and the slug for a trim box of the
+/// given size. Tint bars are separate (see `color_bar`) because they differ
+/// per ink.
+pub fn layout_marks(
+    settings: &MarkSettings,
+    width: f32,
+    height: f32,
+    slug: &SlugInfo,
+) -> Vec<Mark> {
+    let mut marks = vec![];
+    let o = settings.offset_mm;
+    let l = MARK_LENGTH_MM;
+
+    if settings.crop_marks {
+        for (x, dx) in [(0.0, -1.0), (width, 1.0)] {
+            for (y, dy) in [(0.0, -1.0), (height, 1.0)] {
+                marks.push(line((x + dx * o, y), (x + dx * (o + l), y)));
+                marks.push(line((x, y + dy * o), (x, y + dy * (o + l))));
+            }
+        }
+    }
+
+    if settings.registration {
+        let away = o + l / 2.0;
+        for center in [
+            (width / 2.0, -away),
+            (width / 2.0, height + away),
+            (-away, height / 2.0),
+            (width + away, height / 2.0),
+        ] {
+            marks.extend(registration_target(center));
+        }
+    }
+
+    if settings.slug {
+        marks.push(Mark::Text {
+            x: 0.0,
+            y: -(o + l + SLUG_TEXT_MM + 1.0),
+            size: SLUG_TEXT_MM,
+            text: slug.text(),
+        });
+    }
+
+    marks
+}
+
+/// Tint steps for one ink above the top left of the trim. Each plate's bar
+/// is shifted along so the bars of all drums sit side by side on the print.
+pub fn color_bar(settings: &MarkSettings, height: f32, plate_index: usize) -> Vec<Mark> {
+    if !settings.color_bars {
+        return vec![];
+    }
+
+    let bar_length = BAR_TINTS.len() as f32 * BAR_PATCH_MM;
+    let start = plate_index as f32 * (bar_length + BAR_GAP_MM);
+    let y = height + settings.offset_mm + (MARK_LENGTH_MM - BAR_PATCH_MM) / 2.0;
+
+    BAR_TINTS
+        .iter()
+        .enumerate()
+        .map(|(i, tint)| Mark::Patch {
+            x: start + i as f32 * BAR_PATCH_MM,
+            y,
+            width: BAR_PATCH_MM,
+            height: BAR_PATCH_MM,
+            tint: *tint,
+        })
+        .collect()
+}
+
+/// Maps mark coordinates onto a raster sheet whose trim box starts at
+/// `margin` pixels from the top left
+struct Raster<'a> {
+    sheet: &'a mut GrayImage,
+    px_per_mm: f32,
+    margin: f32,
+    trim_height: f32,
+    screened: bool,
+}
+
+// 4x4 Bayer matrix, used to screen tint patches on 1-bit plates
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl Raster<'_> {
+    fn to_px(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            self.margin + x * self.px_per_mm,
+            self.margin + self.trim_height - y * self.px_per_mm,
+        )
+    }
+
+    fn ink(&mut self, x: i64, y: i64, tint: f32) {
+        if x < 0 || y < 0 || x >= self.sheet.width() as i64 || y >= self.sheet.height() as i64 {
+            return;
+        }
+        let value = if self.screened {
+            let threshold = (BAYER[(y % 4) as usize][(x % 4) as usize] as f32 + 0.5) / 16.0;
+            if tint > threshold {
+                0
+            } else {
+                255
+            }
+        } else {
+            (255.0 * (1.0 - tint)).round() as u8
+        };
+        let pixel = self.sheet.get_pixel_mut(x as u32, y as u32);
+        pixel[0] = pixel[0].min(value);
+    }
+
+    fn fill(&mut self, left: f32, top: f32, right: f32, bottom: f32, tint: f32) {
+        for y in top.round() as i64..bottom.round() as i64 {
+            for x in left.round() as i64..right.round() as i64 {
+                self.ink(x, y, tint);
+            }
+        }
+    }
+
+    fn stroke(&mut self, from: (f32, f32), to: (f32, f32), weight: f32) {
+        let (x0, y0) = self.to_px(from);
+        let (x1, y1) = self.to_px(to);
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0) as usize;
+        let half = weight / 2.0;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let (x, y) = (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+            self.fill(x - half, y - half, x + half, y + half, 1.0);
+        }
+    }
+
+    fn text(&mut self, origin: (f32, f32), size: f32, text: &str) {
+        let (x, baseline) = self.to_px(origin);
+        let scale = (size * self.px_per_mm / 7.0).max(1.0);
+        let top = baseline - 7.0 * scale;
+        for (i, ch) in text.chars().enumerate() {
+            let left = x + i as f32 * 6.0 * scale;
+            for (row, bits) in glyph(ch).iter().enumerate() {
+                for col in 0..5 {
+                    if bits & (0x10 >> col) != 0 {
+                        let gx = left + col as f32 * scale;
+                        let gy = top + row as f32 * scale;
+                        self.fill(gx, gy, gx + scale, gy + scale, 1.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Places a plate on a sheet with room for the marks and draws them. `dpi`
+/// converts the mark geometry to pixels; `screened` plates get dithered tint
+/// patches so they survive being stored at one bit per pixel.
+pub fn add_marks_to_plate(
+    plate: &GrayImage,
+    marks: &[Mark],
+    settings: &MarkSettings,
+    dpi: f32,
+    screened: bool,
+) -> GrayImage {
+    let px_per_mm = dpi / 25.4;
+    let margin = (settings.margin_mm() * px_per_mm).ceil();
+    let mut sheet = GrayImage::from_pixel(
+        plate.width() + 2 * margin as u32,
+        plate.height() + 2 * margin as u32,
+        image::Luma([255]),
+    );
+    image::imageops::replace(&mut sheet, plate, margin as i64, margin as i64);
+
+    let weight = (settings.line_weight_pt / 72.0 * dpi).max(1.0);
+    let mut raster = Raster {
+        sheet: &mut sheet,
+        px_per_mm,
+        margin,
+        trim_height: plate.height() as f32,
+        screened,
+    };
+
+    for mark in marks {
+        match mark {
+            Mark::Path { points, closed } => {
+                for pair in points.windows(2) {
+                    raster.stroke(pair[0], pair[1], weight);
+                }
+                if let (true, Some(first), Some(last)) = (closed, points.first(), points.last()) {
+                    raster.stroke(*last, *first, weight);
+                }
+            }
+            Mark::Patch {
+                x,
+                y,
+                width,
+                height,
+                tint,
+            } => {
+                let (left, bottom) = raster.to_px((*x, *y));
+                let (right, top) = raster.to_px((x + width, y + height));
+                raster.fill(left, top, right, bottom, *tint);
+            }
+            Mark::Text { x, y, size, text } => raster.text((*x, *y), *size, text),
+        }
+    }
+
+    sheet
+}
+
+/// 5x7 bitmap glyphs for the slug line, one byte per row with the leftmost
+/// pixel in bit 4. Lowercase letters use the capitals.
+fn glyph(ch: char) -> [u8; 7] {
+    match ch.to_ascii_uppercase() {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00; 7],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '|' => [0x04; 7],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
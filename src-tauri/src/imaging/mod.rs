@@ -1,7 +1,28 @@
 pub mod cmyk;
 pub mod colormap;
+pub mod composite;
+pub mod cost;
+pub mod coverage;
 pub mod effects;
 pub mod export;
+pub mod fax;
 pub mod filters;
+pub mod job_sheet;
+pub mod knockout;
+pub mod marks;
+pub mod output;
+pub mod page;
+pub mod plate;
 pub mod processes;
+#[cfg(feature = "app")]
+pub mod proof;
+pub mod psd;
+#[cfg(feature = "app")]
+pub mod registration;
+pub mod spot_pdf;
+pub mod tiff;
+pub mod tiles;
+pub mod trapping;
 pub mod treatment;
+#[cfg(feature = "app")]
+pub mod variants;
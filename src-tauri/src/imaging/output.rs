@@ -0,0 +1,180 @@
+//! Physical print size of a job. The source is resampled to the output
+//! resolution before separation, so halftone cells, dither grain and the
+//! exported files all correspond to real distances on the print.
+
+use crate::errors::Error;
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// Well past what a RISO drum resolves, which tops out at 600 dpi
+pub const MAX_DPI: f32 = 4800.0;
+/// Longest side an output size may resample to, in pixels
+pub const MAX_SIDE_PIXELS: u32 = 30_000;
+/// Largest output an output size may resample to, in pixels. An A3 sheet at
+/// 1200 dpi is about 280 million.
+pub const MAX_PIXELS: u64 = 300_000_000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum LengthUnit {
+    #[default]
+    Millimetres,
+    Inches,
+}
+
+impl LengthUnit {
+    fn to_inches(self, value: f32) -> f32 {
+        match self {
+            LengthUnit::Millimetres => value / 25.4,
+            LengthUnit::Inches => value,
+        }
+    }
+}
+
+/// Target size and resolution. With only one side set the other follows the
+/// source's aspect ratio; with both set the image is fitted inside them.
+/// Leaving both unset keeps the source pixels and only records the DPI.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OutputSize {
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+    #[serde(default)]
+    pub unit: LengthUnit,
+    pub dpi: f32,
+}
+
+impl OutputSize {
+    pub fn validate(&self) -> Result<(), Error> {
+        let positive = |v: f32| v.is_finite() && v > 0.0;
+
+        if !positive(self.dpi) {
+            return Err(Error::InvalidOutputSize("DPI must be positive".to_string()));
+        }
+        if self.dpi > MAX_DPI {
+            return Err(Error::InvalidOutputSize(format!(
+                "DPI can be at most {}",
+                MAX_DPI
+            )));
+        }
+        let sides: Vec<f32> = [self.width, self.height].into_iter().flatten().collect();
+        if sides.iter().any(|v| !positive(*v)) {
+            return Err(Error::InvalidOutputSize(
+                "width and height must be positive".to_string(),
+            ));
+        }
+        if sides
+            .iter()
+            .any(|v| self.unit.to_inches(*v) * self.dpi > MAX_SIDE_PIXELS as f32)
+        {
+            return Err(Error::InvalidOutputSize(format!(
+                "width and height can be at most {} pixels at {} dpi",
+                MAX_SIDE_PIXELS, self.dpi
+            )));
+        }
+        Ok(())
+    }
+
+    /// `pixel_size`, refused when the result is too large to process. With
+    /// one side set the other follows the source, so this can only be
+    /// checked once the source is known.
+    pub fn checked_pixel_size(&self, width: u32, height: u32) -> Result<(u32, u32), Error> {
+        let (width, height) = self.pixel_size(width, height);
+        if width.max(height) > MAX_SIDE_PIXELS {
+            return Err(Error::InvalidOutputSize(format!(
+                "the output would be {} x {} pixels, more than {} per side",
+                width, height, MAX_SIDE_PIXELS
+            )));
+        }
+        if width as u64 * height as u64 > MAX_PIXELS {
+            return Err(Error::InvalidOutputSize(format!(
+                "the output would be {} x {} pixels, more than {} in all",
+                width, height, MAX_PIXELS
+            )));
+        }
+        Ok((width, height))
+    }
+
+    /// Whether the source has to be resampled, as opposed to only tagged
+    pub fn resizes(&self) -> bool {
+        self.width.is_some() || self.height.is_some()
+    }
+
+    /// Pixel size of an image of `width` x `height` pixels at this output size
+    pub fn pixel_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let to_px = |v: f32| self.unit.to_inches(v) * self.dpi;
+        let (width, height) = (width as f32, height as f32);
+
+        let scale = match (self.width, self.height) {
+            (Some(w), Some(h)) => (to_px(w) / width).min(to_px(h) / height),
+            (Some(w), None) => to_px(w) / width,
+            (None, Some(h)) => to_px(h) / height,
+            (None, None) => 1.0,
+        };
+
+        (
+            ((width * scale).round() as u32).max(1),
+            ((height * scale).round() as u32).max(1),
+        )
+    }
+
+    pub fn resample(&self, image: DynamicImage) -> Result<DynamicImage, Error> {
+        let (width, height) = self.checked_pixel_size(image.width(), image.height())?;
+        if (width, height) == (image.width(), image.height()) {
+            return Ok(image);
+        }
+        Ok(image.resize_exact(width, height, FilterType::Lanczos3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(width: Option<f32>, height: Option<f32>, dpi: f32) -> OutputSize {
+        OutputSize {
+            width,
+            height,
+            unit: LengthUnit::Millimetres,
+            dpi,
+        }
+    }
+
+    #[test]
+    fn resolution_and_sides_are_bounded() {
+        assert!(size(Some(297.0), Some(420.0), 600.0).validate().is_ok());
+        assert!(size(None, None, MAX_DPI).validate().is_ok());
+        for bad in [
+            size(None, None, 0.0),
+            size(None, None, f32::NAN),
+            size(None, None, MAX_DPI + 1.0),
+            size(Some(-1.0), None, 300.0),
+            size(None, Some(f32::INFINITY), 300.0),
+            // Two metres at 600 dpi is over 47,000 pixels
+            size(Some(2000.0), None, 600.0),
+        ] {
+            assert!(
+                matches!(bad.validate(), Err(Error::InvalidOutputSize(_))),
+                "{:?}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn side_that_follows_the_source_is_bounded() {
+        // 100 mm wide at 600 dpi is 2362 pixels, so a 1:20 strip runs long
+        let output = size(Some(100.0), None, 600.0);
+        assert!(output.validate().is_ok());
+        assert_eq!(output.checked_pixel_size(1000, 1000).unwrap(), (2362, 2362));
+        assert!(matches!(
+            output.checked_pixel_size(100, 2000),
+            Err(Error::InvalidOutputSize(_))
+        ));
+        // Within each side but too many pixels in all
+        let output = size(Some(1200.0), Some(1200.0), 600.0);
+        assert!(output.validate().is_ok());
+        assert!(matches!(
+            output.checked_pixel_size(1000, 1000),
+            Err(Error::InvalidOutputSize(_))
+        ));
+    }
+}
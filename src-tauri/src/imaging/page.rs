@@ -0,0 +1,242 @@
+//! Page setup for PDF export: paper size, orientation, margins, bleed and
+//! how plates are scaled and aligned on the sheet.
+//!
+//! All measurements are in millimetres. Positions are measured from the
+//! bottom left corner of the media box, which is the trimmed sheet grown by
+//! the bleed on every side.
+
+use crate::errors::Error;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum PaperSize {
+    A4,
+    A3,
+    /// JIS B4, the size RISO machines feed
+    B4,
+    Letter,
+    Tabloid,
+    Custom {
+        width_mm: f32,
+        height_mm: f32,
+    },
+}
+
+impl PaperSize {
+    /// Portrait width and height in millimetres
+    pub fn dimensions(&self) -> (f32, f32) {
+        match self {
+            PaperSize::A4 => (210.0, 297.0),
+            PaperSize::A3 => (297.0, 420.0),
+            PaperSize::B4 => (257.0, 364.0),
+            PaperSize::Letter => (215.9, 279.4),
+            PaperSize::Tabloid => (279.4, 431.8),
+            PaperSize::Custom {
+                width_mm,
+                height_mm,
+            } => (*width_mm, *height_mm),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Margins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl Margins {
+    pub fn uniform(mm: f32) -> Self {
+        Self {
+            top: mm,
+            right: mm,
+            bottom: mm,
+            left: mm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Scaling {
+    /// Print the plate at the job's output resolution, falling back to
+    /// `Fit` for jobs without an output size
+    Output,
+    /// Scale the plate to fill the space inside the margins, never
+    /// enlarging it past its size at 72 DPI
+    Fit,
+    /// Print the plate at its pixel size at the given resolution
+    ActualSize { dpi: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum HorizontalAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum VerticalAlign {
+    Top,
+    Center,
+    Bottom,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PageSetup {
+    pub size: PaperSize,
+    pub orientation: Orientation,
+    pub margins: Margins,
+    /// Extra paper around the trim on every side. Plates placed against an
+    /// edge with no margin run out into it.
+    pub bleed_mm: f32,
+    pub scaling: Scaling,
+    pub horizontal_align: HorizontalAlign,
+    pub vertical_align: VerticalAlign,
+}
+
+impl Default for PageSetup {
+    fn default() -> Self {
+        Self {
+            size: PaperSize::A4,
+            orientation: Orientation::Portrait,
+            margins: Margins::uniform(20.0),
+            bleed_mm: 0.0,
+            scaling: Scaling::Output,
+            horizontal_align: HorizontalAlign::Center,
+            vertical_align: VerticalAlign::Center,
+        }
+    }
+}
+
+/// Where an image lands on the page, in millimetres from the bottom left
+/// of the media box
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub dpi: f32,
+}
+
+/// Area of the page an image may occupy
+struct Area {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl PageSetup {
+    /// Trimmed sheet size with the orientation applied
+    pub fn trim_size(&self) -> (f32, f32) {
+        let (width, height) = self.size.dimensions();
+        match self.orientation {
+            Orientation::Portrait => (width.min(height), width.max(height)),
+            Orientation::Landscape => (width.max(height), width.min(height)),
+        }
+    }
+
+    /// Size of the whole sheet including the bleed
+    pub fn media_size(&self) -> (f32, f32) {
+        let (width, height) = self.trim_size();
+        (width + 2.0 * self.bleed_mm, height + 2.0 * self.bleed_mm)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |reason: &str| Err(Error::InvalidPageSetup(reason.to_string()));
+        let (width, height) = self.trim_size();
+        let m = &self.margins;
+
+        let positive = |v: f32| v.is_finite() && v > 0.0;
+
+        if !positive(width) || !positive(height) {
+            return invalid("paper size must be positive");
+        }
+        if [m.top, m.right, m.bottom, m.left, self.bleed_mm]
+            .iter()
+            .any(|v| !v.is_finite() || *v < 0.0)
+        {
+            return invalid("margins and bleed can't be negative");
+        }
+        if m.left + m.right >= width || m.top + m.bottom >= height {
+            return invalid("margins leave no room on the page");
+        }
+        if let Scaling::ActualSize { dpi } = self.scaling {
+            if !positive(dpi) {
+                return invalid("DPI must be positive");
+            }
+        }
+        Ok(())
+    }
+
+    // Space inside the margins. A side without a margin extends to the edge
+    // of the bleed so edge to edge plates fill it.
+    fn printable_area(&self) -> Area {
+        let (width, height) = self.trim_size();
+        let bleed = self.bleed_mm;
+        let m = &self.margins;
+        let edge = |margin: f32| if margin > 0.0 { margin } else { -bleed };
+
+        let left = bleed + edge(m.left);
+        let bottom = bleed + edge(m.bottom);
+        let right = bleed + width - edge(m.right);
+        let top = bleed + height - edge(m.top);
+
+        Area {
+            x: left,
+            y: bottom,
+            width: right - left,
+            height: top - bottom,
+        }
+    }
+
+    /// Scales and aligns an image of the given pixel size on the page.
+    /// `job_dpi` is the resolution the plate was processed at, if any.
+    pub fn place(&self, width_px: usize, height_px: usize, job_dpi: Option<f32>) -> Placement {
+        let area = self.printable_area();
+        let width_px = width_px as f32;
+        let height_px = height_px as f32;
+
+        let dpi = match (self.scaling, job_dpi) {
+            (Scaling::Output, Some(dpi)) => dpi,
+            (Scaling::ActualSize { dpi }, _) => dpi,
+            (Scaling::Fit | Scaling::Output, _) => {
+                let dpi_for_width = width_px / area.width * 25.4;
+                let dpi_for_height = height_px / area.height * 25.4;
+                dpi_for_width.max(dpi_for_height).max(72.0)
+            }
+        };
+
+        let width = width_px / dpi * 25.4;
+        let height = height_px / dpi * 25.4;
+
+        let x = match self.horizontal_align {
+            HorizontalAlign::Left => area.x,
+            HorizontalAlign::Center => area.x + (area.width - width) / 2.0,
+            HorizontalAlign::Right => area.x + area.width - width,
+        };
+        let y = match self.vertical_align {
+            VerticalAlign::Bottom => area.y,
+            VerticalAlign::Center => area.y + (area.height - height) / 2.0,
+            VerticalAlign::Top => area.y + area.height - height,
+        };
+
+        Placement {
+            x,
+            y,
+            width,
+            height,
+            dpi,
+        }
+    }
+}
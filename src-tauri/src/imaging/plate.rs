@@ -0,0 +1,134 @@
+//! Plates are single-channel images where 0 is full ink and 255 is bare
+//! paper. Screened plates (dither, halftone, threshold) only ever hold 0 or
+//! 255 and are stored at one bit per pixel. Plates are only expanded to RGB
+//! when they are previewed or composited.
+
+use crate::errors::Error;
+use image::imageops::FilterType;
+use image::GrayImage;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Screens are read as tone by blurring over about this distance, and never
+// less than half a halftone cell
+const TONE_RADIUS_MM: f32 = 0.2;
+const MIN_TONE_SIGMA: f32 = 3.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlateDepth {
+    /// Continuous tone, 8 bits per pixel
+    #[default]
+    Gray8,
+    /// Screened, 1 bit per pixel
+    Bilevel,
+}
+
+/// Packs a screened plate into rows of 1-bit pixels, most significant bit
+/// first, each row padded to a whole byte. A set bit is paper (white).
+pub fn pack_bilevel(plate: &GrayImage) -> Vec<u8> {
+    let width = plate.width() as usize;
+    let row_bytes = width.div_ceil(8);
+    let mut packed = vec![0u8; row_bytes * plate.height() as usize];
+    if width == 0 {
+        return packed;
+    }
+
+    for (row, out) in plate
+        .as_raw()
+        .chunks_exact(width)
+        .zip(packed.chunks_exact_mut(row_bytes))
+    {
+        for (x, value) in row.iter().enumerate() {
+            if *value >= 128 {
+                out[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    packed
+}
+
+/// PNG pHYs value for a resolution; the chunk only stores pixels per metre
+pub fn png_pixel_dims(dpi: f32) -> png::PixelDimensions {
+    let ppm = (dpi / 0.0254).round() as u32;
+    png::PixelDimensions {
+        xppu: ppm,
+        yppu: ppm,
+        unit: png::Unit::Meter,
+    }
+}
+
+/// Writes a plate as a grayscale PNG, tagging it with its resolution when the
+/// job has one
+pub fn save_plate_png(
+    plate: &GrayImage,
+    depth: PlateDepth,
+    dpi: Option<f32>,
+    path: &Path,
+) -> Result<(), Error> {
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, plate.width(), plate.height());
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_compression(png::Compression::Fast);
+    encoder.set_pixel_dims(dpi.map(png_pixel_dims));
+
+    let data = match depth {
+        PlateDepth::Gray8 => {
+            encoder.set_depth(png::BitDepth::Eight);
+            plate.as_raw().clone()
+        }
+        PlateDepth::Bilevel => {
+            encoder.set_depth(png::BitDepth::One);
+            pack_bilevel(plate)
+        }
+    };
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(|e| Error::Processing(format!("Failed to write plate: {}", e)))
+}
+
+/// Loads a plate from disk. 1-bit files are expanded to 0/255.
+pub fn load_plate(path: &str) -> Result<GrayImage, Error> {
+    let img = image::open(path).map_err(|e| Error::Processing(e.to_string()))?;
+    Ok(img.into_luma8())
+}
+
+/// Scales a plate down so its longest side is at most `max_size` pixels,
+/// averaging screens into the tone they print. Returns the plate with the
+/// factor it was scaled by.
+pub fn fit_plate(plate: GrayImage, max_size: u32) -> (GrayImage, f32) {
+    let longest = plate.width().max(plate.height());
+    if max_size == 0 || longest <= max_size {
+        return (plate, 1.0);
+    }
+    let scale = max_size as f32 / longest as f32;
+    let width = ((plate.width() as f32 * scale).round() as u32).max(1);
+    let height = ((plate.height() as f32 * scale).round() as u32).max(1);
+    let scaled = image::imageops::resize(&plate, width, height, FilterType::Triangle);
+    (scaled, scale)
+}
+
+/// Blur, in pixels at `dpi`, that makes a screen read as the tone it prints
+pub fn tone_sigma(dpi: f32) -> f32 {
+    (TONE_RADIUS_MM * dpi / 25.4).max(MIN_TONE_SIGMA)
+}
+
+/// Ink coverage of every pixel from 0.0 to 1.0, blurred by `sigma` pixels
+/// first unless it is 0
+pub fn plate_tone(plate: &GrayImage, sigma: f32) -> Vec<f32> {
+    let blurred;
+    let plate = if sigma > 0.0 {
+        blurred = image::imageops::fast_blur(plate, sigma);
+        &blurred
+    } else {
+        plate
+    };
+    plate
+        .as_raw()
+        .iter()
+        .map(|&v| 1.0 - v as f32 / 255.0)
+        .collect()
+}
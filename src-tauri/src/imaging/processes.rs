@@ -1,100 +1,170 @@
 use super::colormap::ColorMap;
 use super::effects::get_effect;
-use super::filters::get_filter;
+use super::filters::{get_filter, ImageFilter};
+use super::knockout::{knock_out, KnockoutSettings};
+use super::output::OutputSize;
+use super::plate::{load_plate, save_plate_png, PlateDepth};
+use super::trapping::{trap_plates, TrapSettings};
 use super::treatment::ImageTreatment;
 use crate::errors::Error;
-use crate::state::AppStateInner;
-use image::{open, DynamicImage, ImageBuffer, Rgb};
+use crate::jobs::{JobContext, JobStage};
+use crate::state::{ColorInfo, ProcessSettings};
+use image::{open, DynamicImage, GrayImage};
 use std::env;
-use std::fs::File;
-use std::io::BufWriter;
 
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct ProcessResult {
     pub channel: String,
     pub image_path: String,
+    pub depth: PlateDepth,
+    /// Print resolution when the job has an output size
+    pub dpi: Option<f32>,
 }
 
+const CHANNEL_NAMES: [&str; 4] = ["cyan", "magenta", "yellow", "black"];
+
+fn channel_name(index: usize) -> &'static str {
+    CHANNEL_NAMES.get(index).copied().unwrap_or("unknown")
+}
+
+/// Runs the pipeline over single-channel plates. The source image is only
+/// kept until separation; the filter is applied per tile during separation.
 struct ImageProcessor {
-    image: DynamicImage,
-    processed_images: Vec<DynamicImage>,
+    image: Option<DynamicImage>,
+    filter: Option<Box<dyn ImageFilter>>,
+    processed_images: Vec<GrayImage>,
+    depth: PlateDepth,
+    dpi: Option<f32>,
+    ctx: JobContext,
 }
 
 impl ImageProcessor {
-    fn new(image: DynamicImage) -> Self {
+    fn new(image: DynamicImage, ctx: &JobContext) -> Self {
         Self {
-            image,
+            image: Some(image),
+            filter: None,
             processed_images: vec![],
+            depth: PlateDepth::Gray8,
+            dpi: None,
+            ctx: ctx.clone(),
         }
     }
 
-    fn from_channels(channels: &[ProcessResult]) -> Result<Self, Error> {
+    fn from_channels(channels: &[ProcessResult], ctx: &JobContext) -> Result<Self, Error> {
         let mut images = vec![];
-        for channel in channels {
-            let img = open(&channel.image_path).map_err(|e| Error::Processing(e.to_string()))?;
-            images.push(img);
+        for (i, channel) in channels.iter().enumerate() {
+            ctx.checkpoint()?;
+            ctx.report(
+                JobStage::Loading,
+                Some(&channel.channel),
+                0.2 * i as f32 / channels.len() as f32,
+            );
+            images.push(load_plate(&channel.image_path)?);
         }
 
-        // Use the first channel as the base image (it won't be used for filtering anyway)
-        let base = images
-            .first()
-            .ok_or_else(|| Error::Processing("No channels found".to_string()))?
-            .clone();
+        if images.is_empty() {
+            return Err(Error::Processing("No channels found".to_string()));
+        }
 
         Ok(Self {
-            image: base,
+            image: None,
+            filter: None,
             processed_images: images,
+            depth: PlateDepth::Gray8,
+            dpi: channels[0].dpi,
+            ctx: ctx.clone(),
         })
     }
 
-    fn apply_filter(mut self, filter: Option<&crate::state::ImageFilter>) -> Self {
+    /// Sets the filter up; it runs tile by tile as the channels are
+    /// separated, so its progress is the Separating stage's
+    fn apply_filter(mut self, filter: Option<&crate::state::ImageFilter>) -> Result<Self, Error> {
         if let Some(filter_type) = filter {
-            let filter = get_filter(filter_type);
-            self.image = filter.apply(&self.image);
+            self.filter = Some(get_filter(filter_type));
         }
-        self
+        Ok(self)
     }
 
-    fn apply_effect_to_channels(mut self, effect: Option<&crate::state::ImageEffect>) -> Self {
-        if let Some(effect_type) = effect {
-            let effect = get_effect(effect_type);
-            self.processed_images = self
-                .processed_images
-                .iter()
-                .map(|img| effect.apply(img))
-                .collect();
+    /// Resamples the source to the job's output size before it is separated
+    fn resample(mut self, output: Option<&OutputSize>) -> Result<Self, Error> {
+        let Some(output) = output else {
+            return Ok(self);
+        };
+        output.validate()?;
+        self.dpi = Some(output.dpi);
+
+        if output.resizes() {
+            self.ctx.checkpoint()?;
+            self.ctx.report(JobStage::Resampling, None, 0.15);
+            self.image = self
+                .image
+                .take()
+                .map(|image| output.resample(image))
+                .transpose()?;
         }
-        self
+        Ok(self)
     }
 
-    fn separate_channels(mut self) -> Result<Self, Error> {
-        let channels = ImageTreatment::new(&self.image)?
-            .process()?
-            .into_iter()
-            .map(|channel| DynamicImage::ImageRgb8(channel))
-            .collect();
+    /// Clears knockout inks' areas from the plates below them
+    fn knock_out(mut self, knockout: Option<&KnockoutSettings>) -> Result<Self, Error> {
+        if let Some(knockout) = knockout {
+            self.ctx.checkpoint()?;
+            self.ctx.report(JobStage::Knockout, None, 0.35);
+            knock_out(&mut self.processed_images, knockout)?;
+        }
+        Ok(self)
+    }
+
+    /// Traps the separated plates against each other using their inks
+    fn trap(
+        mut self,
+        trapping: Option<&TrapSettings>,
+        colors: Option<&Vec<ColorInfo>>,
+    ) -> Result<Self, Error> {
+        let (Some(trapping), Some(colors)) = (trapping, colors) else {
+            return Ok(self);
+        };
+        trapping.validate()?;
+        self.ctx.checkpoint()?;
+        self.ctx.report(JobStage::Trapping, None, 0.4);
+
+        let inks: Vec<&str> = colors.iter().map(|c| c.hex.as_str()).collect();
+        let dpi = self.dpi.unwrap_or(72.0);
+        trap_plates(&mut self.processed_images, &inks, trapping, dpi)?;
+        Ok(self)
+    }
 
-        self.processed_images = channels;
+    fn apply_effect_to_channels(
+        mut self,
+        effect: Option<&crate::state::ImageEffect>,
+    ) -> Result<Self, Error> {
+        if let Some(effect_type) = effect {
+            let effect = get_effect(effect_type);
+            let total = self.processed_images.len();
+            for (i, img) in self.processed_images.iter_mut().enumerate() {
+                self.ctx.checkpoint()?;
+                self.ctx.report(
+                    JobStage::Effects,
+                    Some(channel_name(i)),
+                    0.5 + 0.3 * i as f32 / total as f32,
+                );
+                effect.apply(img);
+            }
+            self.depth = effect.depth();
+        }
         Ok(self)
     }
 
-    fn save_jpeg_with_quality(
-        img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
-        filepath: &str,
-        quality: u8,
-    ) -> Result<(), Error> {
-        let file = File::create(filepath)?;
-        let mut writer = BufWriter::new(file);
-
-        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut writer, quality);
-        encoder.encode(
-            img.as_raw(),
-            img.width(),
-            img.height(),
-            image::ExtendedColorType::Rgb8,
-        )?;
-
-        Ok(())
+    fn separate_channels(mut self) -> Result<Self, Error> {
+        self.ctx.checkpoint()?;
+        self.ctx.report(JobStage::Separating, None, 0.2);
+        // The source is dropped once separated to keep peak memory down
+        let image = self
+            .image
+            .take()
+            .ok_or_else(|| Error::Processing("No source image to separate".to_string()))?;
+        self.processed_images = ImageTreatment::new(&image)?.process(self.filter.as_deref())?;
+        Ok(self)
     }
 
     fn save(self, filename: &str) -> Result<Vec<ProcessResult>, Error> {
@@ -106,88 +176,97 @@ impl ImageProcessor {
             .unwrap_or_else(|| "processed".into());
 
         let mut results: Vec<ProcessResult> = vec![];
+        let total = self.processed_images.len();
 
         for (i, img) in self.processed_images.iter().enumerate() {
-            let channel = match i {
-                0 => "cyan",
-                1 => "magenta",
-                2 => "yellow",
-                3 => "black",
-                _ => "unknown",
-            };
+            let channel = channel_name(i);
+            self.ctx.checkpoint()?;
+            self.ctx.report(
+                JobStage::Saving,
+                Some(channel),
+                0.8 + 0.2 * i as f32 / total as f32,
+            );
 
             // Create a unique filename for each channel using the prefix
-            let channel_filename = format!("{}_{}_{}.jpeg", prefix, channel, i);
+            let channel_filename = format!("{}_{}_{}.png", prefix, channel, i);
             let channel_path = temp_dir.join(channel_filename);
 
-            let rgb_img = img.as_rgb8().ok_or_else(|| {
-                Error::Processing(format!("Failed to convert {} channel to RGB8", channel))
-            })?;
-
-            let path_str = channel_path
-                .to_str()
-                .ok_or_else(|| Error::Processing("Failed to convert path to string".to_string()))?;
-
-            ImageProcessor::save_jpeg_with_quality(rgb_img, path_str, 70)?;
+            save_plate_png(img, self.depth, self.dpi, &channel_path)?;
 
             let result = ProcessResult {
                 channel: channel.to_string(),
                 image_path: channel_path.to_string_lossy().to_string(),
+                depth: self.depth,
+                dpi: self.dpi,
             };
 
             results.push(result);
         }
 
+        self.ctx.report(JobStage::Saving, None, 1.0);
         Ok(results)
     }
 }
 
 pub fn process_image(
     file_path: &str,
-    state: &AppStateInner,
+    settings: Option<&ProcessSettings>,
+    image_name: Option<&str>,
     cached_channels: Option<&Vec<ProcessResult>>,
+    ctx: &JobContext,
 ) -> Result<Vec<ProcessResult>, Error> {
-    let img = open(file_path).map_err(|e| Error::Processing(e.to_string()))?;
-    let filter = state
-        .process_settings
-        .as_ref()
-        .and_then(|s| s.filter.as_ref());
-    let effect = state
-        .process_settings
-        .as_ref()
-        .and_then(|s| s.effect.as_ref());
+    let filter = settings.and_then(|s| s.filter.as_ref());
+    let effect = settings.and_then(|s| s.effect.as_ref());
+    let output = settings.and_then(|s| s.output.as_ref());
+    let knockout = settings.and_then(|s| s.knockout.as_ref());
+    let trapping = settings.and_then(|s| s.trapping.as_ref());
+    let colors = settings.and_then(|s| s.colors.as_ref());
 
     let timestamp = chrono::Local::now().timestamp_millis();
     let filename = format!(
         "processed_{}_{}.png",
         timestamp,
-        state.image_name.clone().unwrap_or_default()
+        image_name.unwrap_or_default()
     );
 
-    // If no filter is applied and we have cached channels, use them to skip separation
-    if filter.is_none() {
+    // If no filter is applied and the source keeps its size, the cached
+    // channels can be used to skip separation
+    if filter.is_none() && !output.is_some_and(OutputSize::resizes) {
         if let Some(channels) = cached_channels {
-            return ImageProcessor::from_channels(channels)?
-                .apply_effect_to_channels(effect)
+            return ImageProcessor::from_channels(channels, ctx)?
+                .resample(output)?
+                .knock_out(knockout)?
+                .trap(trapping, colors)?
+                .apply_effect_to_channels(effect)?
                 .save(&filename);
         }
     }
 
-    ImageProcessor::new(img)
-        .apply_filter(filter)
+    ctx.report(JobStage::Loading, None, 0.0);
+    let img = open(file_path).map_err(|e| Error::Processing(e.to_string()))?;
+
+    ImageProcessor::new(img, ctx)
+        .apply_filter(filter)?
+        .resample(output)?
         .separate_channels()?
-        .apply_effect_to_channels(effect)
+        .knock_out(knockout)?
+        .trap(trapping, colors)?
+        .apply_effect_to_channels(effect)?
         .save(&filename)
 }
 
 // New function for background processing
-pub fn process_image_background(file_path: &str) -> Result<Vec<ProcessResult>, Error> {
+pub fn process_image_background(
+    file_path: &str,
+    ctx: &JobContext,
+) -> Result<Vec<ProcessResult>, Error> {
+    ctx.report(JobStage::Loading, None, 0.0);
     let img = open(file_path).map_err(|e| Error::Processing(e.to_string()))?;
     let timestamp = chrono::Local::now().timestamp_millis();
     let filename = format!("processed_{}.png", timestamp);
 
     // Only separate channels without filters/effects for the initial loading
-    ImageProcessor::new(img)
+    ImageProcessor::new(img, ctx)
         .separate_channels()?
         .save(&filename)
 }
@@ -0,0 +1,211 @@
+//! Soft proof of a RISO print. Where `composite` multiplies perfect tints,
+//! this lays the inks down one drum at a time as semi-transparent layers on
+//! coloured, slightly textured paper, with uneven density, grain and
+//! optional misregistration, which is much closer to what comes off the
+//! machine.
+
+use crate::errors::Error;
+use crate::imaging::colormap::ColorMap;
+use crate::imaging::composite::plates_with_inks;
+use crate::imaging::processes::ProcessResult;
+use crate::imaging::registration::{offset_plates, PlateOffset};
+use crate::state::ColorInfo;
+use image::{GrayImage, RgbImage};
+use rayon::prelude::*;
+
+/// Share of the colour underneath an ink hides when opacity isn't set
+const DEFAULT_INK_OPACITY: f32 = 0.1;
+// Noise cell sizes in pixels
+const PAPER_FIBRE_CELL: f32 = 3.0;
+const PAPER_CLOUD_CELL: f32 = 24.0;
+const DENSITY_CELLS_PER_SHEET: f32 = 4.0;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ProofSettings {
+    pub paper_color: String,
+    /// Strength of the paper fibre texture, 0.0 to 1.0
+    pub paper_texture: f32,
+    /// How much each ink hides the inks printed before it, by plate index,
+    /// from 0.0 (transparent) to 1.0 (opaque)
+    pub ink_opacity: Vec<f32>,
+    /// Uneven ink laydown across the sheet, 0.0 to 1.0
+    pub density_variation: f32,
+    /// Speckles where the drum didn't transfer ink, 0.0 to 1.0
+    pub grain: f32,
+    /// Plate indices in the order they go through the machine. Plates left
+    /// out aren't printed; empty means every plate in plate order.
+    pub print_order: Vec<usize>,
+    /// Per plate misregistration, by plate index
+    pub offsets: Vec<PlateOffset>,
+    /// Seed for the texture and grain so a proof can be reproduced
+    pub seed: u32,
+}
+
+impl Default for ProofSettings {
+    fn default() -> Self {
+        Self {
+            paper_color: "#F7F4EC".to_string(),
+            paper_texture: 0.3,
+            ink_opacity: vec![],
+            density_variation: 0.3,
+            grain: 0.2,
+            print_order: vec![],
+            offsets: vec![],
+            seed: 110,
+        }
+    }
+}
+
+impl ProofSettings {
+    /// Plate indices in print order, checked against the number of plates
+    fn order(&self, plate_count: usize) -> Result<Vec<usize>, Error> {
+        if self.print_order.is_empty() {
+            return Ok((0..plate_count).collect());
+        }
+
+        let mut seen = vec![false; plate_count];
+        for &index in &self.print_order {
+            match seen.get_mut(index) {
+                Some(seen) if !*seen => *seen = true,
+                _ => {
+                    return Err(Error::Processing(format!(
+                        "Invalid print order: plate {} doesn't exist or is listed twice",
+                        index
+                    )))
+                }
+            }
+        }
+        Ok(self.print_order.clone())
+    }
+}
+
+/// Hashes a lattice point to a value between 0.0 and 1.0
+fn hash(x: i32, y: i32, seed: u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ seed.wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    (h & 0xffff) as f32 / 65535.0
+}
+
+/// Smoothly interpolated lattice noise between 0.0 and 1.0 with features
+/// roughly `cell` pixels across
+fn value_noise(x: f32, y: f32, cell: f32, seed: u32) -> f32 {
+    let (x, y) = (x / cell, y / cell);
+    let (x0, y0) = (x.floor(), y.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+    let (ix, iy) = (x0 as i32, y0 as i32);
+
+    let top = hash(ix, iy, seed) * (1.0 - tx) + hash(ix + 1, iy, seed) * tx;
+    let bottom = hash(ix, iy + 1, seed) * (1.0 - tx) + hash(ix + 1, iy + 1, seed) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// Ink coverage of a plate at a pixel, 0.0 to 1.0
+fn coverage(plate: &GrayImage, index: usize) -> f32 {
+    1.0 - plate.as_raw()[index] as f32 / 255.0
+}
+
+fn rgb(hex: &str) -> Result<[f32; 3], Error> {
+    let (r, g, b) = ColorMap::hex_to_rgb(hex)?;
+    Ok([r, g, b].map(|c| c as f32 / 255.0))
+}
+
+/// Renders the proof from plates and their ink colours. `dpi` turns the
+/// misregistration offsets into pixels.
+pub fn render_proof(
+    plates: &[GrayImage],
+    inks: &[&str],
+    settings: &ProofSettings,
+    dpi: f32,
+) -> Result<RgbImage, Error> {
+    let Some(first) = plates.first() else {
+        return Err(Error::Processing("No plates to proof".to_string()));
+    };
+    let (width, height) = first.dimensions();
+    if plates.iter().any(|p| p.dimensions() != (width, height)) {
+        return Err(Error::Processing(
+            "All plates must be the same size".to_string(),
+        ));
+    }
+    let order = settings.order(plates.len())?;
+    let plates = offset_plates(plates, &settings.offsets, dpi);
+
+    let paper = rgb(&settings.paper_color)?;
+    let ink_colors = inks
+        .iter()
+        .map(|hex| rgb(hex))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let opacity = |index: usize| {
+        settings
+            .ink_opacity
+            .get(index)
+            .copied()
+            .unwrap_or(DEFAULT_INK_OPACITY)
+            .clamp(0.0, 1.0)
+    };
+    let density_cell = width.max(height) as f32 / DENSITY_CELLS_PER_SHEET;
+    let seed = settings.seed;
+
+    let mut image = RgbImage::new(width, height);
+    image
+        .par_chunks_mut(width as usize * 3)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let y = y as u32;
+            for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                let x = x as u32;
+                let (fx, fy) = (x as f32, y as f32);
+
+                // Paper: fine fibres over soft clouds, darkening slightly
+                let fibre = value_noise(fx, fy, PAPER_FIBRE_CELL, seed);
+                let cloud = value_noise(fx, fy, PAPER_CLOUD_CELL, seed ^ 0x5eed);
+                let texture = 1.0 - settings.paper_texture * 0.08 * (0.6 * fibre + 0.4 * cloud);
+                let mut color = paper.map(|c| c * texture);
+
+                for &index in &order {
+                    let mut amount = coverage(&plates[index], (y * width + x) as usize);
+                    if amount <= 0.0 {
+                        continue;
+                    }
+
+                    let plate_seed = seed.wrapping_add(index as u32 * 7919);
+                    let density = value_noise(fx, fy, density_cell, plate_seed);
+                    amount *= 1.0 - settings.density_variation * 0.35 * density;
+                    let speck = hash(x as i32, y as i32, plate_seed);
+                    amount *= 1.0 - settings.grain * speck.powi(6);
+
+                    // Transparent ink multiplies, opaque ink covers
+                    let ink = ink_colors.get(index).copied().unwrap_or([0.0; 3]);
+                    let hide = opacity(index);
+                    for (c, ink) in color.iter_mut().zip(ink) {
+                        let inked = *c * ink * (1.0 - hide) + ink * hide;
+                        *c += (inked - *c) * amount;
+                    }
+                }
+
+                for (out, c) in pixel.iter_mut().zip(color) {
+                    *out = (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+        });
+
+    Ok(image)
+}
+
+/// Loads the plates of a job and renders the proof with their assigned
+/// inks
+pub fn render_proof_for_channels(
+    channels: &[ProcessResult],
+    colors: Option<&Vec<ColorInfo>>,
+    settings: &ProofSettings,
+) -> Result<RgbImage, Error> {
+    let (plates, inks) = plates_with_inks(channels, colors)?;
+    let dpi = channels.first().and_then(|c| c.dpi).unwrap_or(72.0);
+
+    render_proof(&plates, &inks, settings, dpi)
+}
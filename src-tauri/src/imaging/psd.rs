@@ -0,0 +1,326 @@
+//! Photoshop export: every plate becomes a spot channel of one Multichannel
+//! PSD, named after its RISO ink and previewed in the ink's colour, so a
+//! separation can be touched up in Photoshop and sent back out plate by
+//! plate.
+
+use crate::errors::Error;
+use crate::imaging::colormap::ColorMap;
+use crate::imaging::composite::plates_with_inks;
+use crate::imaging::processes::ProcessResult;
+use crate::imaging::spot_pdf::hex_to_lab;
+use crate::state::ColorInfo;
+use std::fs;
+use std::path::Path;
+
+// Largest image a PSD (as opposed to a PSB) can hold
+const MAX_DIMENSION: u32 = 30_000;
+
+const MULTICHANNEL_MODE: u16 = 7;
+const RLE_COMPRESSION: u16 = 1;
+
+// Image resource IDs
+const RESOLUTION_INFO: u16 = 1005;
+const ALPHA_NAMES: u16 = 1006;
+const DISPLAY_INFO: u16 = 1077;
+
+const SPOT_CHANNEL: u8 = 2;
+
+/// How opaque Photoshop should preview an ink, from 0 to 100. Light inks
+/// like yellow barely cover what is under them, dark ones mostly hide it.
+fn solidity(hex: &str) -> Result<u16, Error> {
+    let [lightness, _, _] = hex_to_lab(hex)?;
+    Ok((100.0 - lightness).round().clamp(0.0, 100.0) as u16)
+}
+
+fn resource(buffer: &mut Vec<u8>, id: u16, data: &[u8]) {
+    buffer.extend(b"8BIM");
+    buffer.extend(id.to_be_bytes());
+    // Empty Pascal string name, padded to an even length
+    buffer.extend([0, 0]);
+    buffer.extend((data.len() as u32).to_be_bytes());
+    buffer.extend(data);
+    if data.len() % 2 == 1 {
+        buffer.push(0);
+    }
+}
+
+/// PackBits compression of one row, as used by PSD image data
+fn pack_bits(row: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < row.len() {
+        let mut run = 1;
+        while i + run < row.len() && run < 128 && row[i + run] == row[i] {
+            run += 1;
+        }
+
+        if run > 1 {
+            out.push((257 - run) as u8);
+            out.push(row[i]);
+            i += run;
+        } else {
+            let start = i;
+            while i < row.len() && i - start < 128 && (i + 1 >= row.len() || row[i] != row[i + 1]) {
+                i += 1;
+            }
+            out.push((i - start - 1) as u8);
+            out.extend(&row[start..i]);
+        }
+    }
+}
+
+pub fn save_channels_to_psd(
+    channels: &[ProcessResult],
+    export_path: &str,
+    base_filename: &str,
+    colors: Option<&Vec<ColorInfo>>,
+) -> Result<(), Error> {
+    let export_dir = Path::new(export_path);
+    if !export_dir.exists() {
+        fs::create_dir_all(export_dir)?;
+    }
+
+    let (plates, inks) = plates_with_inks(channels, colors)?;
+    let Some(first) = plates.first() else {
+        return Err(Error::Processing("No plates to export".to_string()));
+    };
+
+    let (width, height) = first.dimensions();
+    if plates.iter().any(|p| p.dimensions() != (width, height)) {
+        return Err(Error::Processing(
+            "All plates must be the same size".to_string(),
+        ));
+    }
+    if width == 0 || height == 0 {
+        return Err(Error::Processing("Plates are empty".to_string()));
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(Error::Processing(format!(
+            "PSD export is limited to {} pixels per side",
+            MAX_DIMENSION
+        )));
+    }
+
+    let mut psd = Vec::new();
+
+    // Header
+    psd.extend(b"8BPS");
+    psd.extend(1u16.to_be_bytes());
+    psd.extend([0u8; 6]);
+    psd.extend((plates.len() as u16).to_be_bytes());
+    psd.extend(height.to_be_bytes());
+    psd.extend(width.to_be_bytes());
+    psd.extend(8u16.to_be_bytes());
+    psd.extend(MULTICHANNEL_MODE.to_be_bytes());
+
+    // No colour mode data
+    psd.extend(0u32.to_be_bytes());
+
+    let mut names = Vec::new();
+    let mut display = 1u32.to_be_bytes().to_vec();
+    for (i, (channel, hex)) in channels.iter().zip(inks).enumerate() {
+        // Use the RISO ink assigned in the UI, falling back to the process colour
+        let name = colors
+            .and_then(|c| c.get(i))
+            .map_or(channel.channel.as_str(), |c| c.name.as_str());
+
+        let name: Vec<u8> = name.bytes().filter(u8::is_ascii).take(255).collect();
+        names.push(name.len() as u8);
+        names.extend(name);
+
+        let (r, g, b) = ColorMap::hex_to_rgb(hex)?;
+        display.extend(0u16.to_be_bytes()); // RGB colour space
+        for component in [r, g, b, 0] {
+            display.extend((component as u16 * 257).to_be_bytes());
+        }
+        display.extend(solidity(hex)?.to_be_bytes());
+        display.extend([SPOT_CHANNEL, 0]);
+    }
+
+    // Resolution in 16.16 fixed point pixels per inch
+    let dpi = channels[0].dpi.unwrap_or(72.0);
+    let fixed = ((dpi * 65536.0).round() as u32).to_be_bytes();
+    let mut resolution = Vec::new();
+    for _ in 0..2 {
+        resolution.extend(fixed);
+        resolution.extend(1u16.to_be_bytes()); // pixels per inch
+        resolution.extend(1u16.to_be_bytes()); // show sizes in inches
+    }
+
+    let mut resources = Vec::new();
+    resource(&mut resources, RESOLUTION_INFO, &resolution);
+    resource(&mut resources, ALPHA_NAMES, &names);
+    resource(&mut resources, DISPLAY_INFO, &display);
+    psd.extend((resources.len() as u32).to_be_bytes());
+    psd.extend(resources);
+
+    // No layers
+    psd.extend(0u32.to_be_bytes());
+
+    // Image data: row byte counts for every channel, then the rows. Plates
+    // already use 0 for full ink, which is how Photoshop stores spot channels.
+    let mut counts = Vec::new();
+    let mut rows = Vec::new();
+    for plate in &plates {
+        for row in plate.as_raw().chunks_exact(width as usize) {
+            let start = rows.len();
+            pack_bits(row, &mut rows);
+            counts.extend(((rows.len() - start) as u16).to_be_bytes());
+        }
+    }
+    psd.extend(RLE_COMPRESSION.to_be_bytes());
+    psd.extend(counts);
+    psd.extend(rows);
+
+    fs::write(export_dir.join(format!("{}.psd", base_filename)), psd)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::plate::{save_plate_png, PlateDepth};
+    use image::{GrayImage, Luma};
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {
+        u16::from_be_bytes([data[at], data[at + 1]])
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+    }
+
+    fn unpack_bits(mut data: &[u8]) -> Vec<u8> {
+        let mut row = Vec::new();
+        while let [header, rest @ ..] = data {
+            if *header < 128 {
+                let len = *header as usize + 1;
+                row.extend(&rest[..len]);
+                data = &rest[len..];
+            } else {
+                row.extend(std::iter::repeat_n(rest[0], 257 - *header as usize));
+                data = &rest[1..];
+            }
+        }
+        row
+    }
+
+    #[test]
+    fn pack_bits_round_trips_runs_and_literals() {
+        let mut row: Vec<u8> = (0..300).map(|x| (x % 7) as u8).collect();
+        row.extend([9; 200]);
+        row.extend([1, 2, 2, 3]);
+        let mut packed = Vec::new();
+        pack_bits(&row, &mut packed);
+        assert_eq!(unpack_bits(&packed), row);
+    }
+
+    #[test]
+    fn sections_and_channels_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let (width, height) = (150u32, 20u32);
+        let plates: Vec<GrayImage> = (0..3u32)
+            .map(|i| {
+                GrayImage::from_fn(width, height, |x, y| {
+                    Luma([if (x / (i + 2) + y) % 2 == 0 {
+                        0
+                    } else {
+                        (x * i) as u8
+                    }])
+                })
+            })
+            .collect();
+        let channels: Vec<ProcessResult> = plates
+            .iter()
+            .enumerate()
+            .map(|(i, plate)| {
+                let path = dir.path().join(format!("{}.png", i));
+                save_plate_png(plate, PlateDepth::Gray8, Some(600.0), &path).unwrap();
+                ProcessResult {
+                    channel: ["cyan", "magenta", "yellow"][i].to_string(),
+                    image_path: path.to_string_lossy().to_string(),
+                    depth: PlateDepth::Gray8,
+                    dpi: Some(600.0),
+                }
+            })
+            .collect();
+        // The third plate has no ink, so it keeps its process name
+        let inks = vec![
+            ColorInfo {
+                hex: "#0078BF".to_string(),
+                name: "Blue".to_string(),
+                cost_per_ml: None,
+            },
+            ColorInfo {
+                hex: "#FF48B0".to_string(),
+                name: "Fluorescent Pink".to_string(),
+                cost_per_ml: None,
+            },
+        ];
+        let out = dir.path().to_string_lossy().to_string();
+        save_channels_to_psd(&channels, &out, "job", Some(&inks)).unwrap();
+        let psd = fs::read(dir.path().join("job.psd")).unwrap();
+
+        assert_eq!(&psd[..4], b"8BPS");
+        assert_eq!(u16_at(&psd, 4), 1);
+        assert_eq!(u16_at(&psd, 12), 3);
+        assert_eq!(u32_at(&psd, 14), height);
+        assert_eq!(u32_at(&psd, 18), width);
+        assert_eq!(u16_at(&psd, 22), 8);
+        assert_eq!(u16_at(&psd, 24), MULTICHANNEL_MODE);
+        assert_eq!(u32_at(&psd, 26), 0);
+
+        // Image resources, each padded to an even length
+        let resources_len = u32_at(&psd, 30) as usize;
+        let resources_end = 34 + resources_len;
+        let mut at = 34;
+        let mut names = vec![];
+        let mut display = vec![];
+        while at < resources_end {
+            assert_eq!(&psd[at..at + 4], b"8BIM");
+            let id = u16_at(&psd, at + 4);
+            let len = u32_at(&psd, at + 8) as usize;
+            let data = &psd[at + 12..at + 12 + len];
+            match id {
+                ALPHA_NAMES => {
+                    let mut rest = data;
+                    while let [len, tail @ ..] = rest {
+                        names.push(String::from_utf8(tail[..*len as usize].to_vec()).unwrap());
+                        rest = &tail[*len as usize..];
+                    }
+                }
+                DISPLAY_INFO => display = data.to_vec(),
+                _ => {}
+            }
+            at += 12 + len + len % 2;
+        }
+        assert_eq!(at, resources_end);
+        assert_eq!(names, ["Blue", "Fluorescent Pink", "yellow"]);
+        // Version, then 14 bytes per channel
+        assert_eq!(display.len(), 4 + 3 * 14);
+        // The first channel previews in Blue, #0078BF
+        assert_eq!(u16_at(&display, 4 + 2), 0);
+        assert_eq!(u16_at(&display, 4 + 4), 0x78 * 257);
+        assert_eq!(u16_at(&display, 4 + 6), 0xBF * 257);
+        assert!(display[4..].chunks(14).all(|c| c[12] == SPOT_CHANNEL));
+
+        // No layers, then RLE image data with a byte count per row
+        assert_eq!(u32_at(&psd, resources_end), 0);
+        let data = resources_end + 4;
+        assert_eq!(u16_at(&psd, data), RLE_COMPRESSION);
+        let rows = (3 * height) as usize;
+        let counts: Vec<usize> = (0..rows)
+            .map(|row| u16_at(&psd, data + 2 + row * 2) as usize)
+            .collect();
+        let mut at = data + 2 + rows * 2;
+        assert_eq!(at + counts.iter().sum::<usize>(), psd.len());
+
+        // Channels follow each other, row by row
+        let expected = plates
+            .iter()
+            .flat_map(|plate| plate.as_raw().chunks_exact(width as usize));
+        for (row, len) in expected.zip(counts) {
+            assert_eq!(unpack_bits(&psd[at..at + len]), row);
+            at += len;
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Misregistration: RISO drums drift by up to a millimetre between passes,
+//! so plates never land exactly on top of each other. This shifts and
+//! rotates plates by a given amount and finds where that would open white
+//! gaps between inks or leave fringes of one ink along another's edge.
+
+use crate::errors::Error;
+use crate::imaging::composite::{composite, composite_preview, plates_with_inks};
+use crate::imaging::plate::{fit_plate, plate_tone, tone_sigma, PlateDepth};
+use crate::imaging::processes::ProcessResult;
+use crate::state::ColorInfo;
+use image::{GrayImage, RgbImage};
+use rayon::prelude::*;
+use std::borrow::Cow;
+
+/// Below this much ink a pixel reads as bare paper
+const PAPER_TONE: f32 = 0.08;
+/// At this much ink a pixel reads as printed
+const INK_TONE: f32 = 0.25;
+/// Change in an ink's tone that shows as a fringe
+const FRINGE_TONE: f32 = 0.25;
+
+/// Longest side, in pixels, plates are checked at. The check keeps a tone
+/// plane per plate before and after the move, which at full resolution
+/// runs to gigabytes for a large job. Fixed rather than tied to the preview
+/// size so the ratios don't change with the window.
+const MAX_CHECK_SIZE: u32 = 2048;
+
+const GAP_COLOR: [u8; 3] = [230, 0, 0];
+const FRINGE_COLOR: [u8; 3] = [0, 150, 255];
+
+/// Where one plate lands relative to where it should. Positive `x` moves it
+/// right, positive `y` down the sheet and positive `rotation` turns it
+/// clockwise about its centre.
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PlateOffset {
+    pub x_mm: f32,
+    pub y_mm: f32,
+    /// In degrees
+    pub rotation: f32,
+}
+
+impl PlateOffset {
+    pub fn is_zero(&self) -> bool {
+        self.x_mm == 0.0 && self.y_mm == 0.0 && self.rotation == 0.0
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RegistrationReport {
+    /// The print with the plates offset, as a PNG data URL
+    pub composite: String,
+    /// The offset print faded, with gaps and fringes marked, as a PNG data URL
+    pub highlights: String,
+    /// Share of the sheet where paper shows through between inks
+    pub gap_ratio: f32,
+    /// Share of the sheet where an ink shows along another ink's edge
+    pub fringe_ratio: f32,
+}
+
+/// Moves and rotates a plate by `offset` at `dpi`, sampling bilinearly.
+/// Whatever moves in from outside the plate is paper.
+pub fn offset_plate(plate: &GrayImage, offset: PlateOffset, dpi: f32) -> GrayImage {
+    let (width, height) = plate.dimensions();
+    let px_per_mm = dpi / 25.4;
+    let (dx, dy) = (offset.x_mm * px_per_mm, offset.y_mm * px_per_mm);
+    let (sin, cos) = offset.rotation.to_radians().sin_cos();
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+
+    let sample = |x: i64, y: i64| -> f32 {
+        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+            255.0
+        } else {
+            plate.as_raw()[y as usize * width as usize + x as usize] as f32
+        }
+    };
+
+    let mut out = GrayImage::new(width, height);
+    out.par_chunks_mut(width.max(1) as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                // Undo the move, then the rotation, to find the source pixel
+                let rx = x as f32 + 0.5 - cx - dx;
+                let ry = y as f32 + 0.5 - cy - dy;
+                let sx = cx + rx * cos + ry * sin - 0.5;
+                let sy = cy - rx * sin + ry * cos - 0.5;
+
+                let (x0, y0) = (sx.floor(), sy.floor());
+                let (tx, ty) = (sx - x0, sy - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = sample(x0, y0) * (1.0 - tx) + sample(x0 + 1, y0) * tx;
+                let bottom = sample(x0, y0 + 1) * (1.0 - tx) + sample(x0 + 1, y0 + 1) * tx;
+                *pixel = (top * (1.0 - ty) + bottom * ty).round() as u8;
+            }
+        });
+    out
+}
+
+/// Plates with their offsets applied. Plates that don't move are borrowed.
+pub fn offset_plates<'a>(
+    plates: &'a [GrayImage],
+    offsets: &[PlateOffset],
+    dpi: f32,
+) -> Vec<Cow<'a, GrayImage>> {
+    plates
+        .iter()
+        .enumerate()
+        .map(|(i, plate)| match offsets.get(i) {
+            Some(offset) if !offset.is_zero() => Cow::Owned(offset_plate(plate, *offset, dpi)),
+            _ => Cow::Borrowed(plate),
+        })
+        .collect()
+}
+
+/// Renders the offset print and marks where it differs from the aligned one
+/// in a way a viewer would notice. Returns the offset composite, the
+/// highlight image and the gap and fringe pixel counts.
+pub fn check_registration(
+    plates: &[GrayImage],
+    depths: &[PlateDepth],
+    inks: &[&str],
+    offsets: &[PlateOffset],
+    dpi: f32,
+) -> Result<(RgbImage, RgbImage, usize, usize), Error> {
+    let shifted = offset_plates(plates, offsets, dpi);
+    let shifted: Vec<GrayImage> = shifted.into_iter().map(Cow::into_owned).collect();
+    let image = composite(&shifted, inks)?;
+
+    // Screens are compared as tone rather than dot by dot
+    let sigma = |i: usize| match depths.get(i) {
+        Some(PlateDepth::Bilevel) => tone_sigma(dpi),
+        _ => 0.0,
+    };
+    let tones = |plates: &[GrayImage]| -> Vec<Vec<f32>> {
+        plates
+            .par_iter()
+            .enumerate()
+            .map(|(i, plate)| plate_tone(plate, sigma(i)))
+            .collect()
+    };
+    let aligned = tones(plates);
+    let moved = tones(&shifted);
+
+    let mut highlights = RgbImage::new(image.width(), image.height());
+    let (gaps, fringes) = highlights
+        .par_chunks_mut(3)
+        .zip(image.par_chunks(3))
+        .enumerate()
+        .map(|(i, (out, print))| {
+            let inked_before = aligned.iter().any(|t| t[i] >= INK_TONE);
+            let inked_after = moved.iter().any(|t| t[i] >= PAPER_TONE);
+            let gap = inked_before && !inked_after;
+
+            // A fringe is an ink appearing or disappearing where another ink
+            // is printed, rather than a lone shape moving over paper
+            let fringe = !gap
+                && (0..plates.len()).any(|p| {
+                    (moved[p][i] - aligned[p][i]).abs() >= FRINGE_TONE
+                        && (0..plates.len()).any(|q| {
+                            q != p && (aligned[q][i] >= INK_TONE || moved[q][i] >= INK_TONE)
+                        })
+                });
+
+            let color = if gap {
+                GAP_COLOR
+            } else if fringe {
+                FRINGE_COLOR
+            } else {
+                // Fade the print so the marks stand out
+                [0, 1, 2].map(|c| 255 - (255 - print[c]) / 3)
+            };
+            out.copy_from_slice(&color);
+            (gap as usize, fringe as usize)
+        })
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+    Ok((image, highlights, gaps, fringes))
+}
+
+/// Loads the plates of a job, offsets them and reports the gaps and fringes
+/// that would show, with previews scaled to fit `max_size` pixels. Plates
+/// larger than `MAX_CHECK_SIZE` are scaled down before they are checked.
+pub fn registration_report(
+    channels: &[ProcessResult],
+    colors: Option<&Vec<ColorInfo>>,
+    offsets: &[PlateOffset],
+    max_size: Option<u32>,
+) -> Result<RegistrationReport, Error> {
+    report_at_size(channels, colors, offsets, max_size, MAX_CHECK_SIZE)
+}
+
+fn report_at_size(
+    channels: &[ProcessResult],
+    colors: Option<&Vec<ColorInfo>>,
+    offsets: &[PlateOffset],
+    max_size: Option<u32>,
+    check_size: u32,
+) -> Result<RegistrationReport, Error> {
+    let (plates, inks) = plates_with_inks(channels, colors)?;
+    let mut scale = 1.0;
+    let plates: Vec<GrayImage> = plates
+        .into_iter()
+        .map(|plate| {
+            let (plate, plate_scale) = fit_plate(plate, check_size);
+            scale = plate_scale;
+            plate
+        })
+        .collect();
+    let depths: Vec<PlateDepth> = channels.iter().map(|c| c.depth).collect();
+    // Offsets are in millimetres, so they shrink with the plates
+    let dpi = channels.first().and_then(|c| c.dpi).unwrap_or(72.0) * scale;
+
+    let (image, highlights, gaps, fringes) =
+        check_registration(&plates, &depths, &inks, offsets, dpi)?;
+    let pixels = (image.width() as usize * image.height() as usize).max(1) as f32;
+
+    Ok(RegistrationReport {
+        composite: composite_preview(&image, max_size)?,
+        highlights: composite_preview(&highlights, max_size)?,
+        gap_ratio: gaps as f32 / pixels,
+        fringe_ratio: fringes as f32 / pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::plate::save_plate_png;
+    use image::Luma;
+
+    /// Two inks that butt up against each other, so moving the second one
+    /// right opens a gap along the join
+    fn abutting_plates(dir: &tempfile::TempDir) -> Vec<ProcessResult> {
+        [(100, 300), (300, 500)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(left, right))| {
+                let plate = GrayImage::from_fn(600, 400, |x, y| {
+                    let inked = (left..right).contains(&x) && (100..300).contains(&y);
+                    Luma([if inked { 0 } else { 255 }])
+                });
+                let path = dir.path().join(format!("{}.png", i));
+                save_plate_png(&plate, PlateDepth::Gray8, Some(300.0), &path).unwrap();
+                ProcessResult {
+                    channel: i.to_string(),
+                    image_path: path.to_string_lossy().to_string(),
+                    depth: PlateDepth::Gray8,
+                    dpi: Some(300.0),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scaled_checks_find_the_same_gaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let channels = abutting_plates(&dir);
+        let offsets = [
+            PlateOffset::default(),
+            PlateOffset {
+                x_mm: 2.0,
+                ..Default::default()
+            },
+        ];
+
+        let full = report_at_size(&channels, None, &offsets, None, 600).unwrap();
+        let half = report_at_size(&channels, None, &offsets, None, 300).unwrap();
+        // 2 mm at 300 dpi is about 24 of the 600 pixels, 200 rows high
+        let expected = 24.0 * 200.0 / (600.0 * 400.0);
+        assert!(
+            (full.gap_ratio - expected).abs() < 0.002,
+            "{}",
+            full.gap_ratio
+        );
+        // Edges soften when scaled, which costs about a pixel of gap
+        assert!(
+            (half.gap_ratio - full.gap_ratio).abs() < full.gap_ratio * 0.2,
+            "{} at half size, {} at full size",
+            half.gap_ratio,
+            full.gap_ratio
+        );
+        assert!(full.fringe_ratio < 0.001);
+    }
+}
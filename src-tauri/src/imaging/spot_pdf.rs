@@ -0,0 +1,427 @@
+//! PDF export where each plate is an image in a `/Separation` colour space
+//! named after its RISO ink, all overprinted on a single page. A RIP treats
+//! every image as its own spot plate, and Acrobat's Output Preview can show
+//! and hide each ink.
+
+use crate::errors::Error;
+use crate::imaging::colormap::ColorMap;
+use crate::imaging::marks::{color_bar, layout_marks, Mark, SlugInfo};
+use crate::imaging::page::Placement;
+use crate::imaging::plate::{load_plate, pack_bilevel, PlateDepth};
+use crate::imaging::processes::ProcessResult;
+use crate::state::{ColorInfo, ExportSettings};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, Stream, StringFormat};
+use std::fs;
+use std::path::Path;
+
+const MM_TO_PT: f32 = 72.0 / 25.4;
+
+// CIE D65 reference white, used for the Lab alternate space
+const WHITE_POINT: [f32; 3] = [0.9505, 1.0, 1.089];
+
+/// Converts an sRGB hex colour to CIE L*a*b* relative to D65
+pub fn hex_to_lab(hex: &str) -> Result<[f32; 3], Error> {
+    let (r, g, b) = ColorMap::hex_to_rgb(hex)?;
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+
+    let x = 0.4124 * r + 0.3576 * g + 0.1805 * b;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = 0.0193 * r + 0.1192 * g + 0.9505 * b;
+
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let fx = f(x / WHITE_POINT[0]);
+    let fy = f(y / WHITE_POINT[1]);
+    let fz = f(z / WHITE_POINT[2]);
+
+    Ok([116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)])
+}
+
+/// Characters WinAnsiEncoding puts at 0x80 to 0x9F, where Latin-1 has
+/// control codes
+const WIN_ANSI_EXTRAS: [(char, u8); 27] = [
+    ('€', 0x80),
+    ('‚', 0x82),
+    ('ƒ', 0x83),
+    ('„', 0x84),
+    ('…', 0x85),
+    ('†', 0x86),
+    ('‡', 0x87),
+    ('ˆ', 0x88),
+    ('‰', 0x89),
+    ('Š', 0x8A),
+    ('‹', 0x8B),
+    ('Œ', 0x8C),
+    ('Ž', 0x8E),
+    ('‘', 0x91),
+    ('’', 0x92),
+    ('“', 0x93),
+    ('”', 0x94),
+    ('•', 0x95),
+    ('–', 0x96),
+    ('—', 0x97),
+    ('˜', 0x98),
+    ('™', 0x99),
+    ('š', 0x9A),
+    ('›', 0x9B),
+    ('œ', 0x9C),
+    ('ž', 0x9E),
+    ('Ÿ', 0x9F),
+];
+
+/// Encodes text for the slug font, which uses WinAnsiEncoding. Characters
+/// it has no code for become `?`.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x20..=0x7E | 0xA0..=0xFF => c as u8,
+            _ => WIN_ANSI_EXTRAS
+                .iter()
+                .find(|(extra, _)| *extra == c)
+                .map_or(b'?', |(_, code)| *code),
+        })
+        .collect()
+}
+
+fn reals(values: &[f32]) -> Object {
+    Object::Array(values.iter().map(|v| Object::Real(*v)).collect())
+}
+
+/// `[/Separation /Name /Lab <<tint transform>>]`: tint 0 is paper white,
+/// tint 1 is the ink's Lab colour
+fn separation_color_space(doc: &mut Document, ink_name: &str, hex: &str) -> Result<Object, Error> {
+    let tint_transform = doc.add_object(dictionary! {
+        "FunctionType" => 2,
+        "Domain" => reals(&[0.0, 1.0]),
+        "C0" => reals(&[100.0, 0.0, 0.0]),
+        "C1" => reals(&hex_to_lab(hex)?),
+        "N" => 1,
+    });
+    let lab = Object::Array(vec![
+        "Lab".into(),
+        Object::Dictionary(dictionary! {
+            "WhitePoint" => reals(&WHITE_POINT),
+            "Range" => reals(&[-128.0, 127.0, -128.0, 127.0]),
+        }),
+    ]);
+
+    Ok(Object::Array(vec![
+        "Separation".into(),
+        Object::Name(ink_name.as_bytes().to_vec()),
+        lab,
+        tint_transform.into(),
+    ]))
+}
+
+/// `[/Separation /All /DeviceGray ...]`: marks in this space print on every
+/// plate, which is what registration targets and crop marks need
+fn all_color_space() -> Object {
+    let tint_transform = Object::Dictionary(dictionary! {
+        "FunctionType" => 2,
+        "Domain" => reals(&[0.0, 1.0]),
+        "C0" => reals(&[1.0]),
+        "C1" => reals(&[0.0]),
+        "N" => 1,
+    });
+    Object::Array(vec![
+        "Separation".into(),
+        "All".into(),
+        "DeviceGray".into(),
+        tint_transform,
+    ])
+}
+
+/// Content stream operators for marks around the trim at `placement`.
+/// Paths and text use the `All` colour space; tint patches keep whichever
+/// colour space is current, so callers select the ink first.
+fn mark_operations(marks: &[Mark], placement: &Placement) -> Vec<Operation> {
+    let pt = |(x, y): (f32, f32)| {
+        vec![
+            Object::Real((placement.x + x) * MM_TO_PT),
+            Object::Real((placement.y + y) * MM_TO_PT),
+        ]
+    };
+
+    let mut operations = vec![];
+    for mark in marks {
+        match mark {
+            Mark::Path { points, closed } => {
+                for (i, point) in points.iter().enumerate() {
+                    let operator = if i == 0 { "m" } else { "l" };
+                    operations.push(Operation::new(operator, pt(*point)));
+                }
+                if *closed {
+                    operations.push(Operation::new("h", vec![]));
+                }
+                operations.push(Operation::new("S", vec![]));
+            }
+            Mark::Patch {
+                x,
+                y,
+                width,
+                height,
+                tint,
+            } => {
+                let mut rect = pt((*x, *y));
+                rect.extend([
+                    Object::Real(width * MM_TO_PT),
+                    Object::Real(height * MM_TO_PT),
+                ]);
+                operations.extend([
+                    Operation::new("scn", vec![Object::Real(*tint)]),
+                    Operation::new("re", rect),
+                    Operation::new("f", vec![]),
+                ]);
+            }
+            Mark::Text { x, y, size, text } => operations.extend([
+                Operation::new("BT", vec![]),
+                // Helvetica's cap height is about 0.72 of its size
+                Operation::new(
+                    "Tf",
+                    vec!["Slug".into(), Object::Real(size / 0.72 * MM_TO_PT)],
+                ),
+                Operation::new("Td", pt((*x, *y))),
+                Operation::new(
+                    "Tj",
+                    vec![Object::String(win_ansi(text), StringFormat::Literal)],
+                ),
+                Operation::new("ET", vec![]),
+            ]),
+        }
+    }
+    operations
+}
+
+pub fn save_channels_to_spot_pdf(
+    channels: &[ProcessResult],
+    export_path: &str,
+    base_filename: &str,
+    colors: Option<&Vec<ColorInfo>>,
+    settings: &ExportSettings,
+) -> Result<(), Error> {
+    let export_dir = Path::new(export_path);
+    if !export_dir.exists() {
+        fs::create_dir_all(export_dir)?;
+    }
+
+    let page = &settings.page;
+    page.validate()?;
+
+    let pdf_path = export_dir.join(format!("{}.pdf", base_filename));
+    let pdf_error = |e: lopdf::Error| Error::Processing(format!("Failed to write PDF: {}", e));
+
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+
+    let mut xobjects = lopdf::Dictionary::new();
+    let mut color_spaces = dictionary! { "All" => all_color_space() };
+    let mut operations = vec![Operation::new("gs", vec!["Overprint".into()])];
+    let mut placement = None;
+    let mut ink_names = vec![];
+
+    for (i, channel) in channels.iter().enumerate() {
+        let plate = load_plate(&channel.image_path)?;
+        let (width, height) = plate.dimensions();
+
+        // Use the RISO ink assigned in the UI, falling back to the process colour
+        let color = colors.and_then(|c| c.get(i));
+        let ink_name = color.map_or(channel.channel.as_str(), |c| c.name.as_str());
+        let hex = color.map_or("#000000", |c| c.hex.as_str());
+        let color_space = separation_color_space(&mut doc, ink_name, hex)?;
+        ink_names.push(ink_name);
+
+        let (bits, data) = match channel.depth {
+            PlateDepth::Gray8 => (8, plate.into_raw()),
+            PlateDepth::Bilevel => (1, pack_bilevel(&plate)),
+        };
+
+        // Plates store paper as the high value, tints use 0 for no ink
+        let mut image = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width,
+                "Height" => height,
+                "ColorSpace" => color_space.clone(),
+                "BitsPerComponent" => bits,
+                "Decode" => reals(&[1.0, 0.0]),
+            },
+            data,
+        );
+        image.compress().map_err(pdf_error)?;
+        let image_id = doc.add_object(image);
+
+        let name = format!("Plate{}", i);
+        xobjects.set(name.as_str(), image_id);
+
+        let plate_placement = page.place(width as usize, height as usize, channel.dpi);
+        operations.extend([
+            Operation::new("q", vec![]),
+            Operation::new(
+                "cm",
+                vec![
+                    Object::Real(plate_placement.width * MM_TO_PT),
+                    0.into(),
+                    0.into(),
+                    Object::Real(plate_placement.height * MM_TO_PT),
+                    Object::Real(plate_placement.x * MM_TO_PT),
+                    Object::Real(plate_placement.y * MM_TO_PT),
+                ],
+            ),
+            Operation::new("Do", vec![name.into()]),
+            Operation::new("Q", vec![]),
+        ]);
+
+        // Each ink's tint bar is drawn in that ink
+        let ink = format!("Ink{}", i);
+        color_spaces.set(ink.as_str(), color_space);
+        let bar = color_bar(&settings.marks, plate_placement.height, i);
+        if !bar.is_empty() {
+            operations.push(Operation::new("cs", vec![ink.into()]));
+            operations.extend(mark_operations(&bar, &plate_placement));
+        }
+        placement = Some(plate_placement);
+    }
+
+    // All plates share a size, so one set of marks fits every one of them
+    if let Some(placement) = placement.filter(|_| settings.marks.any()) {
+        let slug = SlugInfo::new(base_filename, &ink_names.join(", "), None, channels.len());
+        let marks = layout_marks(&settings.marks, placement.width, placement.height, &slug);
+        operations.extend([
+            Operation::new("w", vec![Object::Real(settings.marks.line_weight_pt)]),
+            Operation::new("CS", vec!["All".into()]),
+            Operation::new("SCN", vec![Object::Real(1.0)]),
+            Operation::new("cs", vec!["All".into()]),
+            Operation::new("scn", vec![Object::Real(1.0)]),
+        ]);
+        operations.extend(mark_operations(&marks, &placement));
+    }
+
+    // Overprint every plate instead of knocking out the ones below it
+    let overprint = doc.add_object(dictionary! {
+        "Type" => "ExtGState",
+        "OP" => true,
+        "op" => true,
+        "OPM" => 1,
+    });
+
+    let content = Content { operations }.encode().map_err(pdf_error)?;
+    let content_id = doc.add_object(Stream::new(dictionary! {}, content));
+    let resources_id = doc.add_object(dictionary! {
+        "XObject" => xobjects,
+        "ExtGState" => dictionary! { "Overprint" => overprint },
+        "ColorSpace" => color_spaces,
+        "Font" => dictionary! {
+            "Slug" => dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => "Helvetica",
+                "Encoding" => "WinAnsiEncoding",
+            },
+        },
+    });
+
+    let (media_width, media_height) = page.media_size();
+    let (trim_width, trim_height) = page.trim_size();
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => reals(&[0.0, 0.0, media_width * MM_TO_PT, media_height * MM_TO_PT]),
+        "BleedBox" => reals(&[0.0, 0.0, media_width * MM_TO_PT, media_height * MM_TO_PT]),
+        "TrimBox" => reals(&[
+            page.bleed_mm * MM_TO_PT,
+            page.bleed_mm * MM_TO_PT,
+            (page.bleed_mm + trim_width) * MM_TO_PT,
+            (page.bleed_mm + trim_height) * MM_TO_PT,
+        ]),
+        "Contents" => content_id,
+        "Resources" => resources_id,
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    doc.save(&pdf_path)
+        .map_err(|e| Error::Processing(e.to_string()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slug_text_is_encoded_as_win_ansi() {
+        assert_eq!(win_ansi("Blue 1/2"), b"Blue 1/2");
+        assert_eq!(win_ansi("Café – 50 €"), b"Caf\xE9 \x96 50 \x80");
+        assert_eq!(win_ansi("“ok”™"), b"\x93ok\x94\x99");
+        // No codes for CJK, emoji or control characters
+        assert_eq!(win_ansi("印刷🖨\t"), b"????");
+
+        let placement = Placement {
+            x: 0.0,
+            y: 0.0,
+            width: 100.0,
+            height: 100.0,
+            dpi: 300.0,
+        };
+        let marks = [Mark::Text {
+            x: 0.0,
+            y: 0.0,
+            size: 2.5,
+            text: "Rosé 2/3".to_string(),
+        }];
+        let operations = mark_operations(&marks, &placement);
+        let shown = operations
+            .iter()
+            .find(|op| op.operator == "Tj")
+            .map(|op| op.operands.clone());
+        assert_eq!(
+            shown,
+            Some(vec![Object::String(
+                b"Ros\xE9 2/3".to_vec(),
+                StringFormat::Literal
+            )])
+        );
+    }
+
+    #[test]
+    fn lab_needs_a_valid_colour() {
+        let [l, a, b] = hex_to_lab("#FFFFFF").unwrap();
+        assert!((l - 100.0).abs() < 0.01 && a.abs() < 0.01 && b.abs() < 0.01);
+        assert!(hex_to_lab("#000000").unwrap()[0].abs() < 0.01);
+        for bad in ["", "#", "#FFF", "#GG0000", "#FF48B0FF", "#é48B0"] {
+            assert!(
+                matches!(hex_to_lab(bad), Err(Error::InvalidColor(_))),
+                "{}",
+                bad
+            );
+        }
+        assert_eq!(ColorMap::hex_to_rgb("0078bf").unwrap(), (0x00, 0x78, 0xBF));
+    }
+}
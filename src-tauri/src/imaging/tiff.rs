@@ -0,0 +1,435 @@
+//! Minimal baseline TIFF writer for plates. The `tiff` crate can't write
+//! 1-bit images or CCITT compression, both of which RISO drivers expect
+//! for screened plates, so files are assembled here: one grayscale strip per
+//! page, with several pages chained into one file when asked.
+
+use super::fax::encode_group4;
+use super::plate::{pack_bilevel, PlateDepth};
+use crate::errors::Error;
+use image::{GrayImage, RgbImage};
+use std::fs;
+use std::path::Path;
+
+/// How screened plates are compressed. Continuous tone plates always use LZW.
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum TiffCompression {
+    #[default]
+    Group4,
+    Lzw,
+}
+
+// Tag numbers and field types from the TIFF 6.0 specification
+const NEW_SUBFILE_TYPE: u16 = 254;
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const PHOTOMETRIC: u16 = 262;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const X_RESOLUTION: u16 = 282;
+const Y_RESOLUTION: u16 = 283;
+const PAGE_NAME: u16 = 285;
+const T6_OPTIONS: u16 = 293;
+const RESOLUTION_UNIT: u16 = 296;
+const PAGE_NUMBER: u16 = 297;
+const SOFTWARE: u16 = 305;
+const PREDICTOR: u16 = 317;
+
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+
+/// One plate ready to be written as a TIFF page
+pub struct TiffPage<'a> {
+    pub plate: &'a GrayImage,
+    pub depth: PlateDepth,
+    pub dpi: f32,
+    /// Stored as the page name, shown by most viewers for multi-page files
+    pub name: &'a str,
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl Entry {
+    fn short(tag: u16, values: &[u16]) -> Self {
+        Self {
+            tag,
+            kind: SHORT,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn long(tag: u16, value: u32) -> Self {
+        Self {
+            tag,
+            kind: LONG,
+            count: 1,
+            data: value.to_le_bytes().to_vec(),
+        }
+    }
+
+    fn rational(tag: u16, value: f32) -> Self {
+        // Hundredths keep fractional resolutions like 127.5 exact
+        let numerator = (value * 100.0).round() as u32;
+        let mut data = numerator.to_le_bytes().to_vec();
+        data.extend(100u32.to_le_bytes());
+        Self {
+            tag,
+            kind: RATIONAL,
+            count: 1,
+            data,
+        }
+    }
+
+    fn ascii(tag: u16, text: &str) -> Self {
+        let mut data: Vec<u8> = text.bytes().filter(u8::is_ascii).collect();
+        data.push(0);
+        Self {
+            tag,
+            kind: ASCII,
+            count: data.len() as u32,
+            data,
+        }
+    }
+}
+
+/// A page with its pixels compressed, ready to be laid out in the file
+struct EncodedPage<'a> {
+    width: u32,
+    height: u32,
+    samples: u16,
+    bits: u16,
+    compression: u16,
+    photometric: u16,
+    predictor: bool,
+    strip: Vec<u8>,
+    dpi: f32,
+    name: &'a str,
+}
+
+fn lzw(data: &[u8]) -> Result<Vec<u8>, Error> {
+    weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+        .encode(data)
+        .map_err(|e| Error::Processing(format!("Failed to compress TIFF: {}", e)))
+}
+
+/// Horizontal differencing (predictor 2) over rows of `samples` bytes per
+/// pixel, which makes continuous tone data compress much better with LZW
+fn difference_rows(data: &mut [u8], row_bytes: usize, samples: usize) {
+    if row_bytes == 0 {
+        return;
+    }
+    for row in data.chunks_exact_mut(row_bytes) {
+        for x in (samples..row_bytes).rev() {
+            row[x] = row[x].wrapping_sub(row[x - samples]);
+        }
+    }
+}
+
+fn encode_page<'a>(
+    page: &TiffPage<'a>,
+    compression: TiffCompression,
+) -> Result<EncodedPage<'a>, Error> {
+    let (bits, compression, photometric, strip) = match (page.depth, compression) {
+        // CCITT codes white as 0
+        (PlateDepth::Bilevel, TiffCompression::Group4) => (1, 4, 0, encode_group4(page.plate)),
+        // Packed plates store paper as 1
+        (PlateDepth::Bilevel, TiffCompression::Lzw) => (1, 5, 1, lzw(&pack_bilevel(page.plate))?),
+        (PlateDepth::Gray8, _) => {
+            let mut data = page.plate.as_raw().clone();
+            difference_rows(&mut data, page.plate.width() as usize, 1);
+            (8, 5, 1, lzw(&data)?)
+        }
+    };
+
+    Ok(EncodedPage {
+        width: page.plate.width(),
+        height: page.plate.height(),
+        samples: 1,
+        bits,
+        compression,
+        photometric,
+        predictor: bits == 8,
+        strip,
+        dpi: page.dpi,
+        name: page.name,
+    })
+}
+
+fn align(buffer: &mut Vec<u8>) {
+    if buffer.len() % 2 == 1 {
+        buffer.push(0);
+    }
+}
+
+/// Writes `pages` into one file, chained as a multi-page TIFF when there is
+/// more than one
+pub fn write_tiff(
+    path: &Path,
+    pages: &[TiffPage],
+    compression: TiffCompression,
+) -> Result<(), Error> {
+    let pages = pages
+        .iter()
+        .map(|page| encode_page(page, compression))
+        .collect::<Result<Vec<_>, _>>()?;
+    write_pages(path, &pages)
+}
+
+/// Writes an RGB image, such as a composite of the plates, LZW compressed
+pub fn write_rgb_tiff(path: &Path, image: &RgbImage, dpi: f32) -> Result<(), Error> {
+    let mut data = image.as_raw().clone();
+    difference_rows(&mut data, image.width() as usize * 3, 3);
+
+    let page = EncodedPage {
+        width: image.width(),
+        height: image.height(),
+        samples: 3,
+        bits: 8,
+        compression: 5,
+        photometric: 2,
+        predictor: true,
+        strip: lzw(&data)?,
+        dpi,
+        name: "Composite",
+    };
+    write_pages(path, &[page])
+}
+
+fn write_pages(path: &Path, pages: &[EncodedPage]) -> Result<(), Error> {
+    let mut buffer = b"II*\0".to_vec();
+    // Where the offset to the next IFD goes, starting with the header's
+    let mut next_ifd_at = buffer.len();
+    buffer.extend([0u8; 4]);
+
+    let page_count = pages.len() as u16;
+    for (index, page) in pages.iter().enumerate() {
+        align(&mut buffer);
+        let strip_offset = buffer.len() as u32;
+        buffer.extend(&page.strip);
+
+        let mut entries = vec![
+            Entry::long(NEW_SUBFILE_TYPE, if page_count > 1 { 2 } else { 0 }),
+            Entry::long(IMAGE_WIDTH, page.width),
+            Entry::long(IMAGE_LENGTH, page.height),
+            Entry::short(BITS_PER_SAMPLE, &vec![page.bits; page.samples as usize]),
+            Entry::short(COMPRESSION, &[page.compression]),
+            Entry::short(PHOTOMETRIC, &[page.photometric]),
+            Entry::long(STRIP_OFFSETS, strip_offset),
+            Entry::short(SAMPLES_PER_PIXEL, &[page.samples]),
+            Entry::long(ROWS_PER_STRIP, page.height),
+            Entry::long(STRIP_BYTE_COUNTS, page.strip.len() as u32),
+            Entry::rational(X_RESOLUTION, page.dpi),
+            Entry::rational(Y_RESOLUTION, page.dpi),
+            Entry::ascii(PAGE_NAME, page.name),
+            Entry::short(RESOLUTION_UNIT, &[2]),
+            Entry::ascii(SOFTWARE, "R110"),
+        ];
+        if page.compression == 4 {
+            entries.push(Entry::long(T6_OPTIONS, 0));
+        }
+        if page_count > 1 {
+            entries.push(Entry::short(PAGE_NUMBER, &[index as u16, page_count]));
+        }
+        if page.predictor {
+            entries.push(Entry::short(PREDICTOR, &[2]));
+        }
+        entries.sort_by_key(|e| e.tag);
+
+        // Values over four bytes live outside the IFD
+        let mut offsets = vec![];
+        for entry in &entries {
+            if entry.data.len() > 4 {
+                align(&mut buffer);
+                offsets.push(Some(buffer.len() as u32));
+                buffer.extend(&entry.data);
+            } else {
+                offsets.push(None);
+            }
+        }
+
+        align(&mut buffer);
+        let ifd_offset = buffer.len() as u32;
+        buffer[next_ifd_at..next_ifd_at + 4].copy_from_slice(&ifd_offset.to_le_bytes());
+
+        buffer.extend((entries.len() as u16).to_le_bytes());
+        for (entry, offset) in entries.iter().zip(offsets) {
+            buffer.extend(entry.tag.to_le_bytes());
+            buffer.extend(entry.kind.to_le_bytes());
+            buffer.extend(entry.count.to_le_bytes());
+            match offset {
+                Some(offset) => buffer.extend(offset.to_le_bytes()),
+                None => {
+                    let mut value = [0u8; 4];
+                    value[..entry.data.len()].copy_from_slice(&entry.data);
+                    buffer.extend(value);
+                }
+            }
+        }
+        next_ifd_at = buffer.len();
+        buffer.extend([0u8; 4]);
+    }
+
+    fs::write(path, buffer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+    use std::fs::File;
+    use tiff::decoder::{Decoder, DecodingResult};
+    use tiff::tags::Tag;
+
+    /// A screened plate with short runs, long runs that need makeup codes
+    /// and rows that repeat the one above
+    fn screened_plate(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            let black = match y % 6 {
+                0 => false,
+                1 => true,
+                2 => x < width / 2,
+                3 => (x * 7 + y * 13 + x * y) % 5 < 2,
+                4 => (x / 3 + y) % 2 == 0,
+                _ => x % 97 < 70,
+            };
+            Luma([if black { 0 } else { 255 }])
+        })
+    }
+
+    fn gray_plate(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| Luma([(x * 5 + y * 11) as u8]))
+    }
+
+    fn page<'a>(plate: &'a GrayImage, depth: PlateDepth, name: &'a str) -> TiffPage<'a> {
+        TiffPage {
+            plate,
+            depth,
+            dpi: 600.0,
+            name,
+        }
+    }
+
+    /// Expands 1-bit rows, most significant bit first. The decoder flips
+    /// WhiteIsZero pages, so a set bit is always paper.
+    fn unpack(data: &[u8], width: u32) -> Vec<u8> {
+        data.chunks_exact((width as usize).div_ceil(8))
+            .flat_map(|row| {
+                (0..width as usize).map(move |x| {
+                    if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                        255
+                    } else {
+                        0
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn decode_u8(decoder: &mut Decoder<File>) -> Vec<u8> {
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("expected 8-bit samples"),
+        }
+    }
+
+    fn write_and_open(pages: &[TiffPage], compression: TiffCompression) -> Decoder<File> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plates.tif");
+        write_tiff(&path, pages, compression).unwrap();
+        Decoder::new(File::open(&path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn group4_plates_decode_to_the_same_pixels() {
+        // Wide enough for the extended makeup codes
+        for (width, height) in [(1, 1), (13, 7), (203, 41), (2700, 12)] {
+            let plate = screened_plate(width, height);
+            let mut decoder = write_and_open(
+                &[page(&plate, PlateDepth::Bilevel, "K")],
+                TiffCompression::Group4,
+            );
+            assert_eq!(decoder.dimensions().unwrap(), (width, height));
+            let pixels = unpack(&decode_u8(&mut decoder), width);
+            assert_eq!(pixels, plate.into_raw(), "{}x{}", width, height);
+        }
+    }
+
+    #[test]
+    fn lzw_bilevel_plates_decode_to_the_same_pixels() {
+        let plate = screened_plate(203, 41);
+        let mut decoder = write_and_open(
+            &[page(&plate, PlateDepth::Bilevel, "K")],
+            TiffCompression::Lzw,
+        );
+        let pixels = unpack(&decode_u8(&mut decoder), 203);
+        assert_eq!(pixels, plate.into_raw());
+    }
+
+    #[test]
+    fn gray_plates_decode_to_the_same_pixels() {
+        let plate = gray_plate(131, 29);
+        let mut decoder = write_and_open(
+            &[page(&plate, PlateDepth::Gray8, "K")],
+            TiffCompression::Group4,
+        );
+        assert_eq!(decode_u8(&mut decoder), plate.into_raw());
+    }
+
+    #[test]
+    fn pages_are_chained_with_their_names() {
+        let screened = screened_plate(64, 16);
+        let gray = gray_plate(40, 30);
+        let mut decoder = write_and_open(
+            &[
+                page(&screened, PlateDepth::Bilevel, "Blue"),
+                page(&gray, PlateDepth::Gray8, "Fluorescent Pink"),
+            ],
+            TiffCompression::Group4,
+        );
+
+        assert_eq!(
+            decoder
+                .get_tag_ascii_string(Tag::Unknown(PAGE_NAME))
+                .unwrap(),
+            "Blue"
+        );
+        assert_eq!(unpack(&decode_u8(&mut decoder), 64), screened.into_raw());
+        assert!(decoder.more_images());
+        decoder.next_image().unwrap();
+        assert_eq!(
+            decoder
+                .get_tag_ascii_string(Tag::Unknown(PAGE_NAME))
+                .unwrap(),
+            "Fluorescent Pink"
+        );
+        assert_eq!(decoder.dimensions().unwrap(), (40, 30));
+        assert_eq!(decode_u8(&mut decoder), gray.into_raw());
+        assert!(!decoder.more_images());
+    }
+
+    #[test]
+    fn rgb_images_decode_to_the_same_pixels() {
+        let image = RgbImage::from_fn(57, 23, |x, y| {
+            image::Rgb([(x * 3) as u8, (y * 7) as u8, (x + y) as u8])
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("composite.tif");
+        write_rgb_tiff(&path, &image, 300.0).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (57, 23));
+        assert_eq!(decode_u8(&mut decoder), image.into_raw());
+    }
+}
@@ -0,0 +1,32 @@
+//! Splits images into horizontal tiles so the pipeline can work on a bounded
+//! amount of pixels at a time. Tiles span the full image width; that keeps
+//! every tile a contiguous slice of a plate buffer, which lets rayon hand out
+//! disjoint `&mut` chunks without any copying back.
+
+/// Number of rows in a tile before alignment is applied
+pub const TILE_ROWS: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub y: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    /// Rows to read for this tile when `halo` rows of context are needed on
+    /// each side, clamped to the image
+    pub fn with_halo(&self, halo: u32, image_height: u32) -> Tile {
+        let top = self.y.saturating_sub(halo);
+        let bottom = (self.y + self.height + halo).min(image_height);
+        Tile {
+            y: top,
+            height: bottom - top,
+        }
+    }
+}
+
+/// Tile height rounded down to a multiple of `alignment` (never below it)
+pub fn tile_rows(alignment: u32) -> u32 {
+    let alignment = alignment.max(1);
+    (TILE_ROWS / alignment).max(1) * alignment
+}
@@ -0,0 +1,191 @@
+//! Trapping: where two flat colours on separate drums meet, the lighter ink
+//! is spread a little under the darker one so misregistration shows as a
+//! thin overlap instead of a white gap. The darker ink keeps its shape, so
+//! the overlap is hidden under it.
+
+use crate::errors::Error;
+use crate::imaging::spot_pdf::hex_to_lab;
+use image::GrayImage;
+use rayon::prelude::*;
+
+// Widest trap that still makes sense on a RISO
+const MAX_WIDTH_MM: f32 = 3.0;
+// A grey level used by at least this share of a plate's pixels counts as a tone
+const TONE_SHARE: f32 = 0.001;
+// Plates using more distinct tones than this are treated as photographic
+const PHOTOGRAPHIC_TONES: usize = 48;
+
+/// Overrides the automatic choice for one pair of inks, given as hex colours
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TrapRule {
+    /// Ink that spreads
+    pub spread: String,
+    /// Ink it spreads under
+    pub into: String,
+    /// Width for this pair instead of the default; 0 stops the pair trapping
+    #[serde(default)]
+    pub width_mm: Option<f32>,
+}
+
+impl TrapRule {
+    fn matches(&self, spread: &str, into: &str) -> bool {
+        self.spread.eq_ignore_ascii_case(spread) && self.into.eq_ignore_ascii_case(into)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TrapSettings {
+    pub width_mm: f32,
+    pub rules: Vec<TrapRule>,
+    /// Leave plates with continuous tone, like photos, untrapped
+    pub skip_photographic: bool,
+}
+
+impl Default for TrapSettings {
+    fn default() -> Self {
+        Self {
+            width_mm: 0.25,
+            rules: vec![],
+            skip_photographic: true,
+        }
+    }
+}
+
+impl TrapSettings {
+    pub fn validate(&self) -> Result<(), Error> {
+        let widths = std::iter::once(self.width_mm)
+            .chain(self.rules.iter().filter_map(|rule| rule.width_mm));
+        for width in widths {
+            if !(width.is_finite() && (0.0..=MAX_WIDTH_MM).contains(&width)) {
+                return Err(Error::InvalidTrapping(format!(
+                    "width must be between 0 and {} mm",
+                    MAX_WIDTH_MM
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Trap width from ink `spread` under ink `into`, if it traps at all.
+    /// A rule for the pair wins; otherwise the lighter ink spreads.
+    fn width(&self, spread: &str, into: &str, spread_is_lighter: bool) -> Option<f32> {
+        if let Some(rule) = self.rules.iter().find(|r| r.matches(spread, into)) {
+            return Some(rule.width_mm.unwrap_or(self.width_mm)).filter(|w| *w > 0.0);
+        }
+        if self.rules.iter().any(|r| r.matches(into, spread)) {
+            return None;
+        }
+
+        (spread_is_lighter && self.width_mm > 0.0).then_some(self.width_mm)
+    }
+}
+
+/// Whether a plate holds continuous tone rather than flat colour. Flat
+/// artwork uses a handful of grey levels plus sparse antialiasing; photos
+/// spread over many.
+fn is_photographic(plate: &GrayImage) -> bool {
+    let mut histogram = [0usize; 256];
+    for &value in plate.as_raw() {
+        histogram[value as usize] += 1;
+    }
+    let min_count = (plate.as_raw().len() as f32 * TONE_SHARE).max(1.0) as usize;
+    histogram
+        .iter()
+        .filter(|&&count| count >= min_count)
+        .count()
+        > PHOTOGRAPHIC_TONES
+}
+
+/// Minimum over a square of `radius` pixels around each pixel. Plates store
+/// ink as dark values, so this spreads the ink outwards.
+fn spread(plate: &GrayImage, radius: u32) -> GrayImage {
+    let (width, height) = plate.dimensions();
+    let (w, r) = (width as usize, radius as usize);
+    if w == 0 || height == 0 {
+        return plate.clone();
+    }
+
+    let mut rows = GrayImage::new(width, height);
+    rows.par_chunks_mut(w)
+        .zip(plate.par_chunks(w))
+        .for_each(|(out, row)| {
+            for (x, pixel) in out.iter_mut().enumerate() {
+                let window = &row[x.saturating_sub(r)..(x + r + 1).min(w)];
+                *pixel = window.iter().copied().min().unwrap_or(255);
+            }
+        });
+
+    let rows = rows.as_raw();
+    let mut out = GrayImage::new(width, height);
+    out.par_chunks_mut(w).enumerate().for_each(|(y, out)| {
+        out.fill(255);
+        let end = (y + r + 1).min(height as usize);
+        for row in rows[y.saturating_sub(r) * w..end * w].chunks_exact(w) {
+            for (pixel, &value) in out.iter_mut().zip(row) {
+                *pixel = (*pixel).min(value);
+            }
+        }
+    });
+    out
+}
+
+/// Spreads lighter inks under darker ones. `inks` holds the hex colour of
+/// each plate; plates without one are left alone.
+pub fn trap_plates(
+    plates: &mut [GrayImage],
+    inks: &[&str],
+    settings: &TrapSettings,
+    dpi: f32,
+) -> Result<(), Error> {
+    let count = plates.len().min(inks.len());
+    let lightness = inks[..count]
+        .iter()
+        .map(|hex| hex_to_lab(hex).map(|[l, _, _]| l))
+        .collect::<Result<Vec<f32>, Error>>()?;
+    let skip: Vec<bool> = plates[..count]
+        .par_iter()
+        .map(|plate| settings.skip_photographic && is_photographic(plate))
+        .collect();
+
+    let mut trapped = Vec::new();
+    for light in (0..count).filter(|&i| !skip[i]) {
+        // Only the darker plates' original shapes are used, so the order
+        // plates are trapped in doesn't matter
+        let mut result: Option<GrayImage> = None;
+        let mut spreads: Vec<(u32, GrayImage)> = Vec::new();
+        for dark in (0..count).filter(|&j| j != light && !skip[j]) {
+            let lighter = lightness[light] > lightness[dark];
+            let Some(width_mm) = settings.width(inks[light], inks[dark], lighter) else {
+                continue;
+            };
+            let radius = (width_mm * dpi / 25.4).round().max(1.0) as u32;
+            let index = match spreads.iter().position(|(r, _)| *r == radius) {
+                Some(index) => index,
+                None => {
+                    spreads.push((radius, spread(&plates[light], radius)));
+                    spreads.len() - 1
+                }
+            };
+            let spread = &spreads[index].1;
+            let result = result.get_or_insert_with(|| plates[light].clone());
+
+            // Keep the spread only where the darker ink prints over it
+            result
+                .par_iter_mut()
+                .zip(spread.par_iter())
+                .zip(plates[dark].par_iter())
+                .for_each(|((value, &spread), &dark)| {
+                    *value = (*value).min(spread.max(dark));
+                });
+        }
+        if let Some(result) = result {
+            trapped.push((light, result));
+        }
+    }
+
+    for (index, plate) in trapped {
+        plates[index] = plate;
+    }
+    Ok(())
+}
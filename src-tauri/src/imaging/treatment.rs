@@ -1,6 +1,7 @@
 use crate::errors::Error;
 use crate::imaging::cmyk::{split_channels, CmykChannels};
-use image::{DynamicImage, RgbImage};
+use crate::imaging::filters::ImageFilter;
+use image::{DynamicImage, GrayImage};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -14,16 +15,16 @@ impl<'a> ImageTreatment<'a> {
         Ok(Self { image })
     }
 
-    pub fn process_channel(&self) -> Option<Vec<RgbImage>> {
-        let channels = split_channels(&self.image, CmykChannels::ALL);
+    pub fn process_channel(&self, filter: Option<&dyn ImageFilter>) -> Option<Vec<GrayImage>> {
+        let channels = split_channels(self.image, CmykChannels::ALL, filter);
         if let Some(channels) = channels {
             return Some(channels);
         }
         None
     }
 
-    pub fn process(&self) -> Result<Vec<RgbImage>> {
-        let processed_channels = self.process_channel();
+    pub fn process(&self, filter: Option<&dyn ImageFilter>) -> Result<Vec<GrayImage>> {
+        let processed_channels = self.process_channel(filter);
         if let Some(channels) = processed_channels {
             return Ok(channels);
         }
@@ -0,0 +1,165 @@
+//! Variants: the same source run through several sets of settings, so
+//! treatments can be compared side by side before one is picked for export.
+
+use crate::errors::Error;
+use crate::imaging::processes::ProcessResult;
+use crate::state::ProcessSettings;
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+
+/// Variants generated in one request
+pub const MAX_VARIANTS: usize = 12;
+pub const DEFAULT_CELL_SIZE: u32 = 512;
+
+const GRID_GAP: u32 = 16;
+const GRID_BACKGROUND: Rgb<u8> = Rgb([0xE4, 0xE4, 0xE4]);
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct VariantSpec {
+    pub name: String,
+    pub settings: ProcessSettings,
+}
+
+/// A generated variant kept in state until it is promoted or replaced
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Variant {
+    pub name: String,
+    pub settings: ProcessSettings,
+    pub processed_images: Vec<ProcessResult>,
+}
+
+/// Where a variant's composite sits in the grid, in grid pixels
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GridCell {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Checks a request and returns the specs with trimmed names
+pub fn validate_specs(specs: Vec<VariantSpec>) -> Result<Vec<VariantSpec>, Error> {
+    if specs.is_empty() {
+        return Err(Error::InvalidVariant("no variants requested".to_string()));
+    }
+    if specs.len() > MAX_VARIANTS {
+        return Err(Error::InvalidVariant(format!(
+            "at most {} variants can be generated at once",
+            MAX_VARIANTS
+        )));
+    }
+
+    let mut names: Vec<String> = vec![];
+    let mut cleaned = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let name = spec.name.trim().to_string();
+        if name.is_empty() {
+            return Err(Error::InvalidVariant(
+                "every variant needs a name".to_string(),
+            ));
+        }
+        if names.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+            return Err(Error::InvalidVariant(format!("{} is used twice", name)));
+        }
+        names.push(name.clone());
+        cleaned.push(VariantSpec { name, ..spec });
+    }
+    Ok(cleaned)
+}
+
+/// Scales a composite to fit a `cell_size` square, so a variant's full size
+/// composite needn't be kept once it is made
+pub fn fit_cell(composite: &RgbImage, cell_size: u32) -> RgbImage {
+    let cell_size = cell_size.max(1);
+    let scale = cell_size as f32 / composite.width().max(composite.height()).max(1) as f32;
+    let width = ((composite.width() as f32 * scale).round() as u32).clamp(1, cell_size);
+    let height = ((composite.height() as f32 * scale).round() as u32).clamp(1, cell_size);
+    if (width, height) == composite.dimensions() {
+        return composite.clone();
+    }
+    imageops::resize(composite, width, height, FilterType::Triangle)
+}
+
+/// Lays composites out in a near-square grid, each scaled to fit a
+/// `cell_size` square and centred in it
+pub fn variant_grid(
+    composites: &[(String, RgbImage)],
+    cell_size: u32,
+) -> Result<(RgbImage, Vec<GridCell>), Error> {
+    if composites.is_empty() {
+        return Err(Error::InvalidVariant("no variants to lay out".to_string()));
+    }
+    let cell_size = cell_size.max(1);
+    let columns = (composites.len() as f32).sqrt().ceil() as u32;
+    let rows = (composites.len() as u32).div_ceil(columns);
+    let pitch = cell_size + GRID_GAP;
+
+    let mut grid = RgbImage::from_pixel(
+        columns * pitch + GRID_GAP,
+        rows * pitch + GRID_GAP,
+        GRID_BACKGROUND,
+    );
+    let mut cells = Vec::with_capacity(composites.len());
+    for (i, (name, composite)) in composites.iter().enumerate() {
+        let thumbnail = fit_cell(composite, cell_size);
+        let (width, height) = thumbnail.dimensions();
+
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        let x = GRID_GAP + column * pitch + (cell_size - width) / 2;
+        let y = GRID_GAP + row * pitch + (cell_size - height) / 2;
+        imageops::replace(&mut grid, &thumbnail, x as i64, y as i64);
+        cells.push(GridCell {
+            name: name.clone(),
+            x,
+            y,
+            width,
+            height,
+        });
+    }
+    Ok((grid, cells))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn cells_fit_the_longest_side_and_keep_the_aspect() {
+        let wide = RgbImage::from_pixel(4000, 1000, Rgb([10, 20, 30]));
+        let cell = fit_cell(&wide, 256);
+        assert_eq!(cell.dimensions(), (256, 64));
+        assert_eq!(cell.get_pixel(100, 30), &Rgb([10, 20, 30]));
+        // Fitting again changes nothing
+        assert_eq!(fit_cell(&cell, 256), cell);
+        assert_eq!(fit_cell(&RgbImage::new(3, 600), 256).dimensions(), (1, 256));
+    }
+
+    #[test]
+    fn grid_places_fitted_cells_centred() {
+        let composites = vec![
+            ("wide".to_string(), fit_cell(&RgbImage::new(400, 200), 100)),
+            ("tall".to_string(), RgbImage::new(50, 100)),
+            ("square".to_string(), RgbImage::new(300, 300)),
+        ];
+        let (grid, cells) = variant_grid(&composites, 100).unwrap();
+        let pitch = 100 + GRID_GAP;
+        assert_eq!(
+            grid.dimensions(),
+            (2 * pitch + GRID_GAP, 2 * pitch + GRID_GAP)
+        );
+        let placed: Vec<_> = cells
+            .iter()
+            .map(|c| (c.x, c.y, c.width, c.height))
+            .collect();
+        assert_eq!(
+            placed,
+            [
+                (GRID_GAP, GRID_GAP + 25, 100, 50),
+                (GRID_GAP + pitch + 25, GRID_GAP, 50, 100),
+                (GRID_GAP, GRID_GAP + pitch, 100, 100),
+            ]
+        );
+    }
+}
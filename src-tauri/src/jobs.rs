@@ -0,0 +1,155 @@
+use crate::errors::Error;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Shared flag used to ask a running job to stop at its next checkpoint
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Channel separation started when an image is selected
+    Separation,
+    /// Full pipeline run from `process_selected_image`
+    Processing,
+    /// Several pipeline runs from `generate_variants`
+    Variants,
+    /// Files processed and exported by `run_batch`
+    Batch,
+    /// Hot folder started by `start_watch`, running until stopped
+    Watch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStage {
+    Loading,
+    Resampling,
+    Separating,
+    Knockout,
+    Trapping,
+    Effects,
+    Saving,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct JobProgress {
+    pub job_id: u64,
+    pub kind: JobKind,
+    pub stage: JobStage,
+    pub channel: Option<String>,
+    /// Progress within the whole job, from 0.0 to 1.0
+    pub progress: f32,
+}
+
+type Reporter = Arc<dyn Fn(JobProgress) + Send + Sync>;
+
+/// Everything a running job needs to report progress and honour cancellation.
+/// Kept free of Tauri types so the imaging pipeline can run without an app.
+#[derive(Clone)]
+pub struct JobContext {
+    id: u64,
+    kind: JobKind,
+    token: CancellationToken,
+    reporter: Reporter,
+}
+
+impl JobContext {
+    pub fn new(
+        id: u64,
+        kind: JobKind,
+        token: CancellationToken,
+        reporter: impl Fn(JobProgress) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            id,
+            kind,
+            token,
+            reporter: Arc::new(reporter),
+        }
+    }
+
+    pub fn report(&self, stage: JobStage, channel: Option<&str>, progress: f32) {
+        (self.reporter)(JobProgress {
+            job_id: self.id,
+            kind: self.kind,
+            stage,
+            channel: channel.map(str::to_string),
+            progress: progress.clamp(0.0, 1.0),
+        });
+    }
+
+    /// Returns `Error::Cancelled` once the job has been cancelled or superseded
+    pub fn checkpoint(&self) -> Result<(), Error> {
+        if self.token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the latest job of each kind. Starting a new job cancels the one it
+/// replaces, and results are only accepted from the job that is still current.
+#[derive(Debug, Default)]
+pub struct JobManager {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<JobKind, (u64, CancellationToken)>>,
+}
+
+impl JobManager {
+    pub fn start(&self, kind: JobKind) -> (u64, CancellationToken) {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let token = CancellationToken::new();
+        let mut active = self.active.lock().unwrap();
+        if let Some((_, previous)) = active.insert(kind, (id, token.clone())) {
+            previous.cancel();
+        }
+        (id, token)
+    }
+
+    pub fn is_current(&self, kind: JobKind, id: u64) -> bool {
+        self.active
+            .lock()
+            .unwrap()
+            .get(&kind)
+            .is_some_and(|(current, token)| *current == id && !token.is_cancelled())
+    }
+
+    pub fn finish(&self, kind: JobKind, id: u64) {
+        let mut active = self.active.lock().unwrap();
+        if active.get(&kind).is_some_and(|(current, _)| *current == id) {
+            active.remove(&kind);
+        }
+    }
+
+    pub fn is_running(&self, kind: JobKind) -> bool {
+        self.active.lock().unwrap().contains_key(&kind)
+    }
+
+    /// Cancels the running job of `kind`, returning whether there was one
+    pub fn cancel(&self, kind: JobKind) -> bool {
+        match self.active.lock().unwrap().remove(&kind) {
+            Some((_, token)) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -1,16 +1,29 @@
+
+mod batch;
+pub mod cli;
+#[cfg(feature = "app")]
 mod commands;
 mod errors;
+#[cfg(feature = "app")]
+mod history;
 mod imaging;
+mod jobs;
+mod presets;
+mod project;
 mod state;
+mod watch;
 
-use commands::*;
-use state::create_state;
-use tauri::Builder;
-
+#[cfg(feature = "app")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    use commands::*;
+    use jobs::JobManager;
+    use state::create_state;
+    use tauri::Builder;
+
     Builder::default()
         .manage(create_state())
+        .manage(JobManager::default())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
@@ -20,8 +33,41 @@ pub fn run() {
             get_processing_status,
             read_processed_images,
             process_selected_image,
+            undo_processing,
+            redo_processing,
+            go_to_history,
+            get_history,
+            generate_variants,
+            list_variants,
+            read_variant_plates,
+            delete_variant,
+            promote_variant,
+            cancel_processing,
+            get_export_settings,
+            set_export_settings,
             export_channels,
+            get_composite_preview,
+            export_composite,
+            get_proof_preview,
+            check_misregistration,
+            get_coverage_report,
+            estimate_job_cost,
+            export_proof,
             save_composed_image,
+            save_project,
+            open_project,
+            list_presets,
+            save_preset,
+            apply_preset,
+            rename_preset,
+            delete_preset,
+            import_preset,
+            export_preset,
+            run_batch,
+            cancel_batch,
+            start_watch,
+            stop_watch,
+            get_watch_status,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
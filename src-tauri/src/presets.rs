@@ -0,0 +1,213 @@
+//! Named presets of `ProcessSettings`. Each preset is a small JSON file in
+//! the app config dir, and the same file is what gets exported and imported,
+//! so presets can be passed around a team as attachments.
+
+use crate::errors::Error;
+use crate::state::ProcessSettings;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PRESET_EXTENSION: &str = "r110preset";
+pub const PRESET_VERSION: u32 = 1;
+
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Preset {
+    pub version: u32,
+    pub name: String,
+    pub settings: ProcessSettings,
+}
+
+/// Checks a preset name and returns it trimmed
+fn clean_name(name: &str) -> Result<String, Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::InvalidPreset("the name is empty".to_string()));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(Error::InvalidPreset(format!(
+            "names are limited to {} characters",
+            MAX_NAME_LEN
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/// A file name for `name` that works on every platform
+fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Reads a preset file, wherever it is
+pub fn read_preset(path: &Path) -> Result<Preset, Error> {
+    let data = fs::read(path)?;
+    let preset: Preset =
+        serde_json::from_slice(&data).map_err(|e| Error::InvalidPreset(e.to_string()))?;
+    if preset.version == 0 || preset.version > PRESET_VERSION {
+        return Err(Error::InvalidPreset(format!(
+            "version {} isn't supported by this version of R110",
+            preset.version
+        )));
+    }
+    Ok(Preset {
+        name: clean_name(&preset.name)?,
+        ..preset
+    })
+}
+
+fn write_preset(path: &Path, preset: &Preset) -> Result<(), Error> {
+    let data =
+        serde_json::to_vec_pretty(preset).map_err(|e| Error::InvalidPreset(e.to_string()))?;
+    fs::write(path, data)?;
+    Ok(())
+}
+
+/// The presets saved in one folder
+pub struct PresetStore {
+    dir: PathBuf,
+}
+
+impl PresetStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Every readable preset with its file, sorted by name. Files that don't
+    /// parse are skipped so one bad file doesn't hide the rest.
+    fn entries(&self) -> Result<Vec<(PathBuf, Preset)>, Error> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut entries: Vec<(PathBuf, Preset)> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == PRESET_EXTENSION))
+            .filter_map(|path| match read_preset(&path) {
+                Ok(preset) => Some((path, preset)),
+                Err(e) => {
+                    log::warn!("Skipping preset {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        entries.sort_by_key(|(_, preset)| preset.name.to_lowercase());
+        Ok(entries)
+    }
+
+    fn find(&self, name: &str) -> Result<Option<(PathBuf, Preset)>, Error> {
+        let name = name.trim();
+        Ok(self
+            .entries()?
+            .into_iter()
+            .find(|(_, preset)| preset.name.eq_ignore_ascii_case(name)))
+    }
+
+    /// A path in the store no other preset is using
+    fn free_path(&self, name: &str) -> PathBuf {
+        let stem = match file_stem(name) {
+            stem if stem.is_empty() => "preset".to_string(),
+            stem => stem,
+        };
+        let mut path = self.dir.join(format!("{}.{}", stem, PRESET_EXTENSION));
+        let mut n = 2;
+        while path.exists() {
+            path = self
+                .dir
+                .join(format!("{}_{}.{}", stem, n, PRESET_EXTENSION));
+            n += 1;
+        }
+        path
+    }
+
+    pub fn list(&self) -> Result<Vec<Preset>, Error> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .map(|(_, preset)| preset)
+            .collect())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Preset, Error> {
+        self.find(name)?
+            .map(|(_, preset)| preset)
+            .ok_or_else(|| Error::PresetNotFound(name.to_string()))
+    }
+
+    /// Saves `settings` as `name`, replacing a preset of that name
+    pub fn save(&self, name: &str, settings: ProcessSettings) -> Result<Preset, Error> {
+        let name = clean_name(name)?;
+        fs::create_dir_all(&self.dir)?;
+        let path = match self.find(&name)? {
+            Some((path, _)) => path,
+            None => self.free_path(&name),
+        };
+
+        let preset = Preset {
+            version: PRESET_VERSION,
+            name,
+            settings,
+        };
+        write_preset(&path, &preset)?;
+        Ok(preset)
+    }
+
+    pub fn rename(&self, name: &str, new_name: &str) -> Result<Preset, Error> {
+        let new_name = clean_name(new_name)?;
+        let (path, preset) = self
+            .find(name)?
+            .ok_or_else(|| Error::PresetNotFound(name.to_string()))?;
+        // Changing only the case is fine, clashing with another preset isn't
+        if let Some((other, _)) = self.find(&new_name)? {
+            if other != path {
+                return Err(Error::InvalidPreset(format!(
+                    "a preset named {} already exists",
+                    new_name
+                )));
+            }
+        }
+
+        let preset = Preset {
+            name: new_name,
+            ..preset
+        };
+        let new_path = self.free_path(&preset.name);
+        write_preset(&new_path, &preset)?;
+        fs::remove_file(&path)?;
+        Ok(preset)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), Error> {
+        let (path, _) = self
+            .find(name)?
+            .ok_or_else(|| Error::PresetNotFound(name.to_string()))?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Copies a preset file into the store. A preset with the same name is
+    /// kept, and the imported one gets a numbered name instead.
+    pub fn import(&self, path: &Path) -> Result<Preset, Error> {
+        let preset = read_preset(path)?;
+        let mut name = preset.name.clone();
+        let mut n = 2;
+        while self.find(&name)?.is_some() {
+            name = format!("{} ({})", preset.name, n);
+            n += 1;
+        }
+        self.save(&name, preset.settings)
+    }
+
+    pub fn export(&self, name: &str, path: &Path) -> Result<(), Error> {
+        write_preset(path, &self.get(name)?)
+    }
+}
@@ -0,0 +1,505 @@
+//! `.r110` project files. A project is a zip holding `manifest.json` with
+//! the source image's path and hash, the process settings (inks included)
+//! and the export settings, plus optional copies of the source and the
+//! processed plates so a job can be reopened on another machine.
+
+use crate::errors::Error;
+use crate::imaging::plate::PlateDepth;
+use crate::imaging::processes::ProcessResult;
+use crate::state::{AppStateInner, ExportSettings, ProcessSettings};
+use chrono::{Datelike, Timelike};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub const PROJECT_EXTENSION: &str = "r110";
+/// Bump when the manifest changes in a way `serde(default)` can't absorb,
+/// and add a migration from the previous version
+pub const PROJECT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+
+/// Upgrades a manifest by one version, in place
+type Migration = fn(&mut Value) -> Result<(), Error>;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` manifest to version `n + 2`
+const MIGRATIONS: [Migration; PROJECT_VERSION as usize - 1] = [];
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SourceImage {
+    pub path: String,
+    pub name: String,
+    pub image_type: String,
+    /// SHA-256 of the file, hex encoded
+    pub sha256: String,
+    /// Archive entry holding a copy of the file
+    #[serde(default)]
+    pub embedded: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProjectPlate {
+    pub channel: String,
+    pub depth: PlateDepth,
+    pub dpi: Option<f32>,
+    /// Archive entry holding the plate PNG
+    pub entry: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Project {
+    pub version: u32,
+    pub source: SourceImage,
+    #[serde(default)]
+    pub process_settings: Option<ProcessSettings>,
+    #[serde(default)]
+    pub export_settings: ExportSettings,
+    /// Embedded plates; empty when they weren't saved
+    #[serde(default)]
+    pub plates: Vec<ProjectPlate>,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SaveOptions {
+    pub embed_source: bool,
+    pub embed_plates: bool,
+}
+
+/// A project read back from disk, with embedded files extracted
+#[derive(Debug, Clone)]
+pub struct OpenedProject {
+    pub project: Project,
+    /// Where the source can be read now; a temporary copy when it was embedded
+    pub source_path: String,
+    pub processed_images: Option<Vec<ProcessResult>>,
+    /// The source on disk no longer matches the one the project was saved with
+    pub source_modified: bool,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// File name part of an archive entry, so entries can't escape the folder
+/// they are extracted to
+fn entry_file_name(entry: &str) -> Result<&str, Error> {
+    Path::new(entry)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::InvalidProject(format!("bad entry name {}", entry)))
+}
+
+fn zip_error(e: ZipError) -> Error {
+    Error::InvalidProject(e.to_string())
+}
+
+/// Local time as zip stores it; the format only covers 1980 to 2107
+fn zip_timestamp() -> zip::DateTime {
+    let now = chrono::Local::now();
+    zip::DateTime::from_date_and_time(
+        now.year().clamp(1980, 2107) as u16,
+        now.month() as u8,
+        now.day() as u8,
+        now.hour() as u8,
+        now.minute() as u8,
+        now.second() as u8,
+    )
+    .unwrap_or_default()
+}
+
+/// Adds a file to the archive. Plates and most sources are already
+/// compressed images, so only text is deflated.
+fn add_entry(
+    zip: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    data: &[u8],
+    compress: bool,
+) -> Result<(), Error> {
+    let method = if compress {
+        CompressionMethod::Deflated
+    } else {
+        CompressionMethod::Stored
+    };
+    let options = SimpleFileOptions::default()
+        .compression_method(method)
+        .last_modified_time(zip_timestamp())
+        .large_file(data.len() as u64 >= u32::MAX as u64);
+    zip.start_file(name, options).map_err(zip_error)?;
+    zip.write_all(data)?;
+    Ok(())
+}
+
+/// Saves the current job to `path`
+pub fn save_project(
+    state: &AppStateInner,
+    path: &Path,
+    options: &SaveOptions,
+) -> Result<(), Error> {
+    let source_path = state.image_path.as_ref().ok_or(Error::NoImageSelected)?;
+    let source_data = fs::read(source_path)?;
+    let name = state.image_name.clone().unwrap_or_default();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut source = SourceImage {
+        path: source_path.clone(),
+        name: name.clone(),
+        image_type: state.image_type.clone().unwrap_or_default(),
+        sha256: sha256_hex(&source_data),
+        embedded: None,
+    };
+    if options.embed_source {
+        let entry = format!("source/{}", entry_file_name(&name).unwrap_or("source"));
+        add_entry(&mut zip, &entry, &source_data, false)?;
+        source.embedded = Some(entry);
+    }
+
+    let mut plates = vec![];
+    if options.embed_plates {
+        for (i, plate) in state.processed_images.iter().flatten().enumerate() {
+            let entry = format!("plates/{}_{}.png", i, plate.channel);
+            add_entry(&mut zip, &entry, &fs::read(&plate.image_path)?, false)?;
+            plates.push(ProjectPlate {
+                channel: plate.channel.clone(),
+                depth: plate.depth,
+                dpi: plate.dpi,
+                entry,
+            });
+        }
+    }
+
+    let project = Project {
+        version: PROJECT_VERSION,
+        source,
+        process_settings: state.process_settings.clone(),
+        export_settings: state.export_settings.clone(),
+        plates,
+    };
+    let manifest =
+        serde_json::to_vec_pretty(&project).map_err(|e| Error::InvalidProject(e.to_string()))?;
+    add_entry(&mut zip, MANIFEST, &manifest, true)?;
+
+    let archive = zip.finish().map_err(zip_error)?.into_inner();
+    fs::write(path, archive)?;
+    Ok(())
+}
+
+/// Brings a manifest from any earlier version up to `PROJECT_VERSION`
+fn migrate(mut manifest: Value) -> Result<Value, Error> {
+    let version = manifest
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| Error::InvalidProject("missing version".to_string()))?;
+    if version == 0 || version > PROJECT_VERSION as u64 {
+        return Err(Error::InvalidProject(format!(
+            "version {} isn't supported by this version of R110",
+            version
+        )));
+    }
+
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(&mut manifest)?;
+    }
+    manifest["version"] = PROJECT_VERSION.into();
+    Ok(manifest)
+}
+
+/// Reads a project, extracting embedded files to a temporary folder
+pub fn open_project(path: &Path) -> Result<OpenedProject, Error> {
+    let mut archive = ZipArchive::new(Cursor::new(fs::read(path)?)).map_err(zip_error)?;
+    let mut take = |entry: &str| -> Result<Vec<u8>, Error> {
+        let mut file = archive.by_name(entry).map_err(|e| match e {
+            ZipError::FileNotFound => Error::InvalidProject(format!("{} is missing", entry)),
+            e => zip_error(e),
+        })?;
+        // Reading to the end checks the entry's CRC
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(|e| Error::InvalidProject(format!("{} is corrupt: {}", entry, e)))?;
+        Ok(data)
+    };
+
+    let manifest: Value = serde_json::from_slice(&take(MANIFEST)?)
+        .map_err(|e| Error::InvalidProject(e.to_string()))?;
+    let project: Project = serde_json::from_value(migrate(manifest)?)
+        .map_err(|e| Error::InvalidProject(e.to_string()))?;
+
+    let timestamp = chrono::Local::now().timestamp_millis();
+    let folder: PathBuf = env::temp_dir().join(format!("r110_project_{}", timestamp));
+    let mut extract = |entry: &str| -> Result<String, Error> {
+        let data = take(entry)?;
+        fs::create_dir_all(&folder)?;
+        let path = folder.join(entry_file_name(entry)?);
+        fs::write(&path, data)?;
+        Ok(path.to_string_lossy().to_string())
+    };
+
+    let source = &project.source;
+    let (source_path, source_modified) = match &source.embedded {
+        Some(entry) => (extract(entry)?, false),
+        None => {
+            let data = fs::read(&source.path).map_err(|_| {
+                Error::InvalidProject(format!("source image {} not found", source.path))
+            })?;
+            (source.path.clone(), sha256_hex(&data) != source.sha256)
+        }
+    };
+
+    let processed_images = if project.plates.is_empty() {
+        None
+    } else {
+        let plates = project
+            .plates
+            .iter()
+            .map(|plate| {
+                Ok(ProcessResult {
+                    channel: plate.channel.clone(),
+                    image_path: extract(&plate.entry)?,
+                    depth: plate.depth,
+                    dpi: plate.dpi,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Some(plates)
+    };
+
+    Ok(OpenedProject {
+        project,
+        source_path,
+        processed_images,
+        source_modified,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::plate::save_plate_png;
+    use crate::state::{ColorInfo, ImageEffect};
+    use image::{GrayImage, Luma, RgbImage};
+    use tempfile::TempDir;
+
+    /// A job with a source image and two plates on disk
+    fn job(dir: &TempDir) -> AppStateInner {
+        let source = dir.path().join("page.png");
+        RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8 * 16, y as u8 * 32, 90]))
+            .save(&source)
+            .unwrap();
+
+        let mut plates = vec![];
+        for (i, (channel, depth)) in [("cyan", PlateDepth::Gray8), ("black", PlateDepth::Bilevel)]
+            .into_iter()
+            .enumerate()
+        {
+            let path = dir.path().join(format!("plate_{}.png", i));
+            let plate =
+                GrayImage::from_fn(16, 8, |x, y| Luma([if (x + y) % 3 == 0 { 0 } else { 255 }]));
+            save_plate_png(&plate, depth, Some(300.0), &path).unwrap();
+            plates.push(ProcessResult {
+                channel: channel.to_string(),
+                image_path: path.to_string_lossy().to_string(),
+                depth,
+                dpi: Some(300.0),
+            });
+        }
+
+        let mut state = AppStateInner {
+            image_path: Some(source.to_string_lossy().to_string()),
+            image_name: Some("page.png".to_string()),
+            image_type: Some("image/png".to_string()),
+            process_settings: Some(ProcessSettings {
+                effect: Some(ImageEffect::HalfTone),
+                filter: None,
+                colors: Some(vec![ColorInfo {
+                    hex: "#0078BF".to_string(),
+                    name: "Blue".to_string(),
+                    cost_per_ml: Some(0.4),
+                }]),
+                output: None,
+                trapping: None,
+                knockout: None,
+            }),
+            processed_images: Some(plates),
+            ..Default::default()
+        };
+        state.export_settings.marks.registration = true;
+        state
+    }
+
+    fn save(dir: &TempDir, state: &AppStateInner, options: SaveOptions) -> PathBuf {
+        let path = dir.path().join("job.r110");
+        save_project(state, &path, &options).unwrap();
+        path
+    }
+
+    fn open_error(path: &Path) -> String {
+        match open_project(path) {
+            Err(Error::InvalidProject(message)) => message,
+            other => panic!(
+                "expected an invalid project, got {:?}",
+                other.map(|p| p.source_path)
+            ),
+        }
+    }
+
+    #[test]
+    fn embedded_files_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = job(&dir);
+        let path = save(
+            &dir,
+            &state,
+            SaveOptions {
+                embed_source: true,
+                embed_plates: true,
+            },
+        );
+        // Moving the originals away shows the embedded copies are used
+        let source = state.image_path.clone().unwrap();
+        let source_data = fs::read(&source).unwrap();
+        fs::remove_file(&source).unwrap();
+
+        let opened = open_project(&path).unwrap();
+        assert_eq!(fs::read(&opened.source_path).unwrap(), source_data);
+        assert!(!opened.source_modified);
+        assert_eq!(opened.project.version, PROJECT_VERSION);
+        assert!(opened.project.export_settings.marks.registration);
+        let settings = opened.project.process_settings.unwrap();
+        assert!(matches!(settings.effect, Some(ImageEffect::HalfTone)));
+        assert_eq!(settings.colors.unwrap()[0].cost_per_ml, Some(0.4));
+
+        let plates = opened.processed_images.unwrap();
+        let saved = state.processed_images.unwrap();
+        assert_eq!(plates.len(), saved.len());
+        for (plate, saved) in plates.iter().zip(&saved) {
+            assert_eq!(plate.channel, saved.channel);
+            assert_eq!(plate.depth, saved.depth);
+            assert_eq!(plate.dpi, saved.dpi);
+            assert_eq!(
+                fs::read(&plate.image_path).unwrap(),
+                fs::read(&saved.image_path).unwrap()
+            );
+        }
+        let _ = fs::remove_dir_all(Path::new(&opened.source_path).parent().unwrap());
+    }
+
+    #[test]
+    fn linked_sources_are_checked_against_their_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = job(&dir);
+        let path = save(&dir, &state, SaveOptions::default());
+        let source = state.image_path.unwrap();
+
+        let opened = open_project(&path).unwrap();
+        assert_eq!(opened.source_path, source);
+        assert!(!opened.source_modified);
+        assert!(opened.processed_images.is_none());
+
+        RgbImage::new(3, 3).save(&source).unwrap();
+        assert!(open_project(&path).unwrap().source_modified);
+
+        fs::remove_file(&source).unwrap();
+        assert!(open_error(&path).contains("not found"));
+    }
+
+    #[test]
+    fn corrupt_entries_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = job(&dir);
+        let path = save(
+            &dir,
+            &state,
+            SaveOptions {
+                embed_source: true,
+                embed_plates: false,
+            },
+        );
+
+        // The source is stored, so its bytes appear as they are
+        let source = fs::read(state.image_path.unwrap()).unwrap();
+        let mut archive = fs::read(&path).unwrap();
+        let at = archive
+            .windows(source.len())
+            .position(|window| window == source.as_slice())
+            .unwrap();
+        archive[at + source.len() / 2] ^= 0xFF;
+        fs::write(&path, archive).unwrap();
+
+        assert!(open_error(&path).contains("corrupt"));
+    }
+
+    #[test]
+    fn truncated_archives_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = save(&dir, &job(&dir), SaveOptions::default());
+        let archive = fs::read(&path).unwrap();
+        for len in [0, 4, archive.len() / 2, archive.len() - 1] {
+            fs::write(&path, &archive[..len]).unwrap();
+            open_error(&path);
+        }
+    }
+
+    #[test]
+    fn archives_with_a_comment_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = save(&dir, &job(&dir), SaveOptions::default());
+
+        // The comment length is the last field of the end record
+        let comment = b"saved by another tool";
+        let mut archive = fs::read(&path).unwrap();
+        let len = archive.len();
+        archive[len - 2..].copy_from_slice(&(comment.len() as u16).to_le_bytes());
+        archive.extend(comment);
+        fs::write(&path, archive).unwrap();
+
+        assert!(open_project(&path)
+            .unwrap()
+            .project
+            .process_settings
+            .is_some());
+    }
+
+    #[test]
+    fn archives_without_a_manifest_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.r110");
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        add_entry(&mut zip, "notes.txt", b"hello", true).unwrap();
+        fs::write(&path, zip.finish().unwrap().into_inner()).unwrap();
+
+        assert_eq!(open_error(&path), "manifest.json is missing");
+    }
+
+    #[test]
+    fn manifests_are_migrated_to_the_current_version() {
+        let manifest = serde_json::json!({ "version": 1, "source": {} });
+        let migrated = migrate(manifest.clone()).unwrap();
+        assert_eq!(migrated["version"], PROJECT_VERSION);
+        assert_eq!(migrated["source"], manifest["source"]);
+
+        for manifest in [
+            serde_json::json!({ "version": 0 }),
+            serde_json::json!({ "version": PROJECT_VERSION + 1 }),
+            serde_json::json!({ "version": "1" }),
+            serde_json::json!({}),
+        ] {
+            assert!(migrate(manifest).is_err());
+        }
+    }
+
+    #[test]
+    fn entries_cant_escape_the_extraction_folder() {
+        assert_eq!(entry_file_name("plates/0_cyan.png").unwrap(), "0_cyan.png");
+        assert_eq!(entry_file_name("../../.bashrc").unwrap(), ".bashrc");
+        assert!(entry_file_name("..").is_err());
+    }
+}
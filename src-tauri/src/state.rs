@@ -1,4 +1,6 @@
-use std::sync::Mutex;
+use crate::errors::Error;
+use std::collections::HashSet;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum ImageEffect {
@@ -25,6 +27,9 @@ pub enum ImageFilter {
 pub struct ColorInfo {
     pub hex: String,
     pub name: String,
+    /// Price of a millilitre of the ink, from the ink library
+    #[serde(default)]
+    pub cost_per_ml: Option<f32>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -32,14 +37,63 @@ pub struct ProcessSettings {
     pub effect: Option<ImageEffect>,
     pub filter: Option<ImageFilter>,
     pub colors: Option<Vec<ColorInfo>>,
+    /// Physical print size; `None` keeps the source resolution
+    #[serde(default)]
+    pub output: Option<crate::imaging::output::OutputSize>,
+    /// Spreads lighter inks under darker ones; `None` leaves plates as
+    /// separated
+    #[serde(default)]
+    pub trapping: Option<crate::imaging::trapping::TrapSettings>,
+    /// Which inks knock out the plates below them; `None` overprints all
+    #[serde(default)]
+    pub knockout: Option<crate::imaging::knockout::KnockoutSettings>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TiffSettings {
+    pub compression: crate::imaging::tiff::TiffCompression,
+    /// Write every plate as a page of one file instead of a file per plate
+    pub multi_page: bool,
+}
+
+/// Options applied when plates are exported
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    pub marks: crate::imaging::marks::MarkSettings,
+    pub page: crate::imaging::page::PageSetup,
+    pub tiff: TiffSettings,
+    pub job_sheet: crate::imaging::job_sheet::JobSheetSettings,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
 pub enum ProcessingStatus {
+    #[default]
     Idle,
     Processing,
     Completed,
     Failed,
+    Cancelled,
+}
+
+impl ProcessingStatus {
+    /// Any state may start a new run (a newer job supersedes a running one),
+    /// but only a running job can finish, fail or be cancelled.
+    pub fn can_transition_to(&self, next: &ProcessingStatus) -> bool {
+        use ProcessingStatus::*;
+        match next {
+            Processing | Idle => true,
+            Completed | Failed | Cancelled => *self == Processing,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            ProcessingStatus::Completed | ProcessingStatus::Failed | ProcessingStatus::Cancelled
+        )
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -52,16 +106,129 @@ pub struct AppStateInner {
     pub processed_images: Option<Vec<crate::imaging::processes::ProcessResult>>,
     pub preprocessed_channels: Option<Vec<crate::imaging::processes::ProcessResult>>,
     pub processing_status: ProcessingStatus,
+    pub processing_error: Option<String>,
+    pub export_settings: ExportSettings,
+    /// Settings of earlier runs on this image, for undo and redo
+    pub history: crate::history::History,
+    /// Named variants generated from this image
+    pub variants: Vec<crate::imaging::variants::Variant>,
+    pub watch: crate::watch::WatchStatus,
 }
 
-impl Default for ProcessingStatus {
-    fn default() -> Self {
-        ProcessingStatus::Idle
+impl AppStateInner {
+    /// Moves to `next`, recording the error that caused a failure if any
+    pub fn transition(
+        &mut self,
+        next: ProcessingStatus,
+        error: Option<String>,
+    ) -> Result<(), Error> {
+        if !self.processing_status.can_transition_to(&next) {
+            return Err(Error::InvalidStatusTransition {
+                from: self.processing_status.clone(),
+                to: next,
+            });
+        }
+        self.processing_status = next;
+        self.processing_error = error;
+        Ok(())
+    }
+
+    /// Plate files the history let go of that nothing shows or uses any
+    /// more, for the caller to delete. Those still in use are handed back to
+    /// the history to be offered again later.
+    pub fn unused_plates(&mut self) -> Vec<String> {
+        let released = self.history.take_released();
+        let in_use: HashSet<&str> = self
+            .processed_images
+            .iter()
+            .chain(&self.preprocessed_channels)
+            .flatten()
+            .chain(self.variants.iter().flat_map(|v| &v.processed_images))
+            .map(|plate| plate.image_path.as_str())
+            .chain(self.history.cached_plates())
+            .collect();
+        let (mut kept, unused): (Vec<String>, Vec<String>) = released
+            .into_iter()
+            .partition(|path| in_use.contains(path.as_str()));
+        kept.sort();
+        kept.dedup();
+        self.history.keep_released(kept);
+        unused
+    }
+
+    /// File name of the selected image without its extension
+    pub fn base_name(&self) -> Option<String> {
+        self.image_name.as_ref().map(|n| {
+            n.rsplit_once('.')
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_else(|| n.clone())
+        })
     }
 }
 
-pub type AppState = Mutex<AppStateInner>;
+/// Shared application state. Commands take short read/write locks to copy
+/// what they need and never hold a guard across dialogs, file IO or
+/// processing, so other commands stay responsive during long operations.
+#[derive(Debug, Default)]
+pub struct AppState(RwLock<AppStateInner>);
+
+impl AppState {
+    pub fn read(&self) -> RwLockReadGuard<'_, AppStateInner> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, AppStateInner> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Copy of the current state for work that runs without the lock
+    pub fn snapshot(&self) -> AppStateInner {
+        self.read().clone()
+    }
+}
 
 pub fn create_state() -> AppState {
-    Mutex::new(AppStateInner::default())
+    AppState::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::plate::PlateDepth;
+    use crate::imaging::processes::ProcessResult;
+
+    fn plates(run: usize) -> Vec<ProcessResult> {
+        vec![ProcessResult {
+            channel: "cyan".to_string(),
+            image_path: format!("run{}.png", run),
+            depth: PlateDepth::Gray8,
+            dpi: None,
+        }]
+    }
+
+    fn settings() -> ProcessSettings {
+        ProcessSettings {
+            effect: None,
+            filter: None,
+            colors: None,
+            output: None,
+            trapping: None,
+            knockout: None,
+        }
+    }
+
+    #[test]
+    fn plates_in_use_are_kept_until_let_go() {
+        let mut state = AppStateInner::default();
+        for run in 0..=crate::history::CACHED_RESULTS + 1 {
+            state.history.push(settings(), plates(run));
+        }
+        // The two oldest runs are evicted; one is still on screen
+        state.processed_images = Some(plates(0));
+        assert_eq!(state.unused_plates(), ["run1.png"]);
+        assert!(state.unused_plates().is_empty());
+
+        state.processed_images = Some(plates(2));
+        assert_eq!(state.unused_plates(), ["run0.png"]);
+    }
 }
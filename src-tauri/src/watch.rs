@@ -0,0 +1,447 @@
+//! Hot folder: watches a directory and runs every image dropped into it
+//! through the pipeline with fixed settings. Originals are moved to a done
+//! or failed folder once handled, and each job is appended to a log in the
+//! output folder.
+//!
+//! The folder is polled rather than watched through OS events, which works
+//! the same on network shares. A file is only picked up once its size and
+//! modification time hold between two polls, so scans still being copied
+//! in are left alone.
+
+use crate::batch::{collect_images, process_file, render_template, DEFAULT_FILENAME_TEMPLATE};
+use crate::errors::Error;
+use crate::imaging::export::ExportFormat;
+use crate::jobs::{CancellationToken, JobContext, JobKind};
+use crate::state::{ExportSettings, ProcessSettings};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const DEFAULT_POLL_SECONDS: f32 = 2.0;
+pub const LOG_FILE: &str = "r110-watch.log";
+/// Jobs the app keeps in memory for its status view
+pub const MAX_RECENT_JOBS: usize = 200;
+
+/// How often a sleeping watcher checks for cancellation
+const CANCEL_CHECK: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WatchSettings {
+    pub folder: String,
+    pub output_dir: String,
+    /// Where handled originals go; `done` inside the folder when unset
+    pub done_dir: Option<String>,
+    /// Where originals that failed go; `failed` inside the folder when unset
+    pub failed_dir: Option<String>,
+    pub format: ExportFormat,
+    /// Same placeholders as a batch; `{index}` counts jobs since the start
+    pub filename_template: String,
+    pub poll_seconds: f32,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            folder: String::new(),
+            output_dir: String::new(),
+            done_dir: None,
+            failed_dir: None,
+            format: ExportFormat::default(),
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            poll_seconds: DEFAULT_POLL_SECONDS,
+        }
+    }
+}
+
+/// One handled file, as written to the log
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WatchJob {
+    pub source: String,
+    /// Where the original was moved to
+    pub moved_to: Option<String>,
+    /// Base name the exports were written under
+    pub output: String,
+    pub error: Option<String>,
+    pub finished_at: String,
+    pub seconds: f32,
+}
+
+/// The app's hot folder, as shown in its status view
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct WatchStatus {
+    pub running: bool,
+    pub settings: Option<WatchSettings>,
+    pub preset: Option<String>,
+    /// Most recent jobs last
+    pub jobs: Vec<WatchJob>,
+    /// Why the watch stopped, when it wasn't stopped on purpose
+    pub error: Option<String>,
+}
+
+impl WatchStatus {
+    pub fn record(&mut self, job: WatchJob) {
+        if self.jobs.len() >= MAX_RECENT_JOBS {
+            self.jobs.remove(0);
+        }
+        self.jobs.push(job);
+    }
+}
+
+/// Folders a watch works with, resolved and checked
+struct WatchFolders {
+    folder: PathBuf,
+    output: PathBuf,
+    done: PathBuf,
+    failed: PathBuf,
+}
+
+fn same_folder(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Checks the settings and creates the output, done and failed folders, so
+/// mistakes surface before a watch is started in the background
+pub fn prepare_folders(settings: &WatchSettings) -> Result<(), Error> {
+    WatchFolders::resolve(settings).map(|_| ())
+}
+
+impl WatchFolders {
+    fn resolve(settings: &WatchSettings) -> Result<Self, Error> {
+        let invalid = |message: &str| Err(Error::InvalidWatch(message.to_string()));
+        if !(settings.poll_seconds.is_finite() && settings.poll_seconds > 0.0) {
+            return invalid("the poll interval must be positive");
+        }
+
+        let folder = PathBuf::from(&settings.folder);
+        if !folder.is_dir() {
+            return Err(Error::InvalidWatch(format!(
+                "{} is not a folder",
+                settings.folder
+            )));
+        }
+        if settings.output_dir.trim().is_empty() {
+            return invalid("an output folder is needed");
+        }
+        let output = PathBuf::from(&settings.output_dir);
+        let done = settings
+            .done_dir
+            .as_ref()
+            .map_or_else(|| folder.join("done"), PathBuf::from);
+        let failed = settings
+            .failed_dir
+            .as_ref()
+            .map_or_else(|| folder.join("failed"), PathBuf::from);
+
+        for dir in [&output, &done, &failed] {
+            fs::create_dir_all(dir)?;
+        }
+        // Anything written back into the watched folder would be picked up
+        // again as a new scan
+        if [&output, &done, &failed]
+            .iter()
+            .any(|dir| same_folder(dir, &folder))
+        {
+            return invalid("output, done and failed folders must differ from the watched folder");
+        }
+
+        Ok(Self {
+            folder,
+            output,
+            done,
+            failed,
+        })
+    }
+}
+
+/// Moves a file, copying when the destination is on another volume. An
+/// existing file of the same name is kept and the new one numbered.
+fn move_file(file: &Path, dir: &Path) -> Result<PathBuf, Error> {
+    let name = file
+        .file_name()
+        .ok_or_else(|| Error::InvalidWatch(format!("{} has no file name", file.display())))?;
+    let mut target = dir.join(name);
+    let mut n = 2;
+    while target.exists() {
+        let stem = file.file_stem().unwrap_or(name).to_string_lossy();
+        target = match file.extension() {
+            Some(ext) => dir.join(format!("{}_{}.{}", stem, n, ext.to_string_lossy())),
+            None => dir.join(format!("{}_{}", stem, n)),
+        };
+        n += 1;
+    }
+
+    if fs::rename(file, &target).is_err() {
+        fs::copy(file, &target)?;
+        fs::remove_file(file)?;
+    }
+    Ok(target)
+}
+
+/// `base` numbered until no file in `dir` starts with it, so a scan dropped
+/// in twice doesn't overwrite the first one's exports
+fn unused_base(dir: &Path, base: &str) -> Result<String, Error> {
+    let taken: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    let in_use = |candidate: &str| {
+        taken.iter().any(|name| {
+            name.strip_prefix(candidate)
+                .is_some_and(|rest| rest.starts_with('_') || rest.starts_with('.'))
+        })
+    };
+
+    let mut name = base.to_string();
+    let mut n = 2;
+    while in_use(&name) {
+        name = format!("{}_{}", base, n);
+        n += 1;
+    }
+    Ok(name)
+}
+
+fn append_log(output: &Path, job: &WatchJob) -> Result<(), Error> {
+    let line = match &job.error {
+        None => format!(
+            "{} ok {} -> {} ({:.1}s)\n",
+            job.finished_at, job.source, job.output, job.seconds
+        ),
+        Some(error) => format!(
+            "{} failed {}: {} ({:.1}s)\n",
+            job.finished_at, job.source, error, job.seconds
+        ),
+    };
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output.join(LOG_FILE))?;
+    log.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Size and modification time, to tell when a file has stopped changing
+fn fingerprint(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Files from this poll that are ready to process: their fingerprint held
+/// since the last poll. The rest are kept in `pending` for the next one;
+/// `stuck` files are left alone.
+fn stable_files(
+    files: Vec<PathBuf>,
+    pending: &mut HashMap<PathBuf, (u64, SystemTime)>,
+    stuck: &HashSet<PathBuf>,
+) -> Vec<PathBuf> {
+    let mut seen = HashMap::new();
+    let mut ready = Vec::new();
+    for file in files {
+        if stuck.contains(&file) {
+            continue;
+        }
+        let Some(print) = fingerprint(&file) else {
+            continue;
+        };
+        if pending.get(&file) == Some(&print) {
+            ready.push(file);
+        } else {
+            seen.insert(file, print);
+        }
+    }
+    *pending = seen;
+    ready
+}
+
+/// Sleeps for `duration`, returning early with `Error::Cancelled` when the
+/// token is cancelled
+fn wait(duration: Duration, token: &CancellationToken) -> Result<(), Error> {
+    let until = Instant::now() + duration;
+    while Instant::now() < until {
+        if token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        std::thread::sleep(CANCEL_CHECK.min(until.saturating_duration_since(Instant::now())));
+    }
+    Ok(())
+}
+
+/// Watches `settings.folder` until `token` is cancelled, calling `on_job`
+/// after each file, and then returns `Error::Cancelled`. Other errors mean
+/// the watch itself can't go on, such as the folder going away; files that
+/// fail are logged and moved like any other.
+#[allow(clippy::too_many_arguments)]
+pub fn watch_folder(
+    settings: &WatchSettings,
+    process_settings: &ProcessSettings,
+    export_settings: &ExportSettings,
+    preset: &str,
+    job_id: u64,
+    token: &CancellationToken,
+    mut on_job: impl FnMut(&WatchJob),
+) -> Result<(), Error> {
+    let folders = WatchFolders::resolve(settings)?;
+    let poll = Duration::from_secs_f32(settings.poll_seconds);
+    let ctx = JobContext::new(job_id, JobKind::Watch, token.clone(), |_| {});
+    // Files seen on the last poll, and files that couldn't be moved away
+    let mut pending: HashMap<PathBuf, (u64, SystemTime)> = HashMap::new();
+    let mut stuck: HashSet<PathBuf> = HashSet::new();
+    let mut count = 0;
+
+    loop {
+        let files = collect_images(&folders.folder)?;
+        for file in stable_files(files, &mut pending, &stuck) {
+            let started = Instant::now();
+            let result = render_template(&settings.filename_template, &file, count, 1, preset)
+                .and_then(|base| unused_base(&folders.output, &base))
+                .and_then(|base| {
+                    process_file(
+                        &file,
+                        process_settings,
+                        export_settings,
+                        settings.format,
+                        &folders.output,
+                        &base,
+                        &ctx,
+                    )
+                    .map(|_| base)
+                });
+            // Stopping mid-file leaves it in place for the next run
+            if matches!(result, Err(Error::Cancelled)) {
+                return Err(Error::Cancelled);
+            }
+            count += 1;
+
+            let (output, mut error) = match result {
+                Ok(base) => (base, None),
+                Err(e) => (String::new(), Some(e.to_string())),
+            };
+            let target = if error.is_none() {
+                &folders.done
+            } else {
+                &folders.failed
+            };
+            let moved_to = match move_file(&file, target) {
+                Ok(moved) => Some(moved.to_string_lossy().to_string()),
+                Err(e) => {
+                    stuck.insert(file.clone());
+                    let message = format!("couldn't move the original: {}", e);
+                    error = Some(match error {
+                        Some(error) => format!("{}; {}", error, message),
+                        None => message,
+                    });
+                    None
+                }
+            };
+
+            let job = WatchJob {
+                source: file.to_string_lossy().to_string(),
+                moved_to,
+                output,
+                error,
+                finished_at: chrono::Local::now().to_rfc3339(),
+                seconds: started.elapsed().as_secs_f32(),
+            };
+            if let Err(e) = append_log(&folders.output, &job) {
+                log::warn!("Failed to write the watch log: {}", e);
+            }
+            on_job(&job);
+        }
+
+        wait(poll, token)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn bases_in_use_are_numbered() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(unused_base(dir.path(), "scan").unwrap(), "scan");
+
+        for name in ["scan.pdf", "scan_2_cyan.png", "scans.pdf"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        // `scans` only shares a prefix, so it doesn't hold up `scan_3`
+        assert_eq!(unused_base(dir.path(), "scan").unwrap(), "scan_3");
+        assert_eq!(unused_base(dir.path(), "scans").unwrap(), "scans_2");
+        assert_eq!(unused_base(dir.path(), "sca").unwrap(), "sca");
+    }
+
+    #[test]
+    fn moved_files_keep_what_is_already_there() {
+        let dir = tempfile::tempdir().unwrap();
+        let done = dir.path().join("done");
+        fs::create_dir(&done).unwrap();
+
+        for (i, name) in ["page.png", "page.png", "page.png", "notes"]
+            .iter()
+            .enumerate()
+        {
+            let file = dir.path().join(name);
+            fs::write(&file, i.to_string()).unwrap();
+            move_file(&file, &done).unwrap();
+            assert!(!file.exists());
+        }
+        fs::write(dir.path().join("notes"), b"again").unwrap();
+        let moved = move_file(&dir.path().join("notes"), &done).unwrap();
+
+        assert_eq!(moved, done.join("notes_2"));
+        assert_eq!(
+            names(&done),
+            ["notes", "notes_2", "page.png", "page_2.png", "page_3.png"]
+        );
+        assert_eq!(fs::read_to_string(done.join("page.png")).unwrap(), "0");
+        assert_eq!(fs::read_to_string(done.join("page_3.png")).unwrap(), "2");
+    }
+
+    #[test]
+    fn files_are_picked_up_once_they_stop_changing() {
+        let dir = tempfile::tempdir().unwrap();
+        let still = dir.path().join("still.png");
+        let growing = dir.path().join("growing.png");
+        let blocked = dir.path().join("blocked.png");
+        for file in [&still, &growing, &blocked] {
+            fs::write(file, b"scan").unwrap();
+        }
+        let files = || vec![still.clone(), growing.clone(), blocked.clone()];
+        let stuck = HashSet::from([blocked.clone()]);
+        let mut pending = HashMap::new();
+
+        // Nothing is ready on the first poll it is seen on
+        assert!(stable_files(files(), &mut pending, &stuck).is_empty());
+        assert_eq!(pending.len(), 2);
+
+        fs::write(&growing, b"scan, still copying").unwrap();
+        assert_eq!(
+            stable_files(files(), &mut pending, &stuck),
+            vec![still.clone()]
+        );
+        // Handled files drop out, files still changing wait another poll
+        assert_eq!(pending.len(), 1);
+        assert!(pending.contains_key(&growing));
+
+        fs::remove_file(&still).unwrap();
+        assert_eq!(
+            stable_files(files(), &mut pending, &stuck),
+            vec![growing.clone()]
+        );
+        assert!(pending.is_empty());
+    }
+}
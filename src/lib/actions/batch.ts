@@ -0,0 +1,46 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import type {
+  BatchInput,
+  BatchOptions,
+  BatchProgress,
+  BatchReport,
+} from "../types";
+
+// Without an input or output folder the backend asks for them; null when
+// either dialog is cancelled
+export async function runBatch(
+  preset: string,
+  input?: BatchInput,
+  outputDir?: string,
+  options?: Partial<BatchOptions>,
+  onProgress?: (progress: BatchProgress) => void,
+): Promise<BatchReport | null> {
+  const unlisten = onProgress
+    ? await listen<BatchProgress>("batch-progress", (event) =>
+        onProgress(event.payload),
+      )
+    : undefined;
+  try {
+    return await invoke<BatchReport | null>("run_batch", {
+      preset,
+      input: input ?? null,
+      output_dir: outputDir ?? null,
+      options: options ?? null,
+    });
+  } catch (error) {
+    console.error("Error running batch:", error);
+    return null;
+  } finally {
+    unlisten?.();
+  }
+}
+
+export async function cancelBatch(): Promise<boolean> {
+  try {
+    return await invoke<boolean>("cancel_batch");
+  } catch (error) {
+    console.error("Error cancelling batch:", error);
+    return false;
+  }
+}
@@ -0,0 +1,48 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { HistoryListing, HistoryResponse } from "../types";
+import { useStore } from "../stores/useStore.svelte";
+import { applyProcessSettings, showProcessedImages } from "./image";
+
+// Steps may reprocess an entry whose plates were evicted, so they show
+// progress like any other run
+async function step(
+  command: string,
+  args: Record<string, unknown> = {},
+): Promise<HistoryListing | null> {
+  useStore.setIsProcessing(true);
+  try {
+    const result = await invoke<HistoryResponse>(command, args);
+    applyProcessSettings(result.process_settings);
+    await showProcessedImages(
+      result.processed_images,
+      result.process_settings.colors?.map((color) => color.hex) ?? [],
+    );
+    return result.history;
+  } catch (error) {
+    console.error("Error moving through history:", error);
+    return null;
+  } finally {
+    useStore.setIsProcessing(false);
+  }
+}
+
+export function undoProcessing() {
+  return step("undo_processing");
+}
+
+export function redoProcessing() {
+  return step("redo_processing");
+}
+
+export function goToHistory(id: number) {
+  return step("go_to_history", { id });
+}
+
+export async function getHistory(): Promise<HistoryListing | null> {
+  try {
+    return await invoke<HistoryListing>("get_history");
+  } catch (error) {
+    console.error("Error reading history:", error);
+    return null;
+  }
+}
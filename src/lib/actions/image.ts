@@ -1,13 +1,28 @@
-import { type AppResponse, useStore } from "../stores/useStore.svelte";
+import {
+  type AppResponse,
+  ImageEffect,
+  type ImageFilter,
+  useStore,
+} from "../stores/useStore.svelte";
 import { invoke } from "@tauri-apps/api/core";
-import type { Channels, ProcessedImages } from "../types";
+import type {
+  Channels,
+  ColorInfo,
+  CostEstimate,
+  CostSettings,
+  CoverageReport,
+  ExportSettings,
+  PlateOffset,
+  ProcessData,
+  ProcessedImages,
+  ProcessSettings,
+  ProjectResponse,
+  ProofSettings,
+  RegistrationReport,
+  SaveOptions,
+} from "../types";
 import { useColors } from "../stores/useColors.svelte";
 
-interface ColorInfo {
-  hex: string;
-  name: string;
-}
-
 let isSelectingImage = false;
 
 async function convertAndSetImageData(
@@ -63,6 +78,34 @@ export async function selectImage() {
   }
 }
 
+// Reads the plates the backend now holds and puts them on screen
+export async function showProcessedImages(
+  processed: ProcessData[],
+  colors: string[],
+) {
+  const images = processed.length
+    ? await handleProcessedImagesRead()
+    : undefined;
+  useStore.setProcessedImages(
+    processed.map((image, index) => ({
+      ...image,
+      image_data: images ? `data:image/png;base64,${images[index][0]}` : null,
+    })),
+  );
+  useStore.setActiveColors(images ? colors : []);
+  useStore.resetColormapCache();
+}
+
+// Loads saved settings into the controls; the image isn't reprocessed
+export function applyProcessSettings(settings: ProcessSettings) {
+  useStore.processState.colors =
+    settings.colors?.map((color) => color.hex) ?? [];
+  useStore.processState.effect =
+    (settings.effect as ImageEffect) ?? ImageEffect.Original;
+  useStore.processState.filter = settings.filter as ImageFilter | null;
+  useStore.processState.shouldFilter = settings.filter !== null;
+}
+
 export async function submitProcessData() {
   if (!useStore.imagePath) {
     console.error("No image selected");
@@ -82,6 +125,7 @@ export async function submitProcessData() {
             return {
               hex,
               name: colorData?.name || hex,
+              cost_per_ml: colorData?.cost_per_ml ?? null,
             };
           })
         : null;
@@ -92,7 +136,7 @@ export async function submitProcessData() {
       filter: filter || null,
     };
 
-    const { image_type, processed_images } = await invoke<AppResponse>(
+    const { processed_images } = await invoke<AppResponse>(
       "process_selected_image",
       {
         process_data,
@@ -106,7 +150,7 @@ export async function submitProcessData() {
             if (image.channel === images[index][1]) {
               return {
                 ...image,
-                image_data: `data:image/${image_type};base64,${images[index][0]}`,
+                image_data: `data:image/png;base64,${images[index][0]}`,
               } as ProcessedImages;
             }
             return undefined;
@@ -159,3 +203,144 @@ export async function saveComposedImage(canvas: HTMLCanvasElement) {
     console.error("Error saving composed image:", error);
   }
 }
+
+export async function getCompositePreview(maxSize?: number): Promise<string> {
+  try {
+    return await invoke<string>("get_composite_preview", {
+      max_size: maxSize ?? null,
+    });
+  } catch (error) {
+    console.error("Error rendering composite:", error);
+    return "";
+  }
+}
+
+export async function exportComposite(format: "Png" | "Jpeg" | "Tiff") {
+  try {
+    await invoke("export_composite", { format });
+  } catch (error) {
+    console.error("Error exporting composite:", error);
+  }
+}
+
+export async function getProofPreview(
+  settings: ProofSettings,
+  maxSize?: number,
+): Promise<string> {
+  try {
+    return await invoke<string>("get_proof_preview", {
+      settings,
+      max_size: maxSize ?? null,
+    });
+  } catch (error) {
+    console.error("Error rendering proof:", error);
+    return "";
+  }
+}
+
+export async function checkMisregistration(
+  offsets: PlateOffset[],
+  maxSize?: number,
+): Promise<RegistrationReport | null> {
+  try {
+    return await invoke<RegistrationReport>("check_misregistration", {
+      offsets,
+      max_size: maxSize ?? null,
+    });
+  } catch (error) {
+    console.error("Error checking misregistration:", error);
+    return null;
+  }
+}
+
+export async function getCoverageReport(
+  tacLimit?: number,
+  maxSize?: number,
+): Promise<CoverageReport | null> {
+  try {
+    return await invoke<CoverageReport>("get_coverage_report", {
+      tac_limit: tacLimit ?? null,
+      max_size: maxSize ?? null,
+    });
+  } catch (error) {
+    console.error("Error measuring coverage:", error);
+    return null;
+  }
+}
+
+export async function getExportSettings(): Promise<ExportSettings | null> {
+  try {
+    return await invoke<ExportSettings>("get_export_settings");
+  } catch (error) {
+    console.error("Error reading export settings:", error);
+    return null;
+  }
+}
+
+// Marks, page setup, TIFF options and the job sheet used by every export
+export async function setExportSettings(
+  settings: ExportSettings,
+): Promise<boolean> {
+  try {
+    await invoke("set_export_settings", { settings });
+    return true;
+  } catch (error) {
+    console.error("Error saving export settings:", error);
+    return false;
+  }
+}
+
+export async function estimateJobCost(
+  settings: CostSettings,
+): Promise<CostEstimate | null> {
+  try {
+    return await invoke<CostEstimate>("estimate_job_cost", { settings });
+  } catch (error) {
+    console.error("Error estimating cost:", error);
+    return null;
+  }
+}
+
+export async function exportProof(
+  settings: ProofSettings,
+  format: "Png" | "Jpeg" | "Tiff",
+) {
+  try {
+    await invoke("export_proof", { settings, format });
+  } catch (error) {
+    console.error("Error exporting proof:", error);
+  }
+}
+
+export async function saveProject(options: SaveOptions) {
+  try {
+    await invoke("save_project", { options });
+  } catch (error) {
+    console.error("Error saving project:", error);
+  }
+}
+
+export async function openProject(): Promise<ProjectResponse | null> {
+  try {
+    const result = await invoke<ProjectResponse>("open_project");
+    useStore.setImagePath(result.image_path);
+    useStore.setImageName(result.image_name);
+    await convertAndSetImageData(
+      await handleImageProcessing(),
+      result.image_type,
+    );
+    useStore.resetProcessState();
+    useStore.resetColormapCache();
+
+    const settings = result.process_settings;
+    if (settings) applyProcessSettings(settings);
+    await showProcessedImages(
+      result.processed_images ?? [],
+      settings?.colors?.map((color) => color.hex) ?? [],
+    );
+    return result;
+  } catch (error) {
+    console.error("Error opening project:", error);
+    return null;
+  }
+}
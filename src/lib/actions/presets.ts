@@ -0,0 +1,76 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { Preset, ProcessSettings } from "../types";
+import { applyProcessSettings } from "./image";
+
+export async function listPresets(): Promise<Preset[]> {
+  try {
+    return await invoke<Preset[]>("list_presets");
+  } catch (error) {
+    console.error("Error listing presets:", error);
+    return [];
+  }
+}
+
+// Without settings the backend saves the ones used for the last run
+export async function savePreset(
+  name: string,
+  settings?: ProcessSettings,
+): Promise<Preset | null> {
+  try {
+    return await invoke<Preset>("save_preset", {
+      name,
+      settings: settings ?? null,
+    });
+  } catch (error) {
+    console.error("Error saving preset:", error);
+    return null;
+  }
+}
+
+export async function applyPreset(name: string): Promise<boolean> {
+  try {
+    const settings = await invoke<ProcessSettings>("apply_preset", { name });
+    applyProcessSettings(settings);
+    return true;
+  } catch (error) {
+    console.error("Error applying preset:", error);
+    return false;
+  }
+}
+
+export async function renamePreset(
+  name: string,
+  newName: string,
+): Promise<Preset | null> {
+  try {
+    return await invoke<Preset>("rename_preset", { name, new_name: newName });
+  } catch (error) {
+    console.error("Error renaming preset:", error);
+    return null;
+  }
+}
+
+export async function deletePreset(name: string) {
+  try {
+    await invoke("delete_preset", { name });
+  } catch (error) {
+    console.error("Error deleting preset:", error);
+  }
+}
+
+export async function importPreset(): Promise<Preset | null> {
+  try {
+    return await invoke<Preset | null>("import_preset");
+  } catch (error) {
+    console.error("Error importing preset:", error);
+    return null;
+  }
+}
+
+export async function exportPreset(name: string) {
+  try {
+    await invoke("export_preset", { name });
+  } catch (error) {
+    console.error("Error exporting preset:", error);
+  }
+}
@@ -0,0 +1,76 @@
+import { invoke } from "@tauri-apps/api/core";
+import type {
+  HistoryListing,
+  HistoryResponse,
+  Variant,
+  VariantSpec,
+  VariantsResponse,
+} from "../types";
+import { applyProcessSettings, showProcessedImages } from "./image";
+
+export async function generateVariants(
+  variants: VariantSpec[],
+  cellSize?: number,
+): Promise<VariantsResponse | null> {
+  try {
+    return await invoke<VariantsResponse>("generate_variants", {
+      variants,
+      cell_size: cellSize ?? null,
+    });
+  } catch (error) {
+    console.error("Error generating variants:", error);
+    return null;
+  }
+}
+
+export async function listVariants(): Promise<Variant[]> {
+  try {
+    return await invoke<Variant[]>("list_variants");
+  } catch (error) {
+    console.error("Error listing variants:", error);
+    return [];
+  }
+}
+
+// Plates as data URLs keyed by channel, for showing one variant's plates
+export async function readVariantPlates(
+  name: string,
+): Promise<[string, string][]> {
+  try {
+    const plates = await invoke<[string, string][]>("read_variant_plates", {
+      name,
+    });
+    return plates.map(([data, channel]) => [
+      `data:image/png;base64,${data}`,
+      channel,
+    ]);
+  } catch (error) {
+    console.error("Error reading variant plates:", error);
+    return [];
+  }
+}
+
+export async function deleteVariant(name: string) {
+  try {
+    await invoke("delete_variant", { name });
+  } catch (error) {
+    console.error("Error deleting variant:", error);
+  }
+}
+
+export async function promoteVariant(
+  name: string,
+): Promise<HistoryListing | null> {
+  try {
+    const result = await invoke<HistoryResponse>("promote_variant", { name });
+    applyProcessSettings(result.process_settings);
+    await showProcessedImages(
+      result.processed_images,
+      result.process_settings.colors?.map((color) => color.hex) ?? [],
+    );
+    return result.history;
+  } catch (error) {
+    console.error("Error promoting variant:", error);
+    return null;
+  }
+}
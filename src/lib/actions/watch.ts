@@ -0,0 +1,51 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+import type { WatchJob, WatchSettings, WatchStatus } from "../types";
+
+export async function startWatch(
+  preset: string,
+  settings: Partial<WatchSettings> & Pick<WatchSettings, "folder" | "output_dir">,
+): Promise<WatchStatus | null> {
+  try {
+    return await invoke<WatchStatus>("start_watch", { preset, settings });
+  } catch (error) {
+    console.error("Error starting watch:", error);
+    return null;
+  }
+}
+
+export async function stopWatch(): Promise<WatchStatus | null> {
+  try {
+    return await invoke<WatchStatus>("stop_watch");
+  } catch (error) {
+    console.error("Error stopping watch:", error);
+    return null;
+  }
+}
+
+export async function getWatchStatus(): Promise<WatchStatus | null> {
+  try {
+    return await invoke<WatchStatus>("get_watch_status");
+  } catch (error) {
+    console.error("Error reading watch status:", error);
+    return null;
+  }
+}
+
+// Calls back for each handled file and when the watch gives up on its own;
+// returns a function that stops listening
+export async function onWatchEvents(
+  onJob: (job: WatchJob) => void,
+  onStopped?: (error: string) => void,
+): Promise<UnlistenFn> {
+  const unlistenJob = await listen<WatchJob>("watch-job", (event) =>
+    onJob(event.payload),
+  );
+  const unlistenStopped = await listen<string>("watch-stopped", (event) =>
+    onStopped?.(event.payload),
+  );
+  return () => {
+    unlistenJob();
+    unlistenStopped();
+  };
+}
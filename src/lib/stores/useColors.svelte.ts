@@ -1,6 +1,8 @@
 interface R_COLOR {
   name: string;
   hex: string;
+  // Price per ml, used for cost estimates
+  cost_per_ml?: number;
 }
 const RISOCOLORS: R_COLOR[] = [
   {
@@ -41,10 +43,33 @@ const RISOCOLORS: R_COLOR[] = [
   },
 ];
 
+// Ink costs are kept with the ink library between sessions, keyed by name
+const INK_COSTS_KEY = "r110.inkCosts";
+
+function loadInkCosts(): Record<string, number> {
+  try {
+    return JSON.parse(localStorage.getItem(INK_COSTS_KEY) ?? "{}");
+  } catch {
+    return {};
+  }
+}
+
+function saveInkCosts(colors: R_COLOR[]) {
+  const costs = Object.fromEntries(
+    colors
+      .filter((color) => color.cost_per_ml !== undefined)
+      .map((color) => [color.name, color.cost_per_ml]),
+  );
+  localStorage.setItem(INK_COSTS_KEY, JSON.stringify(costs));
+}
+
 class ColorStore {
   colors = $state<R_COLOR[]>([]);
   async setColors(colors: R_COLOR[]) {
-    this.colors = colors;
+    const costs = loadInkCosts();
+    this.colors = colors.map((color) =>
+      color.name in costs ? { ...color, cost_per_ml: costs[color.name] } : color
+    );
   }
   async addColor(color: R_COLOR) {
     this.colors = [...this.colors, color];
@@ -52,6 +77,12 @@ class ColorStore {
   async getColorByName(name: string) {
     return this.colors.find((color) => color.name === name);
   }
+  async setInkCost(name: string, costPerMl: number | undefined) {
+    this.colors = this.colors.map((color) =>
+      color.name === name ? { ...color, cost_per_ml: costPerMl } : color
+    );
+    saveInkCosts(this.colors);
+  }
 }
 
 export const useColors = new ColorStore();
@@ -2,12 +2,304 @@ export type Channels = "cyan" | "magenta" | "yellow" | "black";
 export interface ProcessData {
   channel: string;
   image_path: string;
+  depth: "gray8" | "bilevel";
+  dpi: number | null;
 }
+
+export interface OutputSize {
+  width: number | null;
+  height: number | null;
+  unit: "Millimetres" | "Inches";
+  dpi: number;
+}
+
+export type InkMode = "Overprint" | "Knockout";
+
+export interface KnockoutSettings {
+  stacking_order: number[];
+  modes: InkMode[];
+}
+
+export interface TrapRule {
+  spread: string;
+  into: string;
+  width_mm: number | null;
+}
+
+export interface TrapSettings {
+  width_mm: number;
+  rules: TrapRule[];
+  skip_photographic: boolean;
+}
+export interface ColorInfo {
+  hex: string;
+  name: string;
+  cost_per_ml: number | null;
+}
+
+export interface ProcessSettings {
+  effect: string | null;
+  filter: string | null;
+  colors: ColorInfo[] | null;
+  output?: OutputSize | null;
+  trapping?: TrapSettings | null;
+  knockout?: KnockoutSettings | null;
+}
+
 export interface ProcessedImages extends ProcessData {
   image_data: string | null;
 }
 
+export type ProcessingStatus =
+  | "Idle"
+  | "Processing"
+  | "Completed"
+  | "Failed"
+  | "Cancelled";
+
 export interface ProcessingCompletePayload {
-  processed_images: ProcessedImages[];
-  status: string;
+  processed_images: ProcessedImages[] | null;
+  status: ProcessingStatus;
+  error: string | null;
+}
+
+export interface MarkSettings {
+  registration: boolean;
+  crop_marks: boolean;
+  color_bars: boolean;
+  slug: boolean;
+  offset_mm: number;
+  line_weight_pt: number;
+}
+
+export type PaperSize =
+  | "A4"
+  | "A3"
+  | "B4"
+  | "Letter"
+  | "Tabloid"
+  | { Custom: { width_mm: number; height_mm: number } };
+
+export interface PageSetup {
+  size: PaperSize;
+  orientation: "Portrait" | "Landscape";
+  margins: { top: number; right: number; bottom: number; left: number };
+  bleed_mm: number;
+  scaling: "Output" | "Fit" | { ActualSize: { dpi: number } };
+  horizontal_align: "Left" | "Center" | "Right";
+  vertical_align: "Top" | "Center" | "Bottom";
+}
+
+export interface TiffSettings {
+  compression: "Group4" | "Lzw";
+  multi_page: boolean;
+}
+
+export interface CostSettings {
+  copies: number;
+  master_cost: number;
+  copies_per_master: number;
+  ink_ml_per_m2: number;
+  default_ink_cost_per_ml: number;
+}
+
+export interface JobSheetSettings {
+  enabled: boolean;
+  cost: CostSettings | null;
+}
+
+export interface ExportSettings {
+  marks: MarkSettings;
+  page: PageSetup;
+  tiff: TiffSettings;
+  job_sheet: JobSheetSettings;
+}
+
+export interface PlateOffset {
+  x_mm: number;
+  y_mm: number;
+  rotation: number;
+}
+
+export interface RegistrationReport {
+  composite: string;
+  highlights: string;
+  gap_ratio: number;
+  fringe_ratio: number;
+}
+
+export interface ProofSettings {
+  paper_color: string;
+  paper_texture: number;
+  ink_opacity: number[];
+  density_variation: number;
+  grain: number;
+  print_order: number[];
+  offsets: PlateOffset[];
+  seed: number;
+}
+
+export interface PlateCoverage {
+  channel: string;
+  ink: string | null;
+  mean: number;
+  histogram: number[];
+}
+
+export interface CoverageReport {
+  plates: PlateCoverage[];
+  max_tac: number;
+  tac_limit: number;
+  over_limit_ratio: number;
+  heatmap: string;
+}
+
+export interface InkEstimate {
+  channel: string;
+  ink: string | null;
+  coverage: number;
+  masters: number;
+  ink_ml: number;
+  ink_cost: number;
+}
+
+export interface CostEstimate {
+  copies: number;
+  area_mm2: number;
+  inks: InkEstimate[];
+  masters: number;
+  ink_ml: number;
+  master_cost: number;
+  ink_cost: number;
+  total_cost: number;
+  cost_per_copy: number;
+}
+
+export interface SaveOptions {
+  embed_source: boolean;
+  embed_plates: boolean;
+}
+
+export interface ProjectResponse {
+  processed_images: ProcessData[] | null;
+  image_path: string;
+  image_type: string;
+  image_name: string;
+  process_settings: ProcessSettings | null;
+  export_settings: ExportSettings;
+  source_modified: boolean;
+}
+
+export interface Preset {
+  version: number;
+  name: string;
+  settings: ProcessSettings;
+}
+
+export interface HistoryItem {
+  id: number;
+  settings: ProcessSettings;
+  created_at: string;
+  cached: boolean;
+}
+
+export interface HistoryListing {
+  entries: HistoryItem[];
+  current: number | null;
+  can_undo: boolean;
+  can_redo: boolean;
+}
+
+export interface HistoryResponse {
+  processed_images: ProcessData[];
+  process_settings: ProcessSettings;
+  history: HistoryListing;
+}
+
+export interface VariantSpec {
+  name: string;
+  settings: ProcessSettings;
+}
+
+export interface Variant {
+  name: string;
+  settings: ProcessSettings;
+  processed_images: ProcessData[];
+}
+
+export interface VariantPreview extends Variant {
+  composite: string;
+}
+
+export interface GridCell {
+  name: string;
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface VariantsResponse {
+  grid: string;
+  cells: GridCell[];
+  variants: VariantPreview[];
+}
+
+export type ExportFormat = "Pdf" | "Png" | "SpotPdf" | "Tiff" | "Psd";
+
+export type BatchInput = { files: string[] } | { folder: string };
+
+export interface BatchOptions {
+  filename_template: string;
+  format: ExportFormat;
+  workers: number | null;
+}
+
+export interface BatchItem {
+  input: string;
+  output: string;
+  error: string | null;
+  skipped: boolean;
+}
+
+export interface BatchReport {
+  output_dir: string;
+  items: BatchItem[];
+  succeeded: number;
+  failed: number;
+  skipped: number;
+}
+
+export interface BatchProgress {
+  job_id: number;
+  done: number;
+  total: number;
+  item: BatchItem;
+}
+
+export interface WatchSettings {
+  folder: string;
+  output_dir: string;
+  done_dir: string | null;
+  failed_dir: string | null;
+  format: ExportFormat;
+  filename_template: string;
+  poll_seconds: number;
+}
+
+export interface WatchJob {
+  source: string;
+  moved_to: string | null;
+  output: string;
+  error: string | null;
+  finished_at: string;
+  seconds: number;
+}
+
+export interface WatchStatus {
+  running: boolean;
+  settings: WatchSettings | null;
+  preset: string | null;
+  jobs: WatchJob[];
+  error: string | null;
 }